@@ -0,0 +1,69 @@
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+use transactions::{
+    transaction_store::Store, Amount, CompactTransactionStore, PositiveAmount, Transaction,
+    TransactionID, TransactionKind, TransactionStore,
+};
+
+const SIZES: [TransactionID; 2] = [10_000, 1_000_000];
+
+fn deposit(tx_id: TransactionID) -> Transaction {
+    let amount = PositiveAmount::new(Amount::new(tx_id as i64 + 1, 4)).unwrap();
+    Transaction::new(
+        TransactionKind::Deposit { amount },
+        (tx_id % 1000) as u16,
+        tx_id,
+    )
+}
+
+fn fill<S: Store + Default>(size: TransactionID) -> S {
+    let mut store = S::default();
+    for tx_id in 0..size {
+        store.insert(deposit(tx_id)).unwrap();
+    }
+    store
+}
+
+fn insert(c: &mut Criterion) {
+    let mut group = c.benchmark_group("insert");
+    group.sample_size(10);
+
+    for size in SIZES {
+        group.bench_with_input(BenchmarkId::new("hash_map", size), &size, |b, &size| {
+            b.iter(|| black_box(fill::<TransactionStore>(size)))
+        });
+        group.bench_with_input(BenchmarkId::new("compact", size), &size, |b, &size| {
+            b.iter(|| black_box(fill::<CompactTransactionStore>(size)))
+        });
+    }
+
+    group.finish();
+}
+
+fn lookup(c: &mut Criterion) {
+    let mut group = c.benchmark_group("lookup");
+
+    for size in SIZES {
+        let hash_map = fill::<TransactionStore>(size);
+        let compact = fill::<CompactTransactionStore>(size);
+
+        group.bench_with_input(BenchmarkId::new("hash_map", size), &size, |b, &size| {
+            let mut tx_id = 0;
+            b.iter(|| {
+                tx_id = (tx_id + 7919) % size;
+                black_box(hash_map.get(&tx_id).unwrap())
+            })
+        });
+        group.bench_with_input(BenchmarkId::new("compact", size), &size, |b, &size| {
+            let mut tx_id = 0;
+            b.iter(|| {
+                tx_id = (tx_id + 7919) % size;
+                black_box(compact.get(&tx_id).unwrap())
+            })
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, insert, lookup);
+criterion_main!(benches);
@@ -1,31 +1,260 @@
-use std::{ffi::OsStr, path::PathBuf};
+use std::{
+    ffi::OsStr,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
 
 use clap::Parser;
 
-use crate::AppError;
+use transactions::{
+    account_store::{SortDirection, SortKey},
+    ExpiryPolicy, DAY, DEFAULT_MAX_SCALE,
+};
+
+use crate::{
+    input::{Compression, STDIN},
+    AppError,
+};
 
 #[derive(Parser, Debug)]
 #[clap(version, about)]
 pub struct Args {
     #[clap(
         forbid_empty_values = true,
+        required = true,
+        value_parser,
+        help = "Paths to CSV files with transactions processed in given order, - reads from stdin"
+    )]
+    pub transactions_files: Vec<PathBuf>,
+
+    #[clap(
+        long,
+        help = "Accept exact replays of already processed transactions as no-ops"
+    )]
+    pub idempotent: bool,
+
+    #[clap(
+        long,
+        default_value_t = DEFAULT_MAX_SCALE,
+        help = "Maximal number of decimal places of transaction amount"
+    )]
+    pub max_scale: u32,
+
+    #[clap(
+        long,
+        value_parser,
+        help = "A path to CSV file where rejected records are written"
+    )]
+    pub rejects: Option<PathBuf>,
+
+    #[clap(
+        long,
+        value_parser,
+        help = "A path to file where transaction log is persisted instead of memory"
+    )]
+    pub transaction_log: Option<PathBuf>,
+
+    #[clap(long, help = "Abort processing on the first rejected record")]
+    pub strict: bool,
+
+    #[clap(
+        long,
+        value_parser,
+        default_value = "client",
+        help = "Order accounts by: client, total, available, held"
+    )]
+    pub sort_by: SortKey,
+
+    #[clap(
+        long,
+        value_parser,
+        default_value = "asc",
+        help = "Order direction: asc, desc"
+    )]
+    pub order: SortDirection,
+
+    #[clap(
+        long,
+        value_parser,
+        help = "A path to snapshot from which processing is resumed"
+    )]
+    pub resume_from: Option<PathBuf>,
+
+    #[clap(
+        long,
+        help = "Number of input rows to skip, defaults to number of rows recorded in snapshot"
+    )]
+    pub skip_rows: Option<usize>,
+
+    #[clap(
+        long,
+        value_parser,
+        help = "A path to file where snapshot is periodically saved"
+    )]
+    pub checkpoint: Option<PathBuf>,
+
+    #[clap(
+        long,
+        value_parser = clap::value_parser!(u64).range(1..),
+        requires = "checkpoint",
+        help = "Number of input rows between two snapshots, by default 10000 or number of transactions in the last snapshot if it is larger"
+    )]
+    pub checkpoint_every: Option<u64>,
+
+    #[clap(
+        long,
+        default_value_t = 1,
+        value_parser = clap::value_parser!(u16).range(1..),
+        conflicts_with_all = &["resume-from", "checkpoint", "transaction-log"],
+        help = "Number of threads processing transactions, clients are partitioned between them"
+    )]
+    pub shards: u16,
+
+    #[clap(
+        long,
+        value_parser,
+        default_value = "csv",
+        help = "Format of input files: csv, jsonl"
+    )]
+    pub input_format: InputFormat,
+
+    #[clap(
+        long,
+        value_parser,
+        default_value = "csv",
+        help = "Format of printed accounts: csv, jsonl, json"
+    )]
+    pub output_format: OutputFormat,
+
+    #[clap(
+        long,
+        value_parser,
+        help = "A path to file where accounts are written instead of stdout, file is replaced atomically"
+    )]
+    pub output: Option<PathBuf>,
+
+    #[clap(long, help = "Print status of accounts next to locked column")]
+    pub with_status: bool,
+
+    #[clap(
+        long,
+        value_parser,
+        help = "Number of days after transaction within which it can be disputed, requires timestamps"
+    )]
+    pub dispute_window_days: Option<u64>,
+
+    #[clap(
+        long,
+        value_parser,
+        help = "Number of days after which hold of authorization is released, requires timestamps"
+    )]
+    pub hold_expiry_days: Option<u64>,
+
+    #[clap(
+        long,
         value_parser,
-        help = "A path to CSV file with transactions"
+        help = "A path to CSV file with overdrafts of clients: client, limit, withdrawals"
     )]
-    pub transactions_file: PathBuf,
+    pub credit_lines: Option<PathBuf>,
 }
 
-const FILE_EXT: &str = "csv";
+/// Minimal number of input rows between two snapshots when it is not set
+const DEFAULT_CHECKPOINT_EVERY: u64 = 10_000;
 
+impl Args {
+    /// Returns number of input rows after which the next snapshot is taken
+    ///
+    /// By default interval grows with snapshot, so total size of written snapshots stays linear in number of rows.
+    pub fn checkpoint_interval(&self, transactions: usize) -> u64 {
+        self.checkpoint_every
+            .unwrap_or_else(|| DEFAULT_CHECKPOINT_EVERY.max(transactions as u64))
+    }
+
+    /// Returns time windows enforced by engine
+    pub fn expiry_policy(&self) -> ExpiryPolicy {
+        ExpiryPolicy {
+            dispute_window: self
+                .dispute_window_days
+                .map(|days| days.saturating_mul(DAY)),
+            hold_expiry: self.hold_expiry_days.map(|days| days.saturating_mul(DAY)),
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum InputFormat {
+    #[default]
+    Csv,
+    JsonLines,
+}
+
+impl InputFormat {
+    /// Returns extensions of input files in given format
+    fn extensions(self) -> &'static [&'static str] {
+        match self {
+            InputFormat::Csv => &["csv"],
+            InputFormat::JsonLines => &["jsonl", "ndjson"],
+        }
+    }
+}
+
+impl FromStr for InputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "csv" => Ok(InputFormat::Csv),
+            "jsonl" => Ok(InputFormat::JsonLines),
+            _ => Err(format!("Unknown input format {}, expected csv or jsonl", s)),
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    #[default]
+    Csv,
+    JsonLines,
+    Json,
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "csv" => Ok(OutputFormat::Csv),
+            "jsonl" => Ok(OutputFormat::JsonLines),
+            "json" => Ok(OutputFormat::Json),
+            _ => Err(format!(
+                "Unknown output format {}, expected one of csv, jsonl, json",
+                s
+            )),
+        }
+    }
+}
+
+/// Validates extensions of input files, compressed `.csv.gz` and `.csv.zst` files and stdin are accepted too
 pub fn validate_ext(args: &Args) -> Result<(), AppError> {
-    let ext = args
-        .transactions_file
-        .extension()
-        .and_then(OsStr::to_str)
-        .ok_or_else(|| AppError::InvalidFileExt("Unable to validate extension".to_string()))?;
-
-    if ext != FILE_EXT {
-        return Err(AppError::InvalidFileExt(ext.to_string()));
+    for path in args
+        .transactions_files
+        .iter()
+        .filter(|path| path.as_os_str() != STDIN)
+    {
+        // Extension of compressed file precedes extension of compression
+        let path = match Compression::from_path(path) {
+            Some(_) => Path::new(path.file_stem().unwrap_or_default()),
+            None => path.as_path(),
+        };
+
+        let ext = path
+            .extension()
+            .and_then(OsStr::to_str)
+            .ok_or_else(|| AppError::InvalidFileExt("Unable to validate extension".to_string()))?;
+
+        if !args.input_format.extensions().contains(&ext) {
+            return Err(AppError::InvalidFileExt(ext.to_string()));
+        }
     }
 
     Ok(())
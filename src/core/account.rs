@@ -1,28 +1,178 @@
+use std::str::FromStr;
+
 use rust_decimal::Decimal;
-use serde::{Serialize, Serializer};
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+
+use super::transaction::TransactionID;
 
 pub type ClientID = u16;
 pub type Amount = Decimal;
 
-#[derive(thiserror::Error, Debug)]
+#[derive(thiserror::Error, Debug, Clone, Copy, PartialEq, Eq)]
 pub enum AccountError {
     #[error("Account is locked")]
     Locked,
-    #[error("Insufficient funds")]
-    InsufficientFunds,
+    #[error("Account is frozen")]
+    Frozen,
+    #[error("Account is closed")]
+    Closed,
+    #[error("Account with held funds or debt can't be closed")]
+    NotSettled,
+    #[error("Insufficient funds, only {0} can be spent")]
+    InsufficientFunds(Amount),
+    #[error("Account does not exist")]
+    UnknownAccount,
 }
+
+/// Overdraft approved for user account
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CreditLine {
+    /// Amount by which available funds can go below zero
+    #[serde(deserialize_with = "non_negative")]
+    pub limit: Amount,
+    /// Whether withdrawals and outgoing transfers can use credit line, authorizations always can
+    pub withdrawals: bool,
+}
+
+/// Deserializes amount from its text, so CSV doesn't read it as a float, negative amount is refused
+fn non_negative<'de, D>(deserializer: D) -> Result<Amount, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let text = String::deserialize(deserializer)?;
+    let amount = Amount::from_str(text.trim()).map_err(de::Error::custom)?;
+
+    if amount < Amount::ZERO {
+        return Err(de::Error::custom(format!("{} is negative", amount)));
+    }
+
+    Ok(amount)
+}
+
+/// Status of user account, it decides which deltas account accepts
+///
+/// Active - every delta is accepted
+/// Frozen - only deltas which don't decrease available nor total funds are accepted, e.g. incoming credits
+/// Locked - account is locked by chargeback, it only accepts delta which activates it again
+/// Closed - remaining funds have been paid out, nothing is accepted anymore
+///
+/// Legal changes of status:
+/// ```text
+///    ┌───────► Closed ◄───────┐
+///    │                        │
+/// Active ◄─────────────────► Frozen
+///   ▲ │                       │
+///   │ ▼                       │
+/// Locked ◄────────────────────┘
+/// ```
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum AccountStatus {
+    #[default]
+    Active,
+    Frozen,
+    Locked,
+    Closed,
+}
+
+impl AccountStatus {
+    /// Returns true if account in current status can be moved to the next one
+    ///
+    /// Active account can be activated again, which changes nothing.
+    pub fn can_transition_to(self, next: AccountStatus) -> bool {
+        use AccountStatus::*;
+
+        matches!(
+            (self, next),
+            (Active, _) | (Frozen, Active | Locked | Closed) | (Locked, Active)
+        )
+    }
+
+    /// Returns true if account in current status accepts balance change of given delta
+    pub fn allows(self, change: &AccountDelta) -> bool {
+        match self {
+            AccountStatus::Active => true,
+            AccountStatus::Frozen => change.is_credit(),
+            AccountStatus::Locked | AccountStatus::Closed => false,
+        }
+    }
+
+    /// Returns error reported when account in current status refuses delta
+    fn error(self) -> AccountError {
+        match self {
+            AccountStatus::Frozen => AccountError::Frozen,
+            AccountStatus::Closed => AccountError::Closed,
+            AccountStatus::Active | AccountStatus::Locked => AccountError::Locked,
+        }
+    }
+}
+
 /// Represents user account
-#[derive(Serialize, Default, Debug)]
+#[derive(Default, Debug, Clone, PartialEq, Eq)]
 pub struct Account {
-    #[serde(rename = "client")]
     pub(crate) id: ClientID,
-    #[serde(serialize_with = "fixed_width_amount")]
     pub(crate) available: Amount,
-    #[serde(serialize_with = "fixed_width_amount")]
     pub(crate) held: Amount,
-    #[serde(serialize_with = "fixed_width_amount")]
     pub(crate) total: Amount,
-    pub(crate) locked: bool,
+    pub(crate) status: AccountStatus,
+    pub(crate) credit_line: Option<CreditLine>,
+    pub(crate) lock: Option<Lock>,
+}
+
+/// Chargeback which locked account together with status account had before it
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Lock {
+    pub(crate) tx_id: TransactionID,
+    pub(crate) previous: AccountStatus,
+}
+
+/// Account as it is printed, status is printed only on request so default columns stay stable
+#[derive(Serialize)]
+struct AccountRow {
+    client: ClientID,
+    #[serde(serialize_with = "fixed_width_amount")]
+    available: Amount,
+    #[serde(serialize_with = "fixed_width_amount")]
+    held: Amount,
+    #[serde(serialize_with = "fixed_width_amount")]
+    total: Amount,
+    locked: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    status: Option<AccountStatus>,
+}
+
+impl AccountRow {
+    fn new(account: &Account, with_status: bool) -> Self {
+        Self {
+            client: account.id,
+            available: account.available,
+            held: account.held,
+            total: account.total,
+            locked: account.is_locked(),
+            status: with_status.then_some(account.status),
+        }
+    }
+}
+
+impl Serialize for Account {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        AccountRow::new(self, false).serialize(serializer)
+    }
+}
+
+/// Account printed with `status` column next to `locked` one, see [`Account::with_status`]
+pub struct AccountWithStatus<'a>(&'a Account);
+
+impl Serialize for AccountWithStatus<'_> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        AccountRow::new(self.0, true).serialize(serializer)
+    }
 }
 
 fn fixed_width_amount<S>(amount: &Amount, serializer: S) -> Result<S::Ok, S::Error>
@@ -30,36 +180,123 @@ where
     S: Serializer,
 {
     const PRECISION: u32 = 4;
-    serializer.serialize_str(
-        &amount
-            .round_dp_with_strategy(
-                PRECISION,
-                rust_decimal::RoundingStrategy::MidpointAwayFromZero,
-            )
-            .to_string(),
-    )
+    let rounded = amount.round_dp_with_strategy(
+        PRECISION,
+        rust_decimal::RoundingStrategy::MidpointAwayFromZero,
+    );
+    // Scale (and sign) of zero after rounding differs between rust_decimal versions
+    if rounded.is_zero() {
+        return serializer.serialize_str("0.0000");
+    }
+    serializer.serialize_str(&rounded.to_string())
 }
 
 impl Account {
-    pub(crate) fn new(id: ClientID) -> Self {
+    /// Creates empty, active account for given client
+    pub fn new(id: ClientID) -> Self {
         Self {
             id,
             ..Default::default()
         }
     }
 
-    /// Applies delta of user balance, changes are applied only when account is not locked
+    pub fn id(&self) -> ClientID {
+        self.id
+    }
+
+    pub fn available(&self) -> Amount {
+        self.available
+    }
+
+    pub fn held(&self) -> Amount {
+        self.held
+    }
+
+    pub fn total(&self) -> Amount {
+        self.total
+    }
+
+    pub fn is_locked(&self) -> bool {
+        self.status == AccountStatus::Locked
+    }
+
+    pub fn status(&self) -> AccountStatus {
+        self.status
+    }
+
+    /// Returns account which is serialized together with its status
+    pub fn with_status(&self) -> AccountWithStatus<'_> {
+        AccountWithStatus(self)
+    }
+
+    pub fn credit_line(&self) -> Option<CreditLine> {
+        self.credit_line
+    }
+
+    /// Sets overdraft of account, it is enforced for deltas applied from now on
+    pub fn set_credit_line(&mut self, credit_line: Option<CreditLine>) {
+        self.credit_line = credit_line;
+    }
+
+    /// Returns how much can be spent by given delta, credit line is included when delta can use it
+    pub fn headroom(&self, change: &AccountDelta) -> Amount {
+        (self.available + self.credit_limit(change)).max(Amount::ZERO)
+    }
+
+    fn credit_limit(&self, change: &AccountDelta) -> Amount {
+        match self.credit_line {
+            Some(credit_line) if credit_line.withdrawals || !change.is_withdrawal() => {
+                credit_line.limit
+            }
+            _ => Amount::ZERO,
+        }
+    }
+
+    /// Applies delta of user balance, changes are applied only when status of account allows them
+    ///
+    /// Delta which changes status is accepted whenever the change is legal, see [`AccountStatus`].
+    /// Delta which releases lock of the chargeback which locked account restores status account had
+    /// before, the rest of delta has to be accepted in that status. Account can be closed only when
+    /// nothing is held and it has no debt.
+    ///
+    /// Available funds can go below zero only by credit line of account or by delta which can create debt,
+    /// refused delta is reported with headroom which has been left.
     pub fn apply(&mut self, change: AccountDelta) -> Result<(), AccountError> {
-        if self.locked {
-            return Err(AccountError::Locked);
+        match (change.status, self.restored(&change)) {
+            (Some(next), _) if !self.status.can_transition_to(next) => {
+                return Err(self.status.error())
+            }
+            (Some(_), _) => {}
+            (None, Some(previous)) if !previous.allows(&change) => return Err(previous.error()),
+            (None, Some(_)) => {}
+            (None, None) if !self.status.allows(&change) => return Err(self.status.error()),
+            (None, None) => {}
+        }
+
+        if change.status == Some(AccountStatus::Closed)
+            && (!self.held.is_zero() || self.available < Amount::ZERO)
+        {
+            return Err(AccountError::NotSettled);
         }
 
         if let Some(available) = change.available {
             let can_create_debt = change.can_create_debt.unwrap_or_default();
 
-            if !can_create_debt && self.available + available < Decimal::ZERO {
-                return Err(AccountError::InsufficientFunds);
+            if !can_create_debt && self.available + available < -self.credit_limit(&change) {
+                return Err(AccountError::InsufficientFunds(self.headroom(&change)));
             }
+        }
+
+        self.replay(change);
+
+        Ok(())
+    }
+
+    /// Applies delta without any check, delta has to be already accepted by account in the same state
+    pub(crate) fn replay(&mut self, change: AccountDelta) {
+        let restored = self.restored(&change);
+
+        if let Some(available) = change.available {
             self.available += available;
         }
 
@@ -67,13 +304,33 @@ impl Account {
             self.held += held;
         }
 
-        if let Some(locked) = change.locked {
-            self.locked = locked;
+        if let Some(LockChange::Lock(tx_id)) = change.lock {
+            self.lock = Some(Lock {
+                tx_id,
+                previous: self.status,
+            });
+        }
+
+        if let Some(status) = change.status.or(restored) {
+            self.status = status;
+        }
+
+        // Lock is forgotten once account is unlocked in any way
+        if self.status != AccountStatus::Locked {
+            self.lock = None;
         }
 
         self.update_total();
+    }
 
-        Ok(())
+    /// Returns status restored by delta, only lock of the same transaction is released
+    fn restored(&self, change: &AccountDelta) -> Option<AccountStatus> {
+        match (change.lock, self.lock) {
+            (Some(LockChange::Release(tx_id)), Some(lock)) if lock.tx_id == tx_id => {
+                Some(lock.previous)
+            }
+            _ => None,
+        }
     }
 
     fn update_total(&mut self) {
@@ -81,12 +338,20 @@ impl Account {
     }
 }
 
+/// Lock of account by chargeback of given transaction or its release
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LockChange {
+    Lock(TransactionID),
+    Release(TransactionID),
+}
+
 /// Represents potential account changes which are outcome of incoming transaction
-#[derive(Default)]
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
 pub struct AccountDelta {
     pub available: Option<Amount>,
     pub held: Option<Amount>,
-    pub locked: Option<bool>,
+    pub status: Option<AccountStatus>,
+    pub lock: Option<LockChange>,
 
     // This is only possible when there is dispute on deposit and user already withdrawn those funds
     pub can_create_debt: Option<bool>,
@@ -133,20 +398,105 @@ impl AccountDelta {
         }
     }
 
-    pub fn chargeback(amount: Amount) -> Self {
+    /// Withdraws held funds and locks account, lock remembers transaction which has been charged back
+    pub fn chargeback(tx_id: TransactionID, amount: Amount) -> Self {
         Self {
             held: Some(-amount),
-            locked: Some(true),
+            status: Some(AccountStatus::Locked),
+            lock: Some(LockChange::Lock(tx_id)),
             ..Default::default()
         }
     }
+
+    pub fn authorize(amount: Amount) -> Self {
+        Self {
+            available: Some(-amount),
+            held: Some(amount),
+            ..Default::default()
+        }
+    }
+
+    pub fn capture(amount: Amount) -> Self {
+        Self {
+            held: Some(-amount),
+            ..Default::default()
+        }
+    }
+
+    pub fn void(amount: Amount) -> Self {
+        Self::resolve(amount)
+    }
+
+    /// Moves account to given status, balances remain untouched
+    pub fn status(status: AccountStatus) -> Self {
+        Self {
+            status: Some(status),
+            ..Default::default()
+        }
+    }
+
+    /// Pays out given amount and closes account
+    pub fn close(payout: Amount) -> Self {
+        Self::withdrawal(payout).merge(Self::status(AccountStatus::Closed))
+    }
+
+    /// Returns true if delta decreases neither available nor total funds, e.g. deposit or release of held funds
+    pub fn is_credit(&self) -> bool {
+        let available = self.available.unwrap_or_default();
+        let held = self.held.unwrap_or_default();
+
+        available >= Amount::ZERO && available + held >= Amount::ZERO
+    }
+
+    /// Whether delta takes funds out of account, as opposed to holding them
+    pub fn is_withdrawal(&self) -> bool {
+        self.available.unwrap_or_default() < Amount::ZERO && self.held.is_none()
+    }
+
+    /// Returns delta which undoes this one
+    ///
+    /// Lock is undone by releasing it, which restores status account had before only while account is
+    /// still locked by the same transaction. Other changes of status can't be undone.
+    /// Inverse never creates debt, even when this delta could, e.g. revert of disputed deposit.
+    pub fn inverse(self) -> Self {
+        Self {
+            available: self.available.map(|available| -available),
+            held: self.held.map(|held| -held),
+            status: None,
+            lock: match self.lock {
+                Some(LockChange::Lock(tx_id)) => Some(LockChange::Release(tx_id)),
+                _ => None,
+            },
+            can_create_debt: None,
+        }
+    }
+
+    /// Returns single delta with the same effect as applying this one followed by the other one
+    pub fn merge(self, other: Self) -> Self {
+        fn sum(a: Option<Amount>, b: Option<Amount>) -> Option<Amount> {
+            match (a, b) {
+                (Some(a), Some(b)) => Some(a + b),
+                (a, b) => a.or(b),
+            }
+        }
+
+        Self {
+            available: sum(self.available, other.available),
+            held: sum(self.held, other.held),
+            status: other.status.or(self.status),
+            lock: other.lock.or(self.lock),
+            can_create_debt: self.can_create_debt.or(other.can_create_debt),
+        }
+    }
 }
 
 #[cfg(test)]
 mod tests {
+    use test_case::test_case;
+
     use crate::core::account::Amount;
 
-    use super::{Account, AccountDelta, AccountError};
+    use super::{Account, AccountDelta, AccountError, AccountStatus, CreditLine};
 
     #[test]
     fn deposit_should_increase_available_funds_and_total() -> Result<(), AccountError> {
@@ -194,7 +544,8 @@ mod tests {
 
         let mut insufficient_funds = false;
 
-        if let Err(AccountError::InsufficientFunds) = account.apply(withdrawal) {
+        if let Err(AccountError::InsufficientFunds(headroom)) = account.apply(withdrawal) {
+            assert_eq!(headroom, Amount::TWO);
             insufficient_funds = true;
         }
 
@@ -298,13 +649,13 @@ mod tests {
         assert_eq!(account.available, Amount::ONE);
         assert_eq!(account.total, Amount::TWO);
 
-        let chargeback = AccountDelta::chargeback(Amount::ONE);
+        let chargeback = AccountDelta::chargeback(1, Amount::ONE);
         account.apply(chargeback)?;
 
         assert_eq!(account.held, Amount::ZERO);
         assert_eq!(account.available, Amount::ONE);
         assert_eq!(account.total, Amount::ONE);
-        assert_eq!(account.locked, true);
+        assert_eq!(account.is_locked(), true);
 
         Ok(())
     }
@@ -326,13 +677,13 @@ mod tests {
         assert_eq!(account.available, Amount::ZERO);
         assert_eq!(account.total, Amount::TWO);
 
-        let chargeback = AccountDelta::chargeback(Amount::TWO);
+        let chargeback = AccountDelta::chargeback(1, Amount::TWO);
         account.apply(chargeback)?;
 
         assert_eq!(account.held, Amount::ZERO);
         assert_eq!(account.available, Amount::ZERO);
         assert_eq!(account.total, Amount::ZERO);
-        assert_eq!(account.locked, true);
+        assert_eq!(account.is_locked(), true);
 
         let deposit = AccountDelta::deposit(Amount::TWO);
 
@@ -342,11 +693,95 @@ mod tests {
         assert_eq!(account.held, Amount::ZERO);
         assert_eq!(account.available, Amount::ZERO);
         assert_eq!(account.total, Amount::ZERO);
-        assert_eq!(account.locked, true);
+        assert_eq!(account.is_locked(), true);
+
+        Ok(())
+    }
+
+    #[test]
+    fn inverse_should_undo_chargeback_and_unlock_acc() -> Result<(), AccountError> {
+        let mut account = Account::new(1);
+
+        let deposit = AccountDelta::deposit(Amount::TWO);
+        let dispute = AccountDelta::dispute_deposit(Amount::TWO);
+        let chargeback = AccountDelta::chargeback(1, Amount::TWO);
+
+        account.apply(deposit)?;
+        let before_dispute = account.clone();
+
+        account.apply(dispute)?;
+        account.apply(chargeback)?;
+        assert_eq!(account.is_locked(), true);
+
+        account.apply(dispute.merge(chargeback).inverse())?;
+
+        assert_eq!(account, before_dispute);
 
         Ok(())
     }
 
+    #[test]
+    fn inverse_of_chargeback_should_restore_status_before_lock() -> Result<(), AccountError> {
+        let mut account = account(Amount::ZERO, Amount::TWO, AccountStatus::Frozen);
+        let before = account.clone();
+
+        let chargeback = AccountDelta::chargeback(1, Amount::TWO);
+        account.apply(chargeback)?;
+        assert_eq!(account.is_locked(), true);
+
+        account.apply(chargeback.inverse())?;
+
+        assert_eq!(account, before);
+
+        Ok(())
+    }
+
+    #[test]
+    fn inverse_of_chargeback_should_not_release_lock_of_other_transaction(
+    ) -> Result<(), AccountError> {
+        let mut account = account(Amount::ZERO, Amount::TWO, AccountStatus::Active);
+
+        account.apply(AccountDelta::chargeback(2, Amount::ONE))?;
+        let locked = account.clone();
+
+        let result = account.apply(AccountDelta::chargeback(1, Amount::ONE).inverse());
+
+        assert_eq!(result, Err(AccountError::Locked));
+        assert_eq!(account, locked);
+
+        Ok(())
+    }
+
+    #[test]
+    fn inverse_of_deposit_should_fail_when_funds_are_withdrawn() -> Result<(), AccountError> {
+        let mut account = Account::new(1);
+
+        account.apply(AccountDelta::deposit(Amount::TWO))?;
+        account.apply(AccountDelta::withdrawal(Amount::ONE))?;
+
+        let result = account.apply(AccountDelta::deposit(Amount::TWO).inverse());
+
+        assert_eq!(result, Err(AccountError::InsufficientFunds(Amount::ONE)));
+        assert_eq!(account.available, Amount::ONE);
+
+        Ok(())
+    }
+
+    #[test]
+    fn merged_delta_should_sum_balances_and_keep_latest_lock() {
+        let merged = AccountDelta::deposit(Amount::TWO)
+            .merge(AccountDelta::dispute_deposit(Amount::TWO))
+            .merge(AccountDelta::chargeback(1, Amount::TWO));
+
+        assert_eq!(merged.available, Some(Amount::ZERO));
+        assert_eq!(merged.held, Some(Amount::ZERO));
+        assert_eq!(merged.status, Some(AccountStatus::Locked));
+        assert_eq!(
+            AccountDelta::none().merge(AccountDelta::none()),
+            AccountDelta::none()
+        );
+    }
+
     #[test]
     fn withdrawal_should_have_negative_available_amount_in_delta() {
         let withdrawal = AccountDelta::withdrawal(Amount::ONE);
@@ -355,7 +790,7 @@ mod tests {
             withdrawal.available.unwrap_or_default(),
             Amount::NEGATIVE_ONE
         );
-        assert_eq!(withdrawal.locked, None);
+        assert_eq!(withdrawal.status, None);
         assert_eq!(withdrawal.held, None);
     }
 
@@ -364,7 +799,7 @@ mod tests {
         let withdrawal = AccountDelta::deposit(Amount::ONE);
 
         assert_eq!(withdrawal.available.unwrap_or_default(), Amount::ONE);
-        assert_eq!(withdrawal.locked, None);
+        assert_eq!(withdrawal.status, None);
         assert_eq!(withdrawal.held, None);
     }
 
@@ -374,6 +809,192 @@ mod tests {
 
         assert_eq!(withdrawal.available.unwrap_or_default(), Amount::ONE);
         assert_eq!(withdrawal.held.unwrap_or_default(), Amount::NEGATIVE_ONE);
-        assert_eq!(withdrawal.locked, None);
+        assert_eq!(withdrawal.status, None);
+    }
+
+    #[test]
+    fn authorization_should_hold_funds_until_captured_or_voided() -> Result<(), AccountError> {
+        let mut account = Account::new(1);
+
+        account.apply(AccountDelta::deposit(Amount::TEN))?;
+        account.apply(AccountDelta::authorize(Amount::new(6, 0)))?;
+
+        assert_eq!(account.available, Amount::new(4, 0));
+        assert_eq!(account.held, Amount::new(6, 0));
+        assert_eq!(account.total, Amount::TEN);
+
+        account.apply(AccountDelta::capture(Amount::new(4, 0)))?;
+        account.apply(AccountDelta::void(Amount::TWO))?;
+
+        assert_eq!(account.available, Amount::new(6, 0));
+        assert_eq!(account.held, Amount::ZERO);
+        assert_eq!(account.total, Amount::new(6, 0));
+
+        Ok(())
+    }
+
+    #[test]
+    fn authorization_should_fail_when_insufficent_funds() -> Result<(), AccountError> {
+        let mut account = Account::new(1);
+
+        account.apply(AccountDelta::deposit(Amount::ONE))?;
+
+        assert_eq!(
+            account.apply(AccountDelta::authorize(Amount::TWO)),
+            Err(AccountError::InsufficientFunds(Amount::ONE))
+        );
+        assert_eq!(account.held, Amount::ZERO);
+
+        Ok(())
+    }
+
+    fn credit_line(limit: Amount, withdrawals: bool) -> Account {
+        let mut account = Account::new(1);
+        account.set_credit_line(Some(CreditLine { limit, withdrawals }));
+        account
+    }
+
+    #[test_case(AccountDelta::withdrawal(Amount::TEN), true, Amount::new(-10, 0) ; "withdrawal_within_limit")]
+    #[test_case(AccountDelta::authorize(Amount::TEN), false, Amount::new(-10, 0) ; "authorization_without_withdrawals")]
+    fn credit_line_should_allow_debt_up_to_limit(
+        delta: AccountDelta,
+        withdrawals: bool,
+        expected_available: Amount,
+    ) -> Result<(), AccountError> {
+        let mut account = credit_line(Amount::TEN, withdrawals);
+
+        account.apply(delta)?;
+
+        assert_eq!(account.available, expected_available);
+
+        Ok(())
+    }
+
+    #[test_case(AccountDelta::withdrawal(Amount::new(11, 0)), true, Amount::TEN ; "withdrawal_over_limit")]
+    #[test_case(AccountDelta::authorize(Amount::new(11, 0)), false, Amount::TEN ; "authorization_over_limit")]
+    #[test_case(AccountDelta::withdrawal(Amount::ONE), false, Amount::ZERO ; "withdrawal_without_withdrawals")]
+    fn credit_line_should_report_headroom_when_exceeded(
+        delta: AccountDelta,
+        withdrawals: bool,
+        expected_headroom: Amount,
+    ) {
+        let mut account = credit_line(Amount::TEN, withdrawals);
+
+        assert_eq!(
+            account.apply(delta),
+            Err(AccountError::InsufficientFunds(expected_headroom))
+        );
+        assert_eq!(account.available, Amount::ZERO);
+    }
+
+    #[test]
+    fn headroom_should_include_available_funds() -> Result<(), AccountError> {
+        let mut account = credit_line(Amount::TEN, true);
+
+        account.apply(AccountDelta::deposit(Amount::TWO))?;
+        account.apply(AccountDelta::withdrawal(Amount::new(5, 0)))?;
+
+        assert_eq!(
+            account.apply(AccountDelta::withdrawal(Amount::TEN)),
+            Err(AccountError::InsufficientFunds(Amount::new(7, 0)))
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn account_over_limit_should_have_no_headroom() {
+        let mut account = credit_line(Amount::ONE, false);
+        account.available = Amount::new(-5, 0);
+
+        assert_eq!(
+            account.headroom(&AccountDelta::authorize(Amount::ONE)),
+            Amount::ZERO
+        );
+    }
+
+    #[test]
+    fn credit_line_with_negative_limit_should_not_be_deserialized() {
+        let line = r#"{"limit":"-1.0","withdrawals":true}"#;
+
+        assert!(serde_json::from_str::<CreditLine>(line).is_err());
+    }
+
+    // Helper for creating account with given balance and status
+    fn account(available: Amount, held: Amount, status: AccountStatus) -> Account {
+        Account {
+            id: 1,
+            available,
+            held,
+            total: available + held,
+            status,
+            credit_line: None,
+            lock: None,
+        }
+    }
+
+    #[test_case(AccountDelta::deposit(Amount::ONE), Ok(()) ; "deposit")]
+    #[test_case(AccountDelta::resolve(Amount::ONE), Ok(()) ; "resolve")]
+    #[test_case(AccountDelta::dispute_withdrawal(Amount::ONE), Ok(()) ; "dispute_of_withdrawal")]
+    #[test_case(AccountDelta::withdrawal(Amount::ONE), Err(AccountError::Frozen) ; "withdrawal")]
+    #[test_case(AccountDelta::authorize(Amount::ONE), Err(AccountError::Frozen) ; "authorization")]
+    #[test_case(AccountDelta::dispute_deposit(Amount::ONE), Err(AccountError::Frozen) ; "dispute_of_deposit")]
+    #[test_case(AccountDelta::capture(Amount::ONE), Err(AccountError::Frozen) ; "capture")]
+    #[test_case(AccountDelta::chargeback(1, Amount::ONE), Ok(()) ; "chargeback")]
+    fn frozen_acc_should_accept_only_credits(
+        change: AccountDelta,
+        expected: Result<(), AccountError>,
+    ) {
+        let mut account = account(Amount::TEN, Amount::TWO, AccountStatus::Frozen);
+
+        assert_eq!(account.apply(change), expected);
+    }
+
+    #[test_case(AccountStatus::Active, AccountStatus::Frozen, Ok(()) ; "freeze_active")]
+    #[test_case(AccountStatus::Frozen, AccountStatus::Active, Ok(()) ; "unfreeze_frozen")]
+    #[test_case(AccountStatus::Locked, AccountStatus::Active, Ok(()) ; "unlock_locked")]
+    #[test_case(AccountStatus::Frozen, AccountStatus::Frozen, Err(AccountError::Frozen) ; "freeze_frozen")]
+    #[test_case(AccountStatus::Locked, AccountStatus::Frozen, Err(AccountError::Locked) ; "freeze_locked")]
+    #[test_case(AccountStatus::Locked, AccountStatus::Closed, Err(AccountError::Locked) ; "close_locked")]
+    #[test_case(AccountStatus::Closed, AccountStatus::Active, Err(AccountError::Closed) ; "reopen_closed")]
+    fn status_should_change_only_when_it_is_legal(
+        from: AccountStatus,
+        to: AccountStatus,
+        expected: Result<(), AccountError>,
+    ) {
+        let mut account = account(Amount::ONE, Amount::ZERO, from);
+
+        assert_eq!(account.apply(AccountDelta::status(to)), expected);
+        assert_eq!(account.status, if expected.is_ok() { to } else { from });
+    }
+
+    #[test]
+    fn close_should_pay_out_available_funds() -> Result<(), AccountError> {
+        let mut account = account(Amount::TEN, Amount::ZERO, AccountStatus::Frozen);
+
+        account.apply(AccountDelta::close(Amount::TEN))?;
+
+        assert_eq!(
+            account,
+            self::account(Amount::ZERO, Amount::ZERO, AccountStatus::Closed)
+        );
+        assert_eq!(
+            account.apply(AccountDelta::deposit(Amount::ONE)),
+            Err(AccountError::Closed)
+        );
+
+        Ok(())
+    }
+
+    #[test_case(Amount::TEN, Amount::ONE ; "held_funds")]
+    #[test_case(Amount::NEGATIVE_ONE, Amount::ZERO ; "debt")]
+    fn unsettled_acc_should_not_be_closed(available: Amount, held: Amount) {
+        let mut account = account(available, held, AccountStatus::Active);
+
+        assert_eq!(
+            account.apply(AccountDelta::close(available)),
+            Err(AccountError::NotSettled)
+        );
+        assert_eq!(account.status, AccountStatus::Active);
     }
 }
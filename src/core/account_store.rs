@@ -1,15 +1,106 @@
-use std::collections::HashMap;
+use std::{cmp::Ordering, collections::HashMap, str::FromStr};
 
 use super::account::{Account, ClientID};
 
+/// Field by which accounts are ordered
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum SortKey {
+    #[default]
+    Client,
+    Total,
+    Available,
+    Held,
+}
+
+impl FromStr for SortKey {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "client" => Ok(SortKey::Client),
+            "total" => Ok(SortKey::Total),
+            "available" => Ok(SortKey::Available),
+            "held" => Ok(SortKey::Held),
+            _ => Err(format!(
+                "Unknown sort key {}, expected one of client, total, available, held",
+                s
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum SortDirection {
+    #[default]
+    Asc,
+    Desc,
+}
+
+impl FromStr for SortDirection {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "asc" => Ok(SortDirection::Asc),
+            "desc" => Ok(SortDirection::Desc),
+            _ => Err(format!(
+                "Unknown sort direction {}, expected asc or desc",
+                s
+            )),
+        }
+    }
+}
+
+/// Order of accounts, ties are always broken by ascending client id
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct AccountOrder {
+    pub key: SortKey,
+    pub direction: SortDirection,
+}
+
+impl AccountOrder {
+    pub fn new(key: SortKey, direction: SortDirection) -> Self {
+        Self { key, direction }
+    }
+
+    fn compare(&self, a: &Account, b: &Account) -> Ordering {
+        let ordering = match self.key {
+            SortKey::Client => a.id.cmp(&b.id),
+            SortKey::Total => a.total.cmp(&b.total),
+            SortKey::Available => a.available.cmp(&b.available),
+            SortKey::Held => a.held.cmp(&b.held),
+        };
+
+        let ordering = match self.direction {
+            SortDirection::Asc => ordering,
+            SortDirection::Desc => ordering.reverse(),
+        };
+
+        ordering.then_with(|| a.id.cmp(&b.id))
+    }
+}
+
 pub trait Store {
     type Storage;
 
     /// Returns existing account or creates a new if does not exist
     fn get_mut_or_new(&mut self, client_id: ClientID) -> &mut Account;
 
+    /// Returns true if account of given client exists
+    fn contains(&self, client_id: ClientID) -> bool;
+
     /// Returns all existing accounts
     fn accounts(&self) -> &Self::Storage;
+
+    /// Returns iterator over all existing accounts in unspecified order
+    fn iter(&self) -> Box<dyn Iterator<Item = &Account> + '_>;
+
+    /// Returns all existing accounts in given order, output is deterministic between runs
+    fn ordered(&self, order: AccountOrder) -> Vec<&Account> {
+        let mut accounts: Vec<_> = self.iter().collect();
+        accounts.sort_by(|a, b| order.compare(a, b));
+        accounts
+    }
 }
 
 /// Represents collection of accounts
@@ -27,15 +118,25 @@ impl Store for AccountStore {
             .or_insert_with(|| Account::new(client_id))
     }
 
+    fn contains(&self, client_id: ClientID) -> bool {
+        self.accounts.contains_key(&client_id)
+    }
+
     fn accounts(&self) -> &Self::Storage {
         &self.accounts
     }
+
+    fn iter(&self) -> Box<dyn Iterator<Item = &Account> + '_> {
+        Box::new(self.accounts.values())
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::AccountStore;
-    use super::Store;
+    use test_case::test_case;
+
+    use super::{AccountOrder, AccountStore, SortDirection, SortKey, Store};
+    use crate::core::account::{AccountDelta, Amount, ClientID};
 
     #[test]
     fn returns_new_account_if_not_exists() {
@@ -48,4 +149,39 @@ mod tests {
 
         assert!(store.accounts().contains_key(&client_id));
     }
+
+    #[test_case(SortKey::Client, SortDirection::Asc, vec![1, 2, 3, 4] ; "client_asc")]
+    #[test_case(SortKey::Client, SortDirection::Desc, vec![4, 3, 2, 1] ; "client_desc")]
+    #[test_case(SortKey::Available, SortDirection::Asc, vec![4, 2, 1, 3] ; "available_asc_ties_by_client")]
+    #[test_case(SortKey::Available, SortDirection::Desc, vec![1, 3, 2, 4] ; "available_desc_ties_by_client")]
+    #[test_case(SortKey::Held, SortDirection::Desc, vec![4, 1, 2, 3] ; "held_desc")]
+    #[test_case(SortKey::Total, SortDirection::Asc, vec![2, 4, 1, 3] ; "total_asc")]
+    fn ordered_accounts(key: SortKey, direction: SortDirection, expected: Vec<ClientID>) {
+        let mut store = AccountStore::default();
+
+        for (client_id, deposit, held) in [(3, 5, 0), (1, 5, 0), (4, 3, 3), (2, 2, 0)] {
+            let account = store.get_mut_or_new(client_id);
+            account
+                .apply(AccountDelta::deposit(Amount::from(deposit)))
+                .unwrap();
+            account
+                .apply(AccountDelta::dispute_deposit(Amount::from(held)))
+                .unwrap();
+        }
+
+        let ids: Vec<ClientID> = store
+            .ordered(AccountOrder::new(key, direction))
+            .into_iter()
+            .map(|acc| acc.id())
+            .collect();
+
+        assert_eq!(ids, expected);
+    }
+
+    #[test]
+    fn unknown_sort_key_should_fail_to_parse() {
+        assert!("balance".parse::<SortKey>().is_err());
+        assert_eq!("held".parse::<SortKey>(), Ok(SortKey::Held));
+        assert_eq!("desc".parse::<SortDirection>(), Ok(SortDirection::Desc));
+    }
 }
@@ -0,0 +1,578 @@
+use std::collections::HashMap;
+
+use super::{
+    account::{Amount, ClientID},
+    transaction::{
+        DisputedAmount, PositiveAmount, Timestamp, Transaction, TransactionID, TransactionKind,
+        TransactionState,
+    },
+    transaction_store::{Store, StoreError},
+};
+
+// Transaction ids below this value are always kept in dense storage
+pub(crate) const MIN_DENSE_LEN: usize = 1024;
+
+// Marks dense transaction without timestamp
+const NO_TIMESTAMP: Timestamp = Timestamp::MAX;
+
+// Kind is kept in four highest bits, deposit has none of them set
+const WITHDRAWAL_KIND: u8 = 1 << 4;
+const TRANSFER_KIND: u8 = 2 << 4;
+const AUTHORIZATION_KIND: u8 = 3 << 4;
+const FREEZE_KIND: u8 = 4 << 4;
+const UNLOCK_KIND: u8 = 5 << 4;
+const CLOSE_KIND: u8 = 6 << 4;
+const KIND_MASK: u8 = 0b1111_0000;
+
+/// Packed transaction, only data needed by disputes and duplicate checks is kept
+///
+/// `flags` holds kind of transaction in the highest bits and state in the lower bits,
+/// zero means that slot is empty. Administrative changes have no amount.
+#[derive(Clone, Copy)]
+struct CompactTransaction {
+    amount: Amount,
+    client_id: ClientID,
+    flags: u8,
+}
+
+impl CompactTransaction {
+    const EMPTY: Self = Self {
+        amount: Amount::ZERO,
+        client_id: 0,
+        flags: 0,
+    };
+
+    fn pack(transaction: &Transaction) -> Option<Self> {
+        let (kind, amount) = match transaction.kind {
+            TransactionKind::Deposit { amount } => (0, amount.value()),
+            TransactionKind::Withdrawal { amount } => (WITHDRAWAL_KIND, amount.value()),
+            TransactionKind::Transfer { amount, .. } => (TRANSFER_KIND, amount.value()),
+            TransactionKind::Authorize { amount } => (AUTHORIZATION_KIND, amount.value()),
+            TransactionKind::Freeze => (FREEZE_KIND, Amount::ZERO),
+            TransactionKind::Unlock => (UNLOCK_KIND, Amount::ZERO),
+            TransactionKind::Close => (CLOSE_KIND, Amount::ZERO),
+            // Other transactions only refer to ids of stored ones
+            _ => return None,
+        };
+
+        Some(Self {
+            amount,
+            client_id: transaction.client_id(),
+            flags: kind | state_to_bits(transaction.state),
+        })
+    }
+
+    fn unpack(self, tx_id: TransactionID, destination: Option<ClientID>) -> Transaction {
+        // Only positive amounts are packed, except for administrative changes
+        let amount = || PositiveAmount::new(self.amount).expect("Packed amount is positive");
+
+        let kind = match self.flags & KIND_MASK {
+            WITHDRAWAL_KIND => TransactionKind::Withdrawal { amount: amount() },
+            TRANSFER_KIND => TransactionKind::Transfer {
+                amount: amount(),
+                destination: destination.expect("Destination of packed transfer is stored"),
+            },
+            AUTHORIZATION_KIND => TransactionKind::Authorize { amount: amount() },
+            FREEZE_KIND => TransactionKind::Freeze,
+            UNLOCK_KIND => TransactionKind::Unlock,
+            CLOSE_KIND => TransactionKind::Close,
+            _ => TransactionKind::Deposit { amount: amount() },
+        };
+
+        let mut transaction = Transaction::new(kind, self.client_id, tx_id);
+        transaction.state = bits_to_state(self.flags & !KIND_MASK);
+        transaction
+    }
+
+    fn is_empty(&self) -> bool {
+        self.flags == 0
+    }
+
+    fn set_state(&mut self, state: TransactionState) {
+        self.flags = (self.flags & KIND_MASK) | state_to_bits(state);
+    }
+}
+
+/// Memory efficient collection of transactions
+///
+/// Transactions are kept in a vector indexed by [`TransactionID`] as long as ids are dense
+/// (at least half of the slots are occupied), remaining ones fall back to a hash map.
+/// Destinations of transfers, disputed and captured amounts are kept aside, so packed transactions remain small.
+///
+/// Timestamps are needed only by time windows, so they are dropped until [`Store::keep_timestamps`] is requested.
+/// Then they are kept in a vector next to dense transactions, timestamps of sparse ones are kept in a hash map.
+#[derive(Default)]
+pub struct CompactTransactionStore {
+    dense: Vec<CompactTransaction>,
+    sparse: HashMap<TransactionID, CompactTransaction>,
+    destinations: HashMap<TransactionID, ClientID>,
+    keep_timestamps: bool,
+    dense_timestamps: Vec<Timestamp>,
+    sparse_timestamps: HashMap<TransactionID, Timestamp>,
+    disputed: HashMap<TransactionID, DisputedAmount>,
+    captured: HashMap<TransactionID, Amount>,
+    len: usize,
+}
+
+impl CompactTransactionStore {
+    /// Returns number of stored transactions
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    fn slot(&self, tx_id: TransactionID) -> Option<&CompactTransaction> {
+        match self.dense.get(tx_id as usize) {
+            Some(slot) if !slot.is_empty() => Some(slot),
+            // Transaction could be stored in hash map before dense storage has grown
+            _ => self.sparse.get(&tx_id),
+        }
+    }
+
+    fn slot_mut(&mut self, tx_id: TransactionID) -> Option<&mut CompactTransaction> {
+        match self.dense.get_mut(tx_id as usize) {
+            Some(slot) if !slot.is_empty() => Some(slot),
+            _ => self.sparse.get_mut(&tx_id),
+        }
+    }
+
+    fn is_dense(&self, tx_id: TransactionID) -> bool {
+        matches!(self.dense.get(tx_id as usize), Some(slot) if !slot.is_empty())
+    }
+
+    fn timestamp(&self, tx_id: TransactionID) -> Option<Timestamp> {
+        if self.is_dense(tx_id) {
+            self.dense_timestamps
+                .get(tx_id as usize)
+                .copied()
+                .filter(|timestamp| *timestamp != NO_TIMESTAMP)
+        } else {
+            self.sparse_timestamps.get(&tx_id).copied()
+        }
+    }
+
+    fn set_timestamp(&mut self, tx_id: TransactionID, timestamp: Timestamp) {
+        if self.is_dense(tx_id) {
+            // Column grows lazily, transactions inserted before timestamps were kept have none
+            if self.dense_timestamps.len() < self.dense.len() {
+                self.dense_timestamps.resize(self.dense.len(), NO_TIMESTAMP);
+            }
+            self.dense_timestamps[tx_id as usize] = timestamp;
+        } else {
+            self.sparse_timestamps.insert(tx_id, timestamp);
+        }
+    }
+
+    fn unpack(&self, tx_id: TransactionID, slot: &CompactTransaction) -> Transaction {
+        let mut transaction = slot.unpack(tx_id, self.destinations.get(&tx_id).copied());
+        transaction.metadata.timestamp = self.timestamp(tx_id);
+        transaction.disputed = self.disputed.get(&tx_id).copied().unwrap_or_default();
+        transaction.captured = self.captured.get(&tx_id).copied().unwrap_or_default();
+        transaction
+    }
+
+    /// Returns true if dense storage can grow up to given id and remain at least half full
+    fn fits_dense(&self, tx_id: TransactionID) -> bool {
+        let required = tx_id as usize + 1;
+        required <= MIN_DENSE_LEN.max(2 * (self.len + 1))
+    }
+}
+
+impl Store for CompactTransactionStore {
+    fn insert(&mut self, transaction: Transaction) -> Result<(), StoreError> {
+        let tx_id = transaction.tx_id();
+
+        if self.slot(tx_id).is_some() {
+            return Ok(());
+        }
+
+        let packed = match CompactTransaction::pack(&transaction) {
+            Some(packed) => packed,
+            None => return Ok(()),
+        };
+
+        if let TransactionKind::Transfer { destination, .. } = transaction.kind {
+            self.destinations.insert(tx_id, destination);
+        }
+
+        if (tx_id as usize) < self.dense.len() {
+            self.dense[tx_id as usize] = packed;
+        } else if self.fits_dense(tx_id) {
+            self.dense
+                .resize(tx_id as usize + 1, CompactTransaction::EMPTY);
+            self.dense[tx_id as usize] = packed;
+        } else {
+            self.sparse.insert(tx_id, packed);
+        }
+
+        if let (true, Some(timestamp)) = (self.keep_timestamps, transaction.timestamp()) {
+            self.set_timestamp(tx_id, timestamp);
+        }
+
+        self.len += 1;
+
+        Ok(())
+    }
+
+    fn contains(&self, tx_id: &TransactionID) -> Result<bool, StoreError> {
+        Ok(self.slot(*tx_id).is_some())
+    }
+
+    fn get(&self, tx_id: &TransactionID) -> Result<Option<Transaction>, StoreError> {
+        Ok(self.slot(*tx_id).map(|slot| self.unpack(*tx_id, slot)))
+    }
+
+    fn set_state(
+        &mut self,
+        tx_id: &TransactionID,
+        state: TransactionState,
+    ) -> Result<(), StoreError> {
+        if let Some(slot) = self.slot_mut(*tx_id) {
+            slot.set_state(state);
+        }
+        Ok(())
+    }
+
+    fn set_disputed(
+        &mut self,
+        tx_id: &TransactionID,
+        state: TransactionState,
+        disputed: DisputedAmount,
+    ) -> Result<(), StoreError> {
+        if let Some(slot) = self.slot_mut(*tx_id) {
+            slot.set_state(state);
+            self.disputed.insert(*tx_id, disputed);
+        }
+        Ok(())
+    }
+
+    fn set_captured(
+        &mut self,
+        tx_id: &TransactionID,
+        state: TransactionState,
+        captured: Amount,
+    ) -> Result<(), StoreError> {
+        if let Some(slot) = self.slot_mut(*tx_id) {
+            slot.set_state(state);
+            self.captured.insert(*tx_id, captured);
+        }
+        Ok(())
+    }
+
+    fn keep_timestamps(&mut self) {
+        self.keep_timestamps = true;
+    }
+
+    fn iter(&self) -> Box<dyn Iterator<Item = Result<Transaction, StoreError>> + '_> {
+        let dense = self
+            .dense
+            .iter()
+            .enumerate()
+            .filter(|(_, slot)| !slot.is_empty())
+            .map(|(tx_id, slot)| self.unpack(tx_id as TransactionID, slot));
+        let sparse = self
+            .sparse
+            .iter()
+            .map(|(tx_id, slot)| self.unpack(*tx_id, slot));
+
+        Box::new(dense.chain(sparse).map(Ok))
+    }
+}
+
+// State is shifted by one, zero is reserved for empty slot
+fn state_to_bits(state: TransactionState) -> u8 {
+    match state {
+        TransactionState::New => 1,
+        TransactionState::Succeeded => 2,
+        TransactionState::Failed => 3,
+        TransactionState::Disputed => 4,
+        TransactionState::Resolved => 5,
+        TransactionState::Chargeback => 6,
+        TransactionState::Reverted => 7,
+        TransactionState::Captured => 8,
+        TransactionState::Voided => 9,
+    }
+}
+
+fn bits_to_state(bits: u8) -> TransactionState {
+    match bits {
+        2 => TransactionState::Succeeded,
+        3 => TransactionState::Failed,
+        4 => TransactionState::Disputed,
+        5 => TransactionState::Resolved,
+        6 => TransactionState::Chargeback,
+        7 => TransactionState::Reverted,
+        8 => TransactionState::Captured,
+        9 => TransactionState::Voided,
+        _ => TransactionState::New,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use test_case::test_case;
+
+    use crate::core::{
+        account::Amount,
+        tests::{positive, transaction},
+        transaction::{DisputedAmount, TransactionID, TransactionKind, TransactionState},
+        transaction_store::{Store, StoreError},
+    };
+
+    use super::{CompactTransaction, CompactTransactionStore};
+
+    #[test]
+    fn packed_transaction_should_fit_in_24_bytes() {
+        assert!(std::mem::size_of::<CompactTransaction>() <= 24);
+    }
+
+    #[test_case(1 ; "dense_id")]
+    #[test_case(4_000_000_000 ; "sparse_id")]
+    fn insert_and_get_transaction(tx_id: TransactionID) -> Result<(), StoreError> {
+        let mut store = CompactTransactionStore::default();
+
+        store.insert(transaction(
+            TransactionKind::Withdrawal {
+                amount: positive(Amount::new(12345, 4)),
+            },
+            tx_id,
+            3,
+        ))?;
+        store.set_state(&tx_id, TransactionState::Disputed)?;
+
+        let transaction = store.get(&tx_id)?.unwrap();
+
+        assert_eq!(transaction.client_id(), 3);
+        assert_eq!(transaction.tx_id(), tx_id);
+        assert_eq!(transaction.state, TransactionState::Disputed);
+        assert_eq!(
+            transaction.kind,
+            TransactionKind::Withdrawal {
+                amount: positive(Amount::new(12345, 4))
+            }
+        );
+        assert!(store.get(&(tx_id - 1))?.is_none());
+        assert_eq!(store.len(), 1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn transfer_should_keep_destination_and_kind_after_state_change() -> Result<(), StoreError> {
+        let mut store = CompactTransactionStore::default();
+        let kind = TransactionKind::Transfer {
+            amount: positive(Amount::TWO),
+            destination: 9,
+        };
+
+        store.insert(transaction(kind, 1, 3))?;
+        store.set_state(&1, TransactionState::Chargeback)?;
+
+        let transaction = store.get(&1)?.unwrap();
+
+        assert_eq!(transaction.kind, kind);
+        assert_eq!(transaction.state, TransactionState::Chargeback);
+
+        Ok(())
+    }
+
+    #[test]
+    fn disputed_amount_should_be_kept_with_transaction() -> Result<(), StoreError> {
+        let mut store = CompactTransactionStore::default();
+        let disputed = DisputedAmount {
+            total: Amount::TWO,
+            outstanding: Amount::ONE,
+            charged_back: Amount::ZERO,
+        };
+
+        store.insert(transaction(
+            TransactionKind::Deposit {
+                amount: positive(Amount::TEN),
+            },
+            1,
+            3,
+        ))?;
+        store.set_disputed(&1, TransactionState::Disputed, disputed)?;
+
+        let transaction = store.get(&1)?.unwrap();
+
+        assert_eq!(transaction.state, TransactionState::Disputed);
+        assert_eq!(transaction.disputed, disputed);
+
+        Ok(())
+    }
+
+    #[test_case(1 ; "dense_id")]
+    #[test_case(4_000_000_000 ; "sparse_id")]
+    fn transaction_should_keep_timestamp(tx_id: TransactionID) -> Result<(), StoreError> {
+        let mut store = CompactTransactionStore::default();
+        let kind = TransactionKind::Deposit {
+            amount: positive(Amount::ONE),
+        };
+
+        store.keep_timestamps();
+        store.insert(transaction(kind, tx_id, 3).with_timestamp(1_700_000_000))?;
+        store.insert(transaction(kind, tx_id + 1, 3))?;
+
+        assert_eq!(store.get(&tx_id)?.unwrap().timestamp(), Some(1_700_000_000));
+        assert_eq!(store.get(&(tx_id + 1))?.unwrap().timestamp(), None);
+
+        Ok(())
+    }
+
+    #[test]
+    fn timestamps_should_be_dropped_unless_kept() -> Result<(), StoreError> {
+        let mut store = CompactTransactionStore::default();
+        let kind = TransactionKind::Deposit {
+            amount: positive(Amount::ONE),
+        };
+
+        store.insert(transaction(kind, 1, 3).with_timestamp(1_700_000_000))?;
+        store.keep_timestamps();
+        store.insert(transaction(kind, 2, 3).with_timestamp(1_700_000_000))?;
+
+        assert_eq!(store.get(&1)?.unwrap().timestamp(), None);
+        assert_eq!(store.get(&2)?.unwrap().timestamp(), Some(1_700_000_000));
+        assert!(store.sparse_timestamps.is_empty());
+
+        Ok(())
+    }
+
+    #[test_case(TransactionKind::Freeze ; "freeze")]
+    #[test_case(TransactionKind::Unlock ; "unlock")]
+    #[test_case(TransactionKind::Close ; "close")]
+    fn administrative_change_should_keep_its_id(kind: TransactionKind) -> Result<(), StoreError> {
+        let mut store = CompactTransactionStore::default();
+
+        store.insert(transaction(kind, 1, 3))?;
+        store.set_state(&1, TransactionState::Failed)?;
+
+        let transaction = store.get(&1)?.unwrap();
+
+        assert_eq!(transaction.kind, kind);
+        assert_eq!(transaction.client_id(), 3);
+        assert_eq!(transaction.state, TransactionState::Failed);
+
+        Ok(())
+    }
+
+    #[test]
+    fn authorization_should_keep_captured_amount() -> Result<(), StoreError> {
+        let mut store = CompactTransactionStore::default();
+        let kind = TransactionKind::Authorize {
+            amount: positive(Amount::TEN),
+        };
+
+        store.insert(transaction(kind, 1, 3))?;
+        store.set_captured(&1, TransactionState::Captured, Amount::TEN)?;
+
+        let transaction = store.get(&1)?.unwrap();
+
+        assert_eq!(transaction.kind, kind);
+        assert_eq!(transaction.state, TransactionState::Captured);
+        assert_eq!(transaction.captured, Amount::TEN);
+
+        Ok(())
+    }
+
+    #[test]
+    fn insert_should_not_overwrite_existing_transaction() -> Result<(), StoreError> {
+        let mut store = CompactTransactionStore::default();
+
+        store.insert(transaction(
+            TransactionKind::Deposit {
+                amount: positive(Amount::ONE),
+            },
+            1,
+            1,
+        ))?;
+        store.insert(transaction(
+            TransactionKind::Deposit {
+                amount: positive(Amount::TEN),
+            },
+            1,
+            2,
+        ))?;
+
+        assert_eq!(store.get(&1)?.unwrap().client_id(), 1);
+        assert_eq!(store.len(), 1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn sparse_transaction_should_be_found_after_dense_storage_grows() -> Result<(), StoreError> {
+        let mut store = CompactTransactionStore::default();
+
+        store.insert(transaction(
+            TransactionKind::Deposit {
+                amount: positive(Amount::TWO),
+            },
+            5_000,
+            7,
+        ))?;
+
+        for tx_id in 0..10_000 {
+            store.insert(transaction(
+                TransactionKind::Deposit {
+                    amount: positive(Amount::ONE),
+                },
+                tx_id,
+                1,
+            ))?;
+        }
+
+        assert_eq!(store.get(&5_000)?.unwrap().client_id(), 7);
+        assert_eq!(store.len(), 10_000);
+
+        Ok(())
+    }
+
+    #[test]
+    fn dense_ids_should_not_use_hash_map() -> Result<(), StoreError> {
+        let mut store = CompactTransactionStore::default();
+
+        for tx_id in 0..10_000 {
+            store.insert(transaction(
+                TransactionKind::Deposit {
+                    amount: positive(Amount::ONE),
+                },
+                tx_id,
+                1,
+            ))?;
+        }
+
+        assert!(store.sparse.is_empty());
+        assert_eq!(store.len(), 10_000);
+
+        Ok(())
+    }
+
+    #[test]
+    fn iter_should_return_dense_and_sparse_transactions() -> Result<(), StoreError> {
+        let mut store = CompactTransactionStore::default();
+
+        for tx_id in [1, 2, 4_000_000_000] {
+            store.insert(transaction(
+                TransactionKind::Deposit {
+                    amount: positive(Amount::ONE),
+                },
+                tx_id,
+                1,
+            ))?;
+        }
+
+        let mut ids = store
+            .iter()
+            .map(|transaction| transaction.map(|transaction| transaction.tx_id()))
+            .collect::<Result<Vec<_>, _>>()?;
+        ids.sort_unstable();
+
+        assert_eq!(ids, vec![1, 2, 4_000_000_000]);
+
+        Ok(())
+    }
+}
@@ -1,8 +1,35 @@
+use std::collections::HashMap;
+
 use super::{
-    account_store, transaction::Transaction, transaction_processor::TransactionProcessor,
-    transaction_store,
+    account::{Account, AccountDelta, AccountError, AccountStatus, ClientID, CreditLine},
+    account_store::{self, AccountOrder},
+    expiry::{ExpiryPolicy, HoldDeadlines},
+    journal::Journal,
+    snapshot::Snapshot,
+    transaction::{Timestamp, Transaction, TransactionID, TransactionKind, TransactionState},
+    transaction_processor::{Counterpart, TransactionError, TransactionProcessor},
+    transaction_store::{self, StoreError},
 };
 
+/// Applies counterpart to account owned by other engine, see [`Engine::process_coordinated`]
+pub(crate) type Remote<'a> = dyn FnMut(Counterpart) -> Result<(), AccountError> + 'a;
+
+/// Outcome of processed transaction
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ProcessOutcome {
+    /// Changes from transaction have been applied to user account
+    Applied,
+    /// User account refused to apply changes
+    Rejected(AccountError),
+    /// Transaction has been skipped, user account remains untouched
+    Ignored(TransactionError),
+    /// Transaction with the same id has been already processed, user account remains untouched
+    Duplicate,
+    /// Transaction storage failed, when it happens after delta has been applied
+    /// user account already reflects the transaction
+    Failed(StoreError),
+}
+
 /// [`Engine`] in an entry point for transaction processing
 ///
 /// Engine has a transaction processor which converts incoming transaction into [`AccountDelta`],
@@ -35,54 +62,411 @@ use super::{
 #[derive(Default)]
 pub struct Engine<T, A>
 where
-    T: transaction_store::Store + Default,
+    T: transaction_store::Store,
     A: account_store::Store,
 {
     transactions: TransactionProcessor<T>,
     accounts: A,
+    journal: Option<Journal>,
+    expiry: ExpiryPolicy,
+    // Time of the latest transaction with timestamp
+    clock: Option<Timestamp>,
+    holds: HoldDeadlines,
+    credit_lines: HashMap<ClientID, CreditLine>,
 }
 
 impl<T, A> Engine<T, A>
 where
-    T: transaction_store::Store + Default,
+    T: transaction_store::Store,
     A: account_store::Store,
 {
+    /// Creates engine on top of given storages
+    pub fn new(transactions: T, accounts: A) -> Self {
+        Self {
+            transactions: TransactionProcessor::new(transactions),
+            accounts,
+            journal: None,
+            expiry: ExpiryPolicy::default(),
+            clock: None,
+            holds: HoldDeadlines::default(),
+            credit_lines: HashMap::new(),
+        }
+    }
+
+    /// Enables journal of processed transactions, see [`Journal`]
+    pub fn enable_journal(&mut self) {
+        self.journal.get_or_insert_with(Journal::default);
+    }
+
+    /// Returns journal if it has been enabled
+    pub fn journal(&self) -> Option<&Journal> {
+        self.journal.as_ref()
+    }
+
+    /// Enables or disables idempotent mode, in which exact replays of already processed
+    /// transactions are ignored instead of being reported as duplicates
+    pub fn set_idempotent(&mut self, idempotent: bool) {
+        self.transactions.set_idempotent(idempotent);
+    }
+
+    /// Sets time windows enforced by engine, see [`ExpiryPolicy`]
+    ///
+    /// Policy should be set before any transaction is processed or restored, so timestamps of stored transactions
+    /// are kept for it and holds of restored authorizations expire too.
+    pub fn set_expiry_policy(&mut self, expiry: ExpiryPolicy) {
+        self.transactions.set_dispute_window(expiry.dispute_window);
+        if expiry != ExpiryPolicy::default() {
+            self.transactions.keep_timestamps();
+        }
+        self.expiry = expiry;
+    }
+
+    /// Sets overdrafts of user accounts, clients without credit line can't go below zero
+    ///
+    /// Credit line is assigned to user account whenever it is touched, so changed lines apply from the next transaction.
+    pub fn set_credit_lines(&mut self, credit_lines: HashMap<ClientID, CreditLine>) {
+        self.credit_lines = credit_lines;
+    }
+
+    /// Returns time of the latest transaction with timestamp
+    pub fn clock(&self) -> Option<Timestamp> {
+        self.clock
+    }
+
+    /// Moves clock forward to given time and releases holds of authorizations which have expired by then
+    ///
+    /// Every expiry is processed as [`TransactionKind::Expire`] of authorization, so it is recorded in journal.
+    /// Clock never moves backwards.
+    pub fn advance_clock(&mut self, now: Timestamp) -> Result<(), StoreError> {
+        let now = self.clock.map_or(now, |clock| clock.max(now));
+        self.clock = Some(now);
+
+        while let Some(tx_id) = self.holds.pop_expired(now) {
+            let client_id = match self.transactions.get(&tx_id)? {
+                Some(transaction) if transaction.state == TransactionState::Succeeded => {
+                    transaction.client_id()
+                }
+                // Authorization which has been captured or voided meanwhile holds nothing
+                _ => continue,
+            };
+
+            let expire = Transaction::new(TransactionKind::Expire, client_id, tx_id);
+            if let ProcessOutcome::Failed(err) = self.process_transaction(expire) {
+                return Err(err);
+            }
+        }
+
+        Ok(())
+    }
+
     /// processes transaction and applies outcome of it to user account
-    pub fn process_transaction(&mut self, transaction: Transaction) {
+    ///
+    /// Transaction with timestamp advances clock of engine first, see [`Engine::advance_clock`].
+    /// Transaction without timestamp is considered to happen at time of the latest one.
+    pub fn process_transaction(&mut self, transaction: Transaction) -> ProcessOutcome {
+        self.process(transaction, None)
+    }
+
+    /// Processes transaction whose counterpart belongs to account of other engine
+    ///
+    /// Counterpart is handed to `remote` only after user account has accepted its own delta,
+    /// user account is changed only when `remote` accepts the counterpart, see [`Engine::apply_counterpart`].
+    pub(crate) fn process_coordinated(
+        &mut self,
+        transaction: Transaction,
+        remote: &mut Remote,
+    ) -> ProcessOutcome {
+        self.process(transaction, Some(remote))
+    }
+
+    /// Reports transaction whose id is stored by other engine as duplicate
+    ///
+    /// Clock is advanced and account is opened the same way as for duplicate found by this engine.
+    pub(crate) fn process_duplicate(&mut self, transaction: Transaction) -> ProcessOutcome {
+        if let Some(timestamp) = transaction.timestamp() {
+            if let Err(err) = self.advance_clock(timestamp) {
+                return ProcessOutcome::Failed(err);
+            }
+        }
+
         let client_id = transaction.client_id();
+        if !transaction.kind.is_administrative() {
+            self.account_mut(client_id);
+        }
+
+        if let Some(journal) = self.journal.as_mut() {
+            journal.record(
+                transaction.tx_id(),
+                client_id,
+                transaction.kind,
+                AccountDelta::none(),
+                ProcessOutcome::Duplicate,
+            );
+        }
+
+        ProcessOutcome::Duplicate
+    }
+
+    /// Applies counterpart of transaction processed by other engine, see [`Engine::process_coordinated`]
+    pub(crate) fn apply_counterpart(
+        &mut self,
+        counterpart: Counterpart,
+    ) -> Result<(), AccountError> {
+        self.account_mut(counterpart.client_id)
+            .apply(counterpart.delta)
+    }
+
+    fn process(
+        &mut self,
+        mut transaction: Transaction,
+        remote: Option<&mut Remote>,
+    ) -> ProcessOutcome {
+        match transaction.timestamp() {
+            Some(timestamp) => {
+                if let Err(err) = self.advance_clock(timestamp) {
+                    return ProcessOutcome::Failed(err);
+                }
+            }
+            None => transaction.metadata.timestamp = self.clock,
+        }
+
         let tx_id = transaction.tx_id();
+        let client_id = transaction.client_id();
+        let kind = transaction.kind;
+        let timestamp = transaction.timestamp();
 
-        let change = self.transactions.produce_delta(transaction);
+        let (delta, counterpart, outcome) = self.apply_transaction(transaction, remote);
+
+        if let (TransactionKind::Authorize { .. }, ProcessOutcome::Applied) = (kind, &outcome) {
+            self.track_hold(tx_id, timestamp);
+        }
 
-        match self.accounts.get_mut_or_new(client_id).apply(change) {
-            Ok(_) => {
-                self.transactions.succeed(tx_id);
+        if let Some(journal) = self.journal.as_mut() {
+            journal.record(tx_id, client_id, kind, delta, outcome.clone());
+
+            // Change of the other account is recorded as its own entry, so every account can be replayed
+            if let Some(counterpart) = counterpart {
+                journal.record(
+                    tx_id,
+                    counterpart.client_id,
+                    kind,
+                    counterpart.delta,
+                    outcome.clone(),
+                );
             }
+        }
+
+        outcome
+    }
+
+    /// Reverts effect of already applied deposit or withdrawal, including disputes of it
+    ///
+    /// Reverted transaction can't be disputed anymore. Revert of chargeback restores status account had
+    /// before it has been locked, only while it is still locked by that chargeback. Revert of deposit
+    /// whose funds have been already withdrawn is rejected with [`AccountError::InsufficientFunds`].
+    pub fn revert(&mut self, tx_id: TransactionID) -> ProcessOutcome {
+        let client_id = match self.transactions.get(&tx_id) {
+            Ok(Some(transaction)) => transaction.client_id(),
+            Ok(None) => return ProcessOutcome::Ignored(TransactionError::UnknownTransaction),
+            Err(err) => return ProcessOutcome::Failed(err),
+        };
+
+        self.process_transaction(Transaction::new(TransactionKind::Revert, client_id, tx_id))
+    }
+
+    fn apply_transaction(
+        &mut self,
+        transaction: Transaction,
+        remote: Option<&mut Remote>,
+    ) -> (AccountDelta, Option<Counterpart>, ProcessOutcome) {
+        let client_id = transaction.client_id();
+        let administrative = transaction.kind.is_administrative();
+
+        // Administrative change refers to existing account, any other transaction opens it
+        let exists = self.accounts.contains(client_id);
+        if !administrative {
+            self.account_mut(client_id);
+        }
+
+        let proposal = match self.transactions.produce_delta(transaction) {
+            Ok(proposal) => proposal,
             Err(err) => {
-                self.transactions.failed(tx_id);
-                log::error!("Transaction {:?} failed: {:?}", tx_id, err);
+                let outcome = match err {
+                    TransactionError::Duplicate => ProcessOutcome::Duplicate,
+                    TransactionError::Store(err) => ProcessOutcome::Failed(err),
+                    err => ProcessOutcome::Ignored(err),
+                };
+                return (AccountDelta::none(), None, outcome);
+            }
+        };
+
+        if administrative && !exists {
+            // Id is kept as for any other refused transaction
+            let outcome = match self.transactions.rollback(proposal.transition) {
+                Ok(_) => ProcessOutcome::Rejected(AccountError::UnknownAccount),
+                Err(err) => ProcessOutcome::Failed(err),
+            };
+            return (AccountDelta::none(), None, outcome);
+        }
+
+        let counterpart = proposal.counterpart;
+        let delta = match proposal.delta.status {
+            // Closed account pays out its available funds, balance is known only here
+            Some(AccountStatus::Closed) => {
+                AccountDelta::close(self.account_mut(client_id).available())
+            }
+            _ => proposal.delta,
+        };
+
+        // Transition of transaction is committed only when account accepts delta,
+        // so transaction storage and user account can't drift apart
+        let outcome = match self.apply_delta(client_id, delta, counterpart, remote) {
+            Ok(_) => match self.transactions.commit(proposal.transition) {
+                Ok(_) => ProcessOutcome::Applied,
+                Err(err) => ProcessOutcome::Failed(err),
+            },
+            Err(err) => match self.transactions.rollback(proposal.transition) {
+                Ok(_) => ProcessOutcome::Rejected(err),
+                Err(err) => ProcessOutcome::Failed(err),
+            },
+        };
+
+        (delta, counterpart, outcome)
+    }
+
+    /// Applies delta to user account together with counterpart, either both of them are applied or none
+    ///
+    /// Counterpart is applied by `remote` when it is set, otherwise by this engine.
+    fn apply_delta(
+        &mut self,
+        client_id: ClientID,
+        delta: AccountDelta,
+        counterpart: Option<Counterpart>,
+        remote: Option<&mut Remote>,
+    ) -> Result<(), AccountError> {
+        if let (Some(counterpart), Some(remote)) = (counterpart, remote) {
+            // Other account can't be changed back, so user account is checked on a copy first
+            let mut account = self.account_mut(client_id).clone();
+            account.apply(delta)?;
+            remote(counterpart)?;
+            *self.account_mut(client_id) = account;
+
+            return Ok(());
+        }
+
+        // Counterpart is applied to a copy first, so it can be discarded when user account refuses delta
+        let other = match counterpart {
+            Some(counterpart) => {
+                let mut other = self.account_mut(counterpart.client_id).clone();
+                other.apply(counterpart.delta)?;
+                Some(other)
+            }
+            None => None,
+        };
+
+        self.account_mut(client_id).apply(delta)?;
+
+        if let Some(other) = other {
+            let other_id = other.id();
+            *self.accounts.get_mut_or_new(other_id) = other;
+        }
+
+        Ok(())
+    }
+
+    /// Returns user account with its credit line, account is created if it doesn't exist yet
+    fn account_mut(&mut self, client_id: ClientID) -> &mut Account {
+        let account = self.accounts.get_mut_or_new(client_id);
+        account.set_credit_line(self.credit_lines.get(&client_id).copied());
+        account
+    }
+
+    /// Returns snapshot of user accounts and stored transactions, journal is not included
+    pub fn snapshot(&self) -> Result<Snapshot, StoreError> {
+        Snapshot::new(self.accounts.iter(), self.transactions.iter())
+    }
+
+    /// Persists stored transactions and returns snapshot which records length of persisted log
+    pub fn checkpoint(&mut self) -> Result<Snapshot, StoreError> {
+        let log_len = self.transactions.sync()?;
+        let mut snapshot = self.snapshot()?;
+        snapshot.log_len = log_len;
+        Ok(snapshot)
+    }
+
+    /// Restores user accounts and transactions from snapshot, existing ones are overwritten
+    pub fn restore(&mut self, snapshot: Snapshot) -> Result<(), StoreError> {
+        let (accounts, transactions) = snapshot.into_parts();
+
+        for account in accounts {
+            let client_id = account.id();
+            let restored = self.account_mut(client_id);
+            *restored = Account {
+                credit_line: restored.credit_line(),
+                ..account
+            };
+        }
+
+        for transaction in transactions {
+            if let Some(timestamp) = transaction.timestamp() {
+                self.clock = Some(self.clock.map_or(timestamp, |clock| clock.max(timestamp)));
+            }
+            if let (TransactionKind::Authorize { .. }, TransactionState::Succeeded) =
+                (transaction.kind, transaction.state)
+            {
+                self.track_hold(transaction.tx_id(), transaction.timestamp());
             }
+
+            self.transactions.restore(transaction)?;
+        }
+
+        Ok(())
+    }
+
+    /// Schedules release of authorization hold, holds of authorizations without timestamp never expire
+    fn track_hold(&mut self, tx_id: TransactionID, timestamp: Option<Timestamp>) {
+        if let (Some(expiry), Some(timestamp)) = (self.expiry.hold_expiry, timestamp) {
+            self.holds.push(timestamp.saturating_add(expiry), tx_id);
         }
     }
 
+    /// Consumes engine and returns storage of user accounts
+    pub fn into_accounts(self) -> A {
+        self.accounts
+    }
+
     // returns all users accounts
     pub fn accounts(&self) -> &A::Storage {
         self.accounts.accounts()
     }
+
+    // returns all users accounts in given order
+    pub fn ordered_accounts(&self, order: AccountOrder) -> Vec<&Account> {
+        self.accounts.ordered(order)
+    }
 }
 
 #[cfg(test)]
 mod tests {
+    use std::collections::HashMap;
+
     use test_case::test_case;
 
     use crate::core::{
-        account::{Account, Amount},
+        account::{
+            Account, AccountDelta, AccountError, AccountStatus, Amount, ClientID, CreditLine, Lock,
+        },
         account_store::AccountStore,
-        transaction::Transaction,
-        transaction_store::TransactionStore,
+        compact_transaction_store::CompactTransactionStore,
+        expiry::{ExpiryPolicy, DAY},
+        transaction::{
+            PositiveAmount, Transaction, TransactionKind, TransactionState, TransitionError,
+        },
+        transaction_processor::TransactionError,
+        transaction_store::{StoreError, TransactionStore},
     };
 
-    use super::Engine;
+    use super::{Engine, ProcessOutcome};
 
     fn read_transactions(transactions: &str) -> Vec<Transaction> {
         csv::ReaderBuilder::new()
@@ -101,7 +485,7 @@ mod tests {
     deposit,2,2,2.0
     deposit,1,3,5.0
     "#,  
-    Account { id: 1, available: Amount::new(8,0), held: Amount::ZERO, total: Amount::new(8,0), locked: false }  ; "deposit_should_increase_available_funds")]
+    Account { id: 1, available: Amount::new(8,0), held: Amount::ZERO, total: Amount::new(8,0), status: AccountStatus::Active, credit_line: None, lock: None }  ; "deposit_should_increase_available_funds")]
     #[test_case(
     r#"
     type,client,tx,amount
@@ -109,7 +493,7 @@ mod tests {
     deposit,2,2,2.0
     withdrawal,1,3,5.0
     "#,  
-    Account { id: 1, available: Amount::new(3,0), held: Amount::ZERO, total: Amount::new(3,0), locked: false }  ; "withdrawal_should_not_exceed_available_funds")]
+    Account { id: 1, available: Amount::new(3,0), held: Amount::ZERO, total: Amount::new(3,0), status: AccountStatus::Active, credit_line: None, lock: None }  ; "withdrawal_should_not_exceed_available_funds")]
     #[test_case(
     r#"
     type,client,tx,amount
@@ -117,7 +501,7 @@ mod tests {
     deposit,2,2,2.0
     withdrawal,1,3,2.0
     "#,  
-    Account { id: 1, available: Amount::new(1,0), held: Amount::ZERO, total: Amount::new(1,0), locked: false }  ; "withdrawal_should_decrease_available_funds")]
+    Account { id: 1, available: Amount::new(1,0), held: Amount::ZERO, total: Amount::new(1,0), status: AccountStatus::Active, credit_line: None, lock: None }  ; "withdrawal_should_decrease_available_funds")]
     #[test_case(
     r#"
     type,client,tx,amount
@@ -125,7 +509,7 @@ mod tests {
     deposit,2,2,2.0
     dispute,1,1,
     "#,  
-    Account { id: 1, available: Amount::ZERO, held: Amount::new(3,0), total: Amount::new(3,0), locked: false }  ; "dispute_should_decrease_available_funds_and_increase_held")]
+    Account { id: 1, available: Amount::ZERO, held: Amount::new(3,0), total: Amount::new(3,0), status: AccountStatus::Active, credit_line: None, lock: None }  ; "dispute_should_decrease_available_funds_and_increase_held")]
     #[test_case(
     r#"
     type,client,tx,amount
@@ -134,7 +518,7 @@ mod tests {
     withdrawal,1,3,2.0
     dispute,1,3,
     "#,  
-    Account { id: 1, available: Amount::new(1, 0), held: Amount::new(2,0), total: Amount::new(3,0), locked: false }  ; "dispute_on_withdrawal_should_increase_held_funds")]
+    Account { id: 1, available: Amount::new(1, 0), held: Amount::new(2,0), total: Amount::new(3,0), status: AccountStatus::Active, credit_line: None, lock: None }  ; "dispute_on_withdrawal_should_increase_held_funds")]
     #[test_case(
     r#"
     type,client,tx,amount
@@ -143,7 +527,7 @@ mod tests {
     withdrawal,1,3,2.0
     dispute,1,1,
     "#,  
-    Account { id: 1, available: Amount::new(-2, 0), held: Amount::new(3,0), total: Amount::new(1,0), locked: false }  ; "dispute_on_deposit_when_user_is_out_of_money_should_create_debt")]
+    Account { id: 1, available: Amount::new(-2, 0), held: Amount::new(3,0), total: Amount::new(1,0), status: AccountStatus::Active, credit_line: None, lock: None }  ; "dispute_on_deposit_when_user_is_out_of_money_should_create_debt")]
     #[test_case(
     r#"
     type,client,tx,amount
@@ -153,7 +537,7 @@ mod tests {
     dispute,1,3,
     resolve,1,3,
     "#,  
-    Account { id: 1, available: Amount::new(3, 0), held: Amount::ZERO, total: Amount::new(3,0), locked: false }  ; "resolved_dispute_should_increase_available_funds_and_decrease_held_funds")]
+    Account { id: 1, available: Amount::new(3, 0), held: Amount::ZERO, total: Amount::new(3,0), status: AccountStatus::Active, credit_line: None, lock: None }  ; "resolved_dispute_should_increase_available_funds_and_decrease_held_funds")]
     #[test_case(
     r#"
     type,client,tx,amount
@@ -163,15 +547,15 @@ mod tests {
     dispute,1,3,
     chargeback,1,3,
     "#,  
-    Account { id: 1, available: Amount::new(1, 0), held: Amount::ZERO, total: Amount::new(1,0), locked: true }  ; "charge_should_withdraw_held_funds_and_lock_acc")]
+    Account { id: 1, available: Amount::new(1, 0), held: Amount::ZERO, total: Amount::new(1,0), status: AccountStatus::Locked, credit_line: None, lock: None }  ; "charge_should_withdraw_held_funds_and_lock_acc")]
 
     fn engine(input_data: &str, expected_acc: Account) {
         let transactions = read_transactions(input_data);
         let mut engine = Engine::<TransactionStore, AccountStore>::default();
 
-        transactions
-            .into_iter()
-            .for_each(|f| engine.process_transaction(f));
+        transactions.into_iter().for_each(|f| {
+            engine.process_transaction(f);
+        });
 
         let accounts = engine.accounts();
 
@@ -181,13 +565,1144 @@ mod tests {
         assert_eq!(acc_1.available, expected_acc.available);
         assert_eq!(acc_1.total, expected_acc.total);
         assert_eq!(acc_1.held, expected_acc.held);
-        assert_eq!(acc_1.locked, expected_acc.locked);
+        assert_eq!(acc_1.status, expected_acc.status);
 
         let acc_2 = accounts.get(&2).unwrap();
 
         assert_eq!(acc_2.available, Amount::new(2, 0));
         assert_eq!(acc_2.total, Amount::new(2, 0));
         assert_eq!(acc_2.held, Amount::ZERO);
-        assert_eq!(acc_2.locked, false);
+        assert_eq!(acc_2.is_locked(), false);
+    }
+
+    #[test_case(
+    r#"
+    type,client,tx,amount
+    deposit,1,1,3.0
+    "#,
+    ProcessOutcome::Applied ; "deposit_should_be_applied")]
+    #[test_case(
+    r#"
+    type,client,tx,amount
+    deposit,1,1,3.0
+    withdrawal,1,2,5.0
+    "#,
+    ProcessOutcome::Rejected(AccountError::InsufficientFunds(Amount::new(3,0))) ; "withdrawal_over_available_funds_should_be_rejected")]
+    #[test_case(
+    r#"
+    type,client,tx,amount
+    deposit,1,1,3.0
+    dispute,1,1,
+    chargeback,1,1,
+    deposit,1,2,1.0
+    "#,
+    ProcessOutcome::Rejected(AccountError::Locked) ; "deposit_on_locked_account_should_be_rejected")]
+    #[test_case(
+    r#"
+    type,client,tx,amount
+    deposit,1,1,3.0
+    dispute,1,2,
+    "#,
+    ProcessOutcome::Ignored(TransactionError::UnknownTransaction) ; "dispute_of_unknown_transaction_should_be_ignored")]
+    #[test_case(
+    r#"
+    type,client,tx,amount
+    deposit,1,1,3.0
+    dispute,2,1,
+    "#,
+    ProcessOutcome::Ignored(TransactionError::ClientMismatch) ; "dispute_of_other_client_transaction_should_be_ignored")]
+    #[test_case(
+    r#"
+    type,client,tx,amount
+    deposit,1,1,3.0
+    dispute,1,1,
+    resolve,1,1,
+    resolve,1,1,
+    "#,
+    ProcessOutcome::Ignored(TransactionError::InvalidTransition(TransitionError { from: TransactionState::Resolved, to: TransactionState::Resolved })) ; "resolve_of_resolved_transaction_should_be_ignored")]
+    #[test_case(
+    r#"
+    type,client,tx,amount
+    deposit,1,1,3.0
+    withdrawal,1,2,5.0
+    dispute,1,2,
+    "#,
+    ProcessOutcome::Ignored(TransactionError::InvalidTransition(TransitionError { from: TransactionState::Failed, to: TransactionState::Disputed })) ; "dispute_of_failed_withdrawal_should_be_ignored")]
+    fn last_transaction_outcome(input_data: &str, expected_outcome: ProcessOutcome) {
+        let transactions = read_transactions(input_data);
+        let mut engine = Engine::<TransactionStore, AccountStore>::default();
+
+        let outcome = transactions
+            .into_iter()
+            .map(|f| engine.process_transaction(f))
+            .last();
+
+        assert_eq!(outcome, Some(expected_outcome));
+    }
+
+    #[test_case(
+    r#"
+    type,client,tx,amount
+    deposit,1,1,3.0
+    deposit,1,1,3.0
+    "#,
+    false, ProcessOutcome::Duplicate ; "replay_should_be_duplicate_when_not_idempotent")]
+    #[test_case(
+    r#"
+    type,client,tx,amount
+    deposit,1,1,3.0
+    deposit,1,1,3.0
+    "#,
+    true, ProcessOutcome::Ignored(TransactionError::Replayed) ; "replay_should_be_ignored_when_idempotent")]
+    #[test_case(
+    r#"
+    type,client,tx,amount
+    deposit,1,1,3.0
+    withdrawal,1,1,3.0
+    "#,
+    true, ProcessOutcome::Duplicate ; "different_transaction_with_same_id_should_be_duplicate_when_idempotent")]
+    #[test_case(
+    r#"
+    type,client,tx,amount
+    deposit,1,1,3.0
+    deposit,2,1,3.0
+    "#,
+    true, ProcessOutcome::Duplicate ; "same_id_of_other_client_should_be_duplicate_when_idempotent")]
+    #[test_case(
+    r#"
+    type,client,tx,amount
+    deposit,1,1,3.0
+    freeze,1,1,
+    "#,
+    true, ProcessOutcome::Duplicate ; "administrative_change_with_id_of_deposit_should_be_duplicate")]
+    #[test_case(
+    r#"
+    type,client,tx,amount
+    deposit,1,1,3.0
+    freeze,1,2,
+    freeze,1,2,
+    "#,
+    false, ProcessOutcome::Duplicate ; "replayed_administrative_change_should_be_duplicate_when_not_idempotent")]
+    #[test_case(
+    r#"
+    type,client,tx,amount
+    deposit,1,1,3.0
+    freeze,1,2,
+    freeze,1,2,
+    "#,
+    true, ProcessOutcome::Ignored(TransactionError::Replayed) ; "replayed_administrative_change_should_be_ignored_when_idempotent")]
+    fn duplicated_transaction(
+        input_data: &str,
+        idempotent: bool,
+        expected_outcome: ProcessOutcome,
+    ) {
+        let transactions = read_transactions(input_data);
+        let mut engine = Engine::<TransactionStore, AccountStore>::default();
+        engine.set_idempotent(idempotent);
+
+        let outcome = transactions
+            .into_iter()
+            .map(|f| engine.process_transaction(f))
+            .last();
+
+        assert_eq!(outcome, Some(expected_outcome));
+
+        let acc_1 = engine.accounts().get(&1).unwrap();
+        assert_eq!(acc_1.available, Amount::new(3, 0));
+        assert_eq!(acc_1.total, Amount::new(3, 0));
+    }
+
+    #[test]
+    fn dispute_should_refer_to_original_transaction_after_duplicate() {
+        let transactions = read_transactions(
+            r#"
+    type,client,tx,amount
+    deposit,1,1,3.0
+    deposit,1,1,10.0
+    dispute,1,1,
+    "#,
+        );
+        let mut engine = Engine::<TransactionStore, AccountStore>::default();
+
+        transactions.into_iter().for_each(|f| {
+            engine.process_transaction(f);
+        });
+
+        let acc_1 = engine.accounts().get(&1).unwrap();
+        assert_eq!(acc_1.available, Amount::ZERO);
+        assert_eq!(acc_1.held, Amount::new(3, 0));
+    }
+
+    #[test]
+    fn journal_should_recreate_balance_before_chargeback() {
+        let transactions = read_transactions(
+            r#"
+    type,client,tx,amount
+    deposit,1,1,3.0
+    deposit,1,2,2.0
+    withdrawal,1,3,10.0
+    dispute,1,1,
+    chargeback,1,1,
+    "#,
+        );
+        let mut engine = Engine::<TransactionStore, AccountStore>::default();
+        engine.enable_journal();
+
+        transactions.into_iter().for_each(|f| {
+            engine.process_transaction(f);
+        });
+
+        let journal = engine.journal().unwrap();
+        assert_eq!(journal.entries().len(), 5);
+
+        let chargeback = journal
+            .entries_for_tx(1)
+            .find(|entry| entry.kind == TransactionKind::Chargeback { amount: None })
+            .unwrap();
+
+        let before = journal.account_before(1, chargeback.sequence);
+        assert_eq!(before.available(), Amount::new(2, 0));
+        assert_eq!(before.held(), Amount::new(3, 0));
+        assert!(!before.is_locked());
+
+        let after = journal.account_at(1, chargeback.sequence);
+        assert_eq!(&after, engine.accounts().get(&1).unwrap());
+    }
+
+    #[test_case(
+    r#"
+    type,client,tx,amount
+    deposit,1,1,3.0
+    deposit,1,2,2.0
+    "#,
+    1,
+    ProcessOutcome::Applied,
+    Account { id: 1, available: Amount::new(2,0), held: Amount::ZERO, total: Amount::new(2,0), status: AccountStatus::Active, credit_line: None, lock: None } ; "revert_deposit")]
+    #[test_case(
+    r#"
+    type,client,tx,amount
+    deposit,1,1,3.0
+    deposit,1,2,2.0
+    dispute,1,1,
+    chargeback,1,1,
+    "#,
+    1,
+    ProcessOutcome::Applied,
+    Account { id: 1, available: Amount::new(2,0), held: Amount::ZERO, total: Amount::new(2,0), status: AccountStatus::Active, credit_line: None, lock: None } ; "revert_chargeback_should_unlock_acc")]
+    #[test_case(
+    r#"
+    type,client,tx,amount
+    deposit,1,1,3.0
+    withdrawal,1,2,2.0
+    "#,
+    1,
+    ProcessOutcome::Rejected(AccountError::InsufficientFunds(Amount::ONE)),
+    Account { id: 1, available: Amount::new(1,0), held: Amount::ZERO, total: Amount::new(1,0), status: AccountStatus::Active, credit_line: None, lock: None } ; "revert_spent_deposit")]
+    #[test_case(
+    r#"
+    type,client,tx,amount
+    deposit,1,1,10.0
+    dispute,1,1,
+    resolve,1,1,
+    withdrawal,1,2,10.0
+    "#,
+    1,
+    ProcessOutcome::Rejected(AccountError::InsufficientFunds(Amount::ZERO)),
+    Account { id: 1, available: Amount::ZERO, held: Amount::ZERO, total: Amount::ZERO, status: AccountStatus::Active, credit_line: None, lock: None } ; "revert_spent_deposit_after_resolved_dispute")]
+    #[test_case(
+    r#"
+    type,client,tx,amount
+    deposit,1,1,3.0
+    "#,
+    2,
+    ProcessOutcome::Ignored(TransactionError::UnknownTransaction),
+    Account { id: 1, available: Amount::new(3,0), held: Amount::ZERO, total: Amount::new(3,0), status: AccountStatus::Active, credit_line: None, lock: None } ; "revert_unknown_transaction")]
+    fn revert(
+        input_data: &str,
+        tx_id: u32,
+        expected_outcome: ProcessOutcome,
+        expected_acc: Account,
+    ) {
+        let transactions = read_transactions(input_data);
+        let mut engine = Engine::<TransactionStore, AccountStore>::default();
+
+        transactions.into_iter().for_each(|f| {
+            engine.process_transaction(f);
+        });
+
+        assert_eq!(engine.revert(tx_id), expected_outcome);
+        assert_eq!(engine.accounts().get(&1), Some(&expected_acc));
+    }
+
+    #[test]
+    fn reverted_transaction_should_not_be_disputed() {
+        let transactions = read_transactions(
+            r#"
+    type,client,tx,amount
+    deposit,1,1,3.0
+    "#,
+        );
+        let mut engine = Engine::<TransactionStore, AccountStore>::default();
+        engine.enable_journal();
+
+        transactions.into_iter().for_each(|f| {
+            engine.process_transaction(f);
+        });
+
+        assert_eq!(engine.revert(1), ProcessOutcome::Applied);
+        assert_eq!(
+            engine.process_transaction(Transaction::new(
+                TransactionKind::Dispute { amount: None },
+                1,
+                1
+            )),
+            ProcessOutcome::Ignored(TransactionError::InvalidTransition(TransitionError {
+                from: TransactionState::Reverted,
+                to: TransactionState::Disputed
+            }))
+        );
+
+        let journal = engine.journal().unwrap();
+        let revert = journal
+            .entries_for_tx(1)
+            .find(|entry| entry.kind == TransactionKind::Revert)
+            .unwrap();
+        assert_eq!(journal.account_at(1, revert.sequence).total(), Amount::ZERO);
+    }
+
+    #[test]
+    fn restored_engine_should_continue_processing() {
+        let transactions = read_transactions(
+            r#"
+    type,client,tx,amount
+    deposit,1,1,3.0
+    deposit,2,2,2.0
+    dispute,1,1,
+    resolve,1,1,
+    dispute,2,2,
+    chargeback,2,2,
+    "#,
+        );
+        let mut resumed = Engine::<CompactTransactionStore, AccountStore>::default();
+        let mut uninterrupted = Engine::<CompactTransactionStore, AccountStore>::default();
+
+        for (row, transaction) in transactions.into_iter().enumerate() {
+            uninterrupted.process_transaction(transaction.clone());
+
+            if row == 2 {
+                let snapshot = resumed.snapshot().unwrap();
+                resumed = Engine::default();
+                resumed.restore(snapshot).unwrap();
+            }
+            resumed.process_transaction(transaction);
+        }
+
+        assert_eq!(resumed.accounts(), uninterrupted.accounts());
+        assert_eq!(
+            resumed.process_transaction(Transaction::new(
+                TransactionKind::Dispute { amount: None },
+                1,
+                1
+            )),
+            ProcessOutcome::Ignored(TransactionError::InvalidTransition(TransitionError {
+                from: TransactionState::Resolved,
+                to: TransactionState::Disputed
+            }))
+        );
+    }
+
+    #[test_case(
+    r#"
+    type,client,tx,amount,to
+    deposit,1,1,5.0,
+    transfer,1,2,2.0,2
+    "#,
+    ProcessOutcome::Applied,
+    Account { id: 1, available: Amount::new(3,0), held: Amount::ZERO, total: Amount::new(3,0), status: AccountStatus::Active, credit_line: None, lock: None },
+    Account { id: 2, available: Amount::new(2,0), held: Amount::ZERO, total: Amount::new(2,0), status: AccountStatus::Active, credit_line: None, lock: None } ; "transfer_should_move_funds")]
+    #[test_case(
+    r#"
+    type,client,tx,amount,to
+    deposit,1,1,1.0,
+    transfer,1,2,2.0,2
+    "#,
+    ProcessOutcome::Rejected(AccountError::InsufficientFunds(Amount::ONE)),
+    Account { id: 1, available: Amount::new(1,0), held: Amount::ZERO, total: Amount::new(1,0), status: AccountStatus::Active, credit_line: None, lock: None },
+    Account { id: 2, available: Amount::ZERO, held: Amount::ZERO, total: Amount::ZERO, status: AccountStatus::Active, credit_line: None, lock: None } ; "transfer_without_funds_should_change_nothing")]
+    #[test_case(
+    r#"
+    type,client,tx,amount,to
+    deposit,1,1,5.0,
+    deposit,2,2,1.0,
+    dispute,2,2,,
+    chargeback,2,2,,
+    transfer,1,3,2.0,2
+    "#,
+    ProcessOutcome::Rejected(AccountError::Locked),
+    Account { id: 1, available: Amount::new(5,0), held: Amount::ZERO, total: Amount::new(5,0), status: AccountStatus::Active, credit_line: None, lock: None },
+    Account { id: 2, available: Amount::ZERO, held: Amount::ZERO, total: Amount::ZERO, status: AccountStatus::Locked, credit_line: None, lock: Some(Lock { tx_id: 2, previous: AccountStatus::Active }) } ; "transfer_to_locked_account_should_change_nothing")]
+    #[test_case(
+    r#"
+    type,client,tx,amount,to
+    deposit,1,1,5.0,
+    transfer,1,2,2.0,2
+    dispute,1,2,,
+    "#,
+    ProcessOutcome::Applied,
+    Account { id: 1, available: Amount::new(3,0), held: Amount::ZERO, total: Amount::new(3,0), status: AccountStatus::Active, credit_line: None, lock: None },
+    Account { id: 2, available: Amount::ZERO, held: Amount::new(2,0), total: Amount::new(2,0), status: AccountStatus::Active, credit_line: None, lock: None } ; "disputed_transfer_should_hold_destination_funds")]
+    #[test_case(
+    r#"
+    type,client,tx,amount,to
+    deposit,1,1,5.0,
+    transfer,1,2,2.0,2
+    dispute,1,2,,
+    chargeback,1,2,,
+    "#,
+    ProcessOutcome::Applied,
+    Account { id: 1, available: Amount::new(5,0), held: Amount::ZERO, total: Amount::new(5,0), status: AccountStatus::Active, credit_line: None, lock: None },
+    Account { id: 2, available: Amount::ZERO, held: Amount::ZERO, total: Amount::ZERO, status: AccountStatus::Locked, credit_line: None, lock: Some(Lock { tx_id: 2, previous: AccountStatus::Active }) } ; "charged_back_transfer_should_return_funds")]
+    fn transfer(
+        input_data: &str,
+        expected_outcome: ProcessOutcome,
+        expected_source: Account,
+        expected_destination: Account,
+    ) {
+        let transactions = read_transactions(input_data);
+        let mut engine = Engine::<CompactTransactionStore, AccountStore>::default();
+
+        let outcome = transactions
+            .into_iter()
+            .map(|f| engine.process_transaction(f))
+            .last();
+
+        assert_eq!(outcome, Some(expected_outcome));
+        assert_eq!(engine.accounts().get(&1), Some(&expected_source));
+        assert_eq!(engine.accounts().get(&2), Some(&expected_destination));
+    }
+
+    #[test]
+    fn journal_should_recreate_both_sides_of_transfer() {
+        let transactions = read_transactions(
+            r#"
+    type,client,tx,amount,to
+    deposit,1,1,5.0,
+    transfer,1,2,2.0,2
+    dispute,1,2,,
+    "#,
+        );
+        let mut engine = Engine::<CompactTransactionStore, AccountStore>::default();
+        engine.enable_journal();
+
+        transactions.into_iter().for_each(|f| {
+            engine.process_transaction(f);
+        });
+
+        let journal = engine.journal().unwrap();
+        let last = journal.entries().last().unwrap().sequence;
+
+        assert_eq!(journal.entries_for_tx(2).count(), 4);
+        assert_eq!(
+            &journal.account_at(1, last),
+            engine.accounts().get(&1).unwrap()
+        );
+        assert_eq!(
+            &journal.account_at(2, last),
+            engine.accounts().get(&2).unwrap()
+        );
+    }
+
+    #[test_case(
+    r#"
+    type,client,tx,amount
+    deposit,1,1,10.0
+    dispute,1,1,3.0
+    dispute,1,1,4.0
+    "#,
+    ProcessOutcome::Applied,
+    Account { id: 1, available: Amount::new(3,0), held: Amount::new(7,0), total: Amount::TEN, status: AccountStatus::Active, credit_line: None, lock: None } ; "partial_disputes_should_hold_their_sum")]
+    #[test_case(
+    r#"
+    type,client,tx,amount
+    deposit,1,1,10.0
+    dispute,1,1,6.0
+    dispute,1,1,5.0
+    "#,
+    ProcessOutcome::Ignored(TransactionError::AmountExceeded(Amount::new(4,0))),
+    Account { id: 1, available: Amount::new(4,0), held: Amount::new(6,0), total: Amount::TEN, status: AccountStatus::Active, credit_line: None, lock: None } ; "disputes_should_not_exceed_original_amount")]
+    #[test_case(
+    r#"
+    type,client,tx,amount
+    deposit,1,1,10.0
+    dispute,1,1,6.0
+    resolve,1,1,2.0
+    dispute,1,1,4.0
+    "#,
+    ProcessOutcome::Applied,
+    Account { id: 1, available: Amount::TWO, held: Amount::new(8,0), total: Amount::TEN, status: AccountStatus::Active, credit_line: None, lock: None } ; "partially_resolved_transaction_should_remain_disputed")]
+    #[test_case(
+    r#"
+    type,client,tx,amount
+    deposit,1,1,10.0
+    dispute,1,1,6.0
+    resolve,1,1,2.0
+    dispute,1,1,
+    "#,
+    ProcessOutcome::Ignored(TransactionError::AmountExceeded(Amount::new(4,0))),
+    Account { id: 1, available: Amount::new(6,0), held: Amount::new(4,0), total: Amount::TEN, status: AccountStatus::Active, credit_line: None, lock: None } ; "dispute_without_amount_should_dispute_whole_transaction")]
+    #[test_case(
+    r#"
+    type,client,tx,amount
+    deposit,1,1,10.0
+    dispute,1,1,3.0
+    resolve,1,1,3.0
+    dispute,1,1,4.0
+    "#,
+    ProcessOutcome::Applied,
+    Account { id: 1, available: Amount::new(6,0), held: Amount::new(4,0), total: Amount::TEN, status: AccountStatus::Active, credit_line: None, lock: None } ; "resolved_transaction_should_be_disputed_for_the_rest")]
+    #[test_case(
+    r#"
+    type,client,tx,amount
+    deposit,1,1,10.0
+    dispute,1,1,3.0
+    resolve,1,1,3.0
+    dispute,1,1,8.0
+    "#,
+    ProcessOutcome::Ignored(TransactionError::AmountExceeded(Amount::new(7,0))),
+    Account { id: 1, available: Amount::TEN, held: Amount::ZERO, total: Amount::TEN, status: AccountStatus::Active, credit_line: None, lock: None } ; "dispute_of_resolved_transaction_should_not_exceed_the_rest")]
+    #[test_case(
+    r#"
+    type,client,tx,amount
+    deposit,1,1,10.0
+    dispute,1,1,6.0
+    resolve,1,1,7.0
+    "#,
+    ProcessOutcome::Ignored(TransactionError::AmountExceeded(Amount::new(6,0))),
+    Account { id: 1, available: Amount::new(4,0), held: Amount::new(6,0), total: Amount::TEN, status: AccountStatus::Active, credit_line: None, lock: None } ; "resolve_should_not_exceed_disputed_amount")]
+    #[test_case(
+    r#"
+    type,client,tx,amount
+    deposit,1,1,10.0
+    dispute,1,1,6.0
+    chargeback,1,1,2.0
+    "#,
+    ProcessOutcome::Applied,
+    Account { id: 1, available: Amount::new(4,0), held: Amount::new(4,0), total: Amount::new(8,0), status: AccountStatus::Locked, credit_line: None, lock: Some(Lock { tx_id: 1, previous: AccountStatus::Active }) } ; "partial_chargeback_should_withdraw_part_of_held_funds")]
+    #[test_case(
+    r#"
+    type,client,tx,amount
+    deposit,1,1,10.0
+    dispute,1,1,6.0
+    chargeback,1,1,2.0
+    unlock,1,2,
+    chargeback,1,1,
+    "#,
+    ProcessOutcome::Applied,
+    Account { id: 1, available: Amount::new(4,0), held: Amount::ZERO, total: Amount::new(4,0), status: AccountStatus::Locked, credit_line: None, lock: Some(Lock { tx_id: 1, previous: AccountStatus::Active }) } ; "rest_of_partial_chargeback_should_be_charged_back_after_unlock")]
+    #[test_case(
+    r#"
+    type,client,tx,amount
+    deposit,1,1,10.0
+    dispute,1,1,6.0
+    chargeback,1,1,2.0
+    unlock,1,2,
+    resolve,1,1,
+    close,1,3,
+    "#,
+    ProcessOutcome::Applied,
+    Account { id: 1, available: Amount::ZERO, held: Amount::ZERO, total: Amount::ZERO, status: AccountStatus::Closed, credit_line: None, lock: None } ; "rest_of_partial_chargeback_should_be_resolved_after_unlock")]
+    fn partial_dispute(input_data: &str, expected_outcome: ProcessOutcome, expected_acc: Account) {
+        let transactions = read_transactions(input_data);
+        let mut engine = Engine::<CompactTransactionStore, AccountStore>::default();
+
+        let outcome = transactions
+            .into_iter()
+            .map(|f| engine.process_transaction(f))
+            .last();
+
+        assert_eq!(outcome, Some(expected_outcome));
+        assert_eq!(engine.accounts().get(&1), Some(&expected_acc));
+    }
+
+    #[test_case(
+    r#"
+    type,client,tx,amount
+    deposit,1,1,10.0
+    authorize,1,2,6.0
+    "#,
+    ProcessOutcome::Applied,
+    Account { id: 1, available: Amount::new(4,0), held: Amount::new(6,0), total: Amount::TEN, status: AccountStatus::Active, credit_line: None, lock: None } ; "authorization_should_hold_funds")]
+    #[test_case(
+    r#"
+    type,client,tx,amount
+    deposit,1,1,10.0
+    authorize,1,2,11.0
+    "#,
+    ProcessOutcome::Rejected(AccountError::InsufficientFunds(Amount::TEN)),
+    Account { id: 1, available: Amount::TEN, held: Amount::ZERO, total: Amount::TEN, status: AccountStatus::Active, credit_line: None, lock: None } ; "authorization_should_not_exceed_available_funds")]
+    #[test_case(
+    r#"
+    type,client,tx,amount
+    deposit,1,1,10.0
+    authorize,1,2,6.0
+    capture,1,2,4.0
+    void,1,2,
+    "#,
+    ProcessOutcome::Applied,
+    Account { id: 1, available: Amount::new(6,0), held: Amount::ZERO, total: Amount::new(6,0), status: AccountStatus::Active, credit_line: None, lock: None } ; "void_should_release_funds_which_have_not_been_captured")]
+    #[test_case(
+    r#"
+    type,client,tx,amount
+    deposit,1,1,10.0
+    authorize,1,2,6.0
+    capture,1,2,
+    void,1,2,
+    "#,
+    ProcessOutcome::Ignored(TransactionError::InvalidTransition(TransitionError { from: TransactionState::Captured, to: TransactionState::Voided })),
+    Account { id: 1, available: Amount::new(4,0), held: Amount::ZERO, total: Amount::new(4,0), status: AccountStatus::Active, credit_line: None, lock: None } ; "captured_authorization_should_not_be_voided")]
+    #[test_case(
+    r#"
+    type,client,tx,amount
+    deposit,1,1,10.0
+    authorize,1,2,6.0
+    capture,1,2,7.0
+    "#,
+    ProcessOutcome::Ignored(TransactionError::CaptureExceeded(Amount::new(6,0))),
+    Account { id: 1, available: Amount::new(4,0), held: Amount::new(6,0), total: Amount::TEN, status: AccountStatus::Active, credit_line: None, lock: None } ; "capture_should_not_exceed_authorized_amount")]
+    fn authorization(input_data: &str, expected_outcome: ProcessOutcome, expected_acc: Account) {
+        let transactions = read_transactions(input_data);
+        let mut engine = Engine::<CompactTransactionStore, AccountStore>::default();
+
+        let outcome = transactions
+            .into_iter()
+            .map(|f| engine.process_transaction(f))
+            .last();
+
+        assert_eq!(outcome, Some(expected_outcome));
+        assert_eq!(engine.accounts().get(&1), Some(&expected_acc));
+    }
+
+    #[test]
+    fn revert_should_undo_partial_disputes() {
+        let transactions = read_transactions(
+            r#"
+    type,client,tx,amount
+    deposit,1,1,10.0
+    deposit,1,2,1.0
+    dispute,1,1,6.0
+    resolve,1,1,2.0
+    chargeback,1,1,3.0
+    "#,
+        );
+        let mut engine = Engine::<CompactTransactionStore, AccountStore>::default();
+
+        transactions.into_iter().for_each(|f| {
+            engine.process_transaction(f);
+        });
+
+        assert_eq!(engine.revert(1), ProcessOutcome::Applied);
+        assert_eq!(
+            engine.accounts().get(&1),
+            Some(&Account {
+                id: 1,
+                available: Amount::ONE,
+                held: Amount::ZERO,
+                total: Amount::ONE,
+                status: AccountStatus::Active,
+                credit_line: None,
+                lock: None,
+            })
+        );
+    }
+
+    // Engine which releases holds after 7 days and accepts disputes within 120 days
+    fn expiring_engine() -> Engine<CompactTransactionStore, AccountStore> {
+        let mut engine = Engine::<CompactTransactionStore, AccountStore>::default();
+        engine.enable_journal();
+        engine.set_expiry_policy(ExpiryPolicy {
+            dispute_window: Some(120 * DAY),
+            hold_expiry: Some(7 * DAY),
+        });
+        engine
+    }
+
+    #[test_case(
+    r#"
+    type,client,tx,amount,timestamp
+    deposit,1,1,10.0,0
+    authorize,1,2,6.0,86400
+    deposit,1,3,1.0,691200
+    "#,
+    Account { id: 1, available: Amount::new(11,0), held: Amount::ZERO, total: Amount::new(11,0), status: AccountStatus::Active, credit_line: None, lock: None } ; "hold_should_be_released_once_it_expires")]
+    #[test_case(
+    r#"
+    type,client,tx,amount,timestamp
+    deposit,1,1,10.0,0
+    authorize,1,2,6.0,86400
+    deposit,1,3,1.0,604800
+    "#,
+    Account { id: 1, available: Amount::new(5,0), held: Amount::new(6,0), total: Amount::new(11,0), status: AccountStatus::Active, credit_line: None, lock: None } ; "hold_should_be_kept_until_it_expires")]
+    #[test_case(
+    r#"
+    type,client,tx,amount,timestamp
+    deposit,1,1,10.0,0
+    authorize,1,2,6.0,86400
+    capture,1,2,4.0,172800
+    deposit,1,3,1.0,691200
+    "#,
+    Account { id: 1, available: Amount::new(7,0), held: Amount::ZERO, total: Amount::new(7,0), status: AccountStatus::Active, credit_line: None, lock: None } ; "part_of_hold_which_has_not_been_captured_should_be_released")]
+    #[test_case(
+    r#"
+    type,client,tx,amount,timestamp
+    deposit,1,1,10.0,
+    authorize,1,2,6.0,
+    deposit,1,3,1.0,691200
+    "#,
+    Account { id: 1, available: Amount::new(5,0), held: Amount::new(6,0), total: Amount::new(11,0), status: AccountStatus::Active, credit_line: None, lock: None } ; "hold_without_timestamp_should_never_expire")]
+    fn hold_expiry(input_data: &str, expected_acc: Account) {
+        let mut engine = expiring_engine();
+
+        for transaction in read_transactions(input_data) {
+            engine.process_transaction(transaction);
+        }
+
+        assert_eq!(engine.accounts().get(&1), Some(&expected_acc));
+    }
+
+    #[test]
+    fn expired_hold_should_be_recorded_in_journal() {
+        let mut engine = expiring_engine();
+        let amount = PositiveAmount::new(Amount::TEN).unwrap();
+
+        engine.process_transaction(
+            Transaction::new(TransactionKind::Deposit { amount }, 1, 1).with_timestamp(0),
+        );
+        engine.process_transaction(
+            Transaction::new(TransactionKind::Authorize { amount }, 1, 2).with_timestamp(DAY),
+        );
+        engine.process_transaction(
+            Transaction::new(TransactionKind::Deposit { amount }, 2, 3).with_timestamp(8 * DAY),
+        );
+
+        let journal = engine.journal().unwrap();
+        let expiry = journal
+            .entries_for_tx(2)
+            .find(|entry| entry.kind == TransactionKind::Expire)
+            .unwrap();
+
+        assert_eq!(expiry.client_id, 1);
+        assert_eq!(expiry.delta, AccountDelta::void(Amount::TEN));
+        assert_eq!(expiry.outcome, ProcessOutcome::Applied);
+        // Expiry precedes transaction which moved clock past deadline
+        assert_eq!(
+            journal.entries_for_tx(3).next().unwrap().sequence,
+            expiry.sequence + 1
+        );
+        assert_eq!(
+            engine.transactions.get(&2).unwrap().unwrap().state,
+            TransactionState::Voided
+        );
+        assert_eq!(engine.clock(), Some(8 * DAY));
+    }
+
+    #[test_case(
+    r#"
+    type,client,tx,amount,timestamp
+    deposit,1,1,10.0,0
+    dispute,1,1,,10368000
+    "#,
+    ProcessOutcome::Applied ; "dispute_within_window_should_be_applied")]
+    #[test_case(
+    r#"
+    type,client,tx,amount,timestamp
+    deposit,1,1,10.0,0
+    dispute,1,1,,10368001
+    "#,
+    ProcessOutcome::Ignored(TransactionError::DisputeWindowExpired) ; "dispute_after_window_should_be_ignored")]
+    #[test_case(
+    r#"
+    type,client,tx,amount,timestamp
+    deposit,1,1,10.0,0
+    deposit,2,2,10.0,10368001
+    dispute,1,1,,
+    "#,
+    ProcessOutcome::Ignored(TransactionError::DisputeWindowExpired) ; "dispute_without_timestamp_should_happen_at_latest_time")]
+    #[test_case(
+    r#"
+    type,client,tx,amount,timestamp
+    deposit,1,1,10.0,
+    dispute,1,1,,10368001
+    "#,
+    ProcessOutcome::Applied ; "transaction_without_timestamp_should_be_always_disputable")]
+    fn dispute_window(input_data: &str, expected_outcome: ProcessOutcome) {
+        let mut engine = expiring_engine();
+
+        let outcome = read_transactions(input_data)
+            .into_iter()
+            .map(|f| engine.process_transaction(f))
+            .last();
+
+        assert_eq!(outcome, Some(expected_outcome));
+    }
+
+    #[test]
+    fn restored_hold_should_expire() -> Result<(), StoreError> {
+        let mut engine = expiring_engine();
+        let amount = PositiveAmount::new(Amount::TEN).unwrap();
+
+        engine.process_transaction(
+            Transaction::new(TransactionKind::Deposit { amount }, 1, 1).with_timestamp(0),
+        );
+        engine.process_transaction(
+            Transaction::new(TransactionKind::Authorize { amount }, 1, 2).with_timestamp(DAY),
+        );
+
+        let mut restored = expiring_engine();
+        restored.restore(engine.snapshot()?)?;
+
+        assert_eq!(restored.clock(), Some(DAY));
+        assert_eq!(restored.advance_clock(8 * DAY), Ok(()));
+        assert_eq!(restored.accounts()[&1].available(), Amount::TEN);
+
+        Ok(())
+    }
+
+    #[test_case(
+    r#"
+    type,client,tx,amount
+    deposit,1,1,10.0
+    freeze,1,2,
+    deposit,1,3,1.0
+    "#,
+    ProcessOutcome::Applied,
+    Account { id: 1, available: Amount::new(11,0), held: Amount::ZERO, total: Amount::new(11,0), status: AccountStatus::Frozen, credit_line: None, lock: None } ; "frozen_account_should_accept_deposit")]
+    #[test_case(
+    r#"
+    type,client,tx,amount
+    deposit,1,1,10.0
+    freeze,1,2,
+    withdrawal,1,3,1.0
+    "#,
+    ProcessOutcome::Rejected(AccountError::Frozen),
+    Account { id: 1, available: Amount::TEN, held: Amount::ZERO, total: Amount::TEN, status: AccountStatus::Frozen, credit_line: None, lock: None } ; "frozen_account_should_refuse_withdrawal")]
+    #[test_case(
+    r#"
+    type,client,tx,amount,to
+    deposit,1,1,10.0,
+    freeze,1,2,,
+    transfer,1,3,1.0,2
+    "#,
+    ProcessOutcome::Rejected(AccountError::Frozen),
+    Account { id: 1, available: Amount::TEN, held: Amount::ZERO, total: Amount::TEN, status: AccountStatus::Frozen, credit_line: None, lock: None } ; "frozen_account_should_refuse_outgoing_transfer")]
+    #[test_case(
+    r#"
+    type,client,tx,amount,to
+    deposit,2,1,10.0,
+    deposit,1,4,1.0,
+    freeze,1,2,,
+    transfer,2,3,1.0,1
+    "#,
+    ProcessOutcome::Applied,
+    Account { id: 1, available: Amount::TWO, held: Amount::ZERO, total: Amount::TWO, status: AccountStatus::Frozen, credit_line: None, lock: None } ; "frozen_account_should_accept_incoming_transfer")]
+    #[test_case(
+    r#"
+    type,client,tx,amount
+    deposit,1,1,10.0
+    freeze,1,2,
+    unlock,1,3,
+    withdrawal,1,4,1.0
+    "#,
+    ProcessOutcome::Applied,
+    Account { id: 1, available: Amount::new(9,0), held: Amount::ZERO, total: Amount::new(9,0), status: AccountStatus::Active, credit_line: None, lock: None } ; "unfrozen_account_should_accept_withdrawal")]
+    #[test_case(
+    r#"
+    type,client,tx,amount
+    deposit,1,1,10.0
+    deposit,1,2,2.0
+    dispute,1,2,
+    chargeback,1,2,
+    unlock,1,3,
+    deposit,1,4,1.0
+    "#,
+    ProcessOutcome::Applied,
+    Account { id: 1, available: Amount::new(11,0), held: Amount::ZERO, total: Amount::new(11,0), status: AccountStatus::Active, credit_line: None, lock: None } ; "unlocked_account_should_accept_deposit")]
+    #[test_case(
+    r#"
+    type,client,tx,amount
+    deposit,1,1,10.0
+    close,1,2,
+    deposit,1,3,1.0
+    "#,
+    ProcessOutcome::Rejected(AccountError::Closed),
+    Account { id: 1, available: Amount::ZERO, held: Amount::ZERO, total: Amount::ZERO, status: AccountStatus::Closed, credit_line: None, lock: None } ; "closed_account_should_refuse_deposit")]
+    #[test_case(
+    r#"
+    type,client,tx,amount
+    deposit,1,1,10.0
+    dispute,1,1,2.0
+    close,1,2,
+    "#,
+    ProcessOutcome::Rejected(AccountError::NotSettled),
+    Account { id: 1, available: Amount::new(8,0), held: Amount::TWO, total: Amount::TEN, status: AccountStatus::Active, credit_line: None, lock: None } ; "account_with_held_funds_should_not_be_closed")]
+    #[test_case(
+    r#"
+    type,client,tx,amount
+    deposit,1,1,10.0
+    close,1,2,
+    unlock,1,3,
+    "#,
+    ProcessOutcome::Rejected(AccountError::Closed),
+    Account { id: 1, available: Amount::ZERO, held: Amount::ZERO, total: Amount::ZERO, status: AccountStatus::Closed, credit_line: None, lock: None } ; "closed_account_should_not_be_reopened")]
+    fn account_status(input_data: &str, expected_outcome: ProcessOutcome, expected_acc: Account) {
+        let transactions = read_transactions(input_data);
+        let mut engine = Engine::<CompactTransactionStore, AccountStore>::default();
+
+        let outcome = transactions
+            .into_iter()
+            .map(|f| engine.process_transaction(f))
+            .last();
+
+        assert_eq!(outcome, Some(expected_outcome));
+        assert_eq!(engine.accounts().get(&1), Some(&expected_acc));
+    }
+
+    #[test]
+    fn administrative_change_of_unknown_client_should_not_open_account() {
+        let mut engine = Engine::<CompactTransactionStore, AccountStore>::default();
+
+        let outcome = engine.process_transaction(Transaction::new(TransactionKind::Freeze, 1, 1));
+
+        assert_eq!(
+            outcome,
+            ProcessOutcome::Rejected(AccountError::UnknownAccount)
+        );
+        assert!(engine.accounts().get(&1).is_none());
+
+        // Id of refused change is kept like id of any other refused transaction
+        let deposit = Transaction::new(
+            TransactionKind::Deposit {
+                amount: PositiveAmount::new(Amount::ONE).unwrap(),
+            },
+            1,
+            1,
+        );
+        assert_eq!(
+            engine.process_transaction(deposit),
+            ProcessOutcome::Duplicate
+        );
+    }
+
+    #[test_case(
+    r#"
+    type,client,tx,amount
+    deposit,1,1,10.0
+    dispute,1,1,
+    chargeback,1,1,
+    unlock,1,2,
+    freeze,1,3,
+    "#,
+    ProcessOutcome::Applied, AccountStatus::Frozen ; "freeze_after_unlock_should_remain")]
+    #[test_case(
+    r#"
+    type,client,tx,amount
+    deposit,1,1,10.0
+    dispute,1,1,
+    freeze,1,2,
+    chargeback,1,1,
+    "#,
+    ProcessOutcome::Applied, AccountStatus::Frozen ; "freeze_before_chargeback_should_be_restored")]
+    #[test_case(
+    r#"
+    type,client,tx,amount
+    deposit,1,1,10.0
+    deposit,1,2,5.0
+    dispute,1,1,
+    chargeback,1,1,
+    unlock,1,3,
+    dispute,1,2,
+    chargeback,1,2,
+    "#,
+    ProcessOutcome::Rejected(AccountError::Locked), AccountStatus::Locked ; "lock_of_other_chargeback_should_remain")]
+    fn revert_of_chargeback_after_administrative_change(
+        input_data: &str,
+        expected_outcome: ProcessOutcome,
+        expected_status: AccountStatus,
+    ) {
+        let mut engine = Engine::<CompactTransactionStore, AccountStore>::default();
+        engine.enable_journal();
+
+        for transaction in read_transactions(input_data) {
+            engine.process_transaction(transaction);
+        }
+
+        assert_eq!(engine.revert(1), expected_outcome);
+
+        let account = engine.accounts().get(&1).unwrap();
+        assert_eq!(account.status(), expected_status);
+
+        let journal = engine.journal().unwrap();
+        let last = journal.entries().last().unwrap().sequence;
+        assert_eq!(&journal.account_at(1, last), account);
+    }
+
+    #[test]
+    fn payout_of_closed_account_should_be_recorded_in_journal() {
+        let mut engine = Engine::<CompactTransactionStore, AccountStore>::default();
+        engine.enable_journal();
+
+        for transaction in read_transactions(
+            r#"
+    type,client,tx,amount
+    deposit,1,1,10.0
+    withdrawal,1,2,4.0
+    close,1,3,
+    "#,
+        ) {
+            engine.process_transaction(transaction);
+        }
+
+        let journal = engine.journal().unwrap();
+        let close = journal.entries_for_tx(3).next().unwrap();
+
+        assert_eq!(close.delta, AccountDelta::close(Amount::new(6, 0)));
+        assert_eq!(
+            &journal.account_at(1, close.sequence),
+            engine.accounts().get(&1).unwrap()
+        );
+    }
+
+    fn credit_lines(withdrawals: bool) -> HashMap<ClientID, CreditLine> {
+        HashMap::from([(
+            1,
+            CreditLine {
+                limit: Amount::TEN,
+                withdrawals,
+            },
+        )])
+    }
+
+    #[test_case(
+    r#"
+    type,client,tx,amount
+    deposit,1,1,2.0
+    withdrawal,1,2,5.0
+    "#,
+    true,
+    ProcessOutcome::Applied,
+    Amount::new(-3,0) ; "withdrawal_should_use_credit_line")]
+    #[test_case(
+    r#"
+    type,client,tx,amount
+    deposit,1,1,2.0
+    withdrawal,1,2,13.0
+    "#,
+    true,
+    ProcessOutcome::Rejected(AccountError::InsufficientFunds(Amount::new(12,0))),
+    Amount::TWO ; "withdrawal_over_credit_line_should_be_rejected")]
+    #[test_case(
+    r#"
+    type,client,tx,amount
+    deposit,1,1,2.0
+    withdrawal,1,2,5.0
+    "#,
+    false,
+    ProcessOutcome::Rejected(AccountError::InsufficientFunds(Amount::TWO)),
+    Amount::TWO ; "withdrawal_should_not_use_credit_line_reserved_for_authorizations")]
+    #[test_case(
+    r#"
+    type,client,tx,amount
+    deposit,1,1,2.0
+    authorize,1,2,5.0
+    "#,
+    false,
+    ProcessOutcome::Applied,
+    Amount::new(-3,0) ; "authorization_should_use_credit_line")]
+    #[test_case(
+    r#"
+    type,client,tx,amount,to
+    deposit,1,1,2.0,
+    transfer,1,2,5.0,2
+    "#,
+    true,
+    ProcessOutcome::Applied,
+    Amount::new(-3,0) ; "transfer_should_use_credit_line")]
+    #[test_case(
+    r#"
+    type,client,tx,amount
+    withdrawal,1,1,5.0
+    close,1,2,
+    "#,
+    true,
+    ProcessOutcome::Rejected(AccountError::NotSettled),
+    Amount::new(-5,0) ; "account_in_overdraft_should_not_be_closed")]
+    fn credit_line(
+        input_data: &str,
+        withdrawals: bool,
+        expected_outcome: ProcessOutcome,
+        expected_available: Amount,
+    ) {
+        let transactions = read_transactions(input_data);
+        let mut engine = Engine::<CompactTransactionStore, AccountStore>::default();
+        engine.set_credit_lines(credit_lines(withdrawals));
+
+        let outcome = transactions
+            .into_iter()
+            .map(|f| engine.process_transaction(f))
+            .last();
+
+        assert_eq!(outcome, Some(expected_outcome));
+        assert_eq!(
+            engine.accounts().get(&1).map(Account::available),
+            Some(expected_available)
+        );
+    }
+
+    #[test]
+    fn restored_account_should_keep_credit_line() {
+        let mut engine = Engine::<CompactTransactionStore, AccountStore>::default();
+        engine.set_credit_lines(credit_lines(true));
+        engine.process_transaction(Transaction::new(
+            TransactionKind::Withdrawal {
+                amount: PositiveAmount::new(Amount::new(5, 0)).unwrap(),
+            },
+            1,
+            1,
+        ));
+
+        let mut restored = Engine::<CompactTransactionStore, AccountStore>::default();
+        restored.set_credit_lines(credit_lines(true));
+        restored.restore(engine.snapshot().unwrap()).unwrap();
+
+        assert_eq!(restored.accounts(), engine.accounts());
+        assert_eq!(
+            restored
+                .accounts()
+                .get(&1)
+                .unwrap()
+                .headroom(&AccountDelta::none()),
+            Amount::new(5, 0)
+        );
+    }
+
+    #[test]
+    fn journal_should_replay_overdraft() {
+        let mut engine = Engine::<CompactTransactionStore, AccountStore>::default();
+        engine.enable_journal();
+        engine.set_credit_lines(credit_lines(true));
+
+        for transaction in read_transactions(
+            r#"
+    type,client,tx,amount
+    deposit,1,1,2.0
+    withdrawal,1,2,5.0
+    deposit,1,3,1.0
+    "#,
+        ) {
+            engine.process_transaction(transaction);
+        }
+
+        let journal = engine.journal().unwrap();
+
+        assert_eq!(journal.account_at(1, 3).available(), Amount::new(-2, 0));
     }
 }
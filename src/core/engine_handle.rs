@@ -0,0 +1,144 @@
+use std::thread;
+
+use tokio::sync::{mpsc, oneshot};
+
+use super::{
+    account_store,
+    engine::{Engine, ProcessOutcome},
+    transaction::Transaction,
+    transaction_store,
+};
+
+#[derive(thiserror::Error, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HandleError {
+    #[error("Engine has stopped")]
+    Stopped,
+}
+
+type Request = (Transaction, oneshot::Sender<ProcessOutcome>);
+
+/// Async front-end of [`Engine`] for tokio based services
+///
+/// Engine runs on its own thread, so processing never blocks async runtime. Submitted transactions
+/// wait in bounded queue, once it is full [`EngineHandle::submit`] waits until engine catches up.
+pub struct EngineHandle<A> {
+    sender: mpsc::Sender<Request>,
+    accounts: oneshot::Receiver<A>,
+}
+
+impl<A> EngineHandle<A>
+where
+    A: account_store::Store + Send + 'static,
+{
+    /// Moves engine to its own thread, at most `capacity` transactions can wait for processing
+    pub fn spawn<T>(mut engine: Engine<T, A>, capacity: usize) -> Self
+    where
+        T: transaction_store::Store + Send + 'static,
+    {
+        let (sender, mut receiver) = mpsc::channel::<Request>(capacity.max(1));
+        let (accounts_sender, accounts) = oneshot::channel();
+
+        thread::spawn(move || {
+            // Queue is drained even after shutdown has been requested
+            while let Some((transaction, reply)) = receiver.blocking_recv() {
+                let outcome = engine.process_transaction(transaction);
+                // Caller might not wait for outcome anymore
+                let _ = reply.send(outcome);
+            }
+
+            let _ = accounts_sender.send(engine.into_accounts());
+        });
+
+        Self { sender, accounts }
+    }
+
+    /// Submits transaction and waits until it is processed
+    pub async fn submit(&self, transaction: Transaction) -> Result<ProcessOutcome, HandleError> {
+        let (reply, outcome) = oneshot::channel();
+
+        self.sender
+            .send((transaction, reply))
+            .await
+            .map_err(|_| HandleError::Stopped)?;
+
+        outcome.await.map_err(|_| HandleError::Stopped)
+    }
+
+    /// Stops accepting transactions, waits until already submitted ones are processed and returns final accounts
+    pub async fn shutdown(self) -> Result<A, HandleError> {
+        drop(self.sender);
+        self.accounts.await.map_err(|_| HandleError::Stopped)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use crate::core::{
+        account::Amount,
+        account_store::{AccountStore, Store},
+        compact_transaction_store::CompactTransactionStore,
+        engine::{Engine, ProcessOutcome},
+        tests::positive,
+        transaction::{Transaction, TransactionKind},
+    };
+
+    use super::EngineHandle;
+
+    #[tokio::test]
+    async fn submitted_transaction_should_return_outcome() {
+        let handle = EngineHandle::spawn(
+            Engine::<CompactTransactionStore, AccountStore>::default(),
+            4,
+        );
+        let amount = positive(Amount::ONE);
+
+        let deposit = handle
+            .submit(Transaction::new(TransactionKind::Deposit { amount }, 1, 1))
+            .await;
+        let duplicate = handle
+            .submit(Transaction::new(TransactionKind::Deposit { amount }, 1, 1))
+            .await;
+
+        assert_eq!(deposit, Ok(ProcessOutcome::Applied));
+        assert_eq!(duplicate, Ok(ProcessOutcome::Duplicate));
+
+        let accounts = handle.shutdown().await.unwrap();
+        assert_eq!(accounts.accounts()[&1].total(), Amount::ONE);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn shutdown_should_drain_submitted_transactions() {
+        let handle = Arc::new(EngineHandle::spawn(
+            Engine::<CompactTransactionStore, AccountStore>::default(),
+            2,
+        ));
+
+        let tasks: Vec<_> = (0..100)
+            .map(|tx_id| {
+                let handle = handle.clone();
+                tokio::spawn(async move {
+                    let amount = positive(Amount::ONE);
+                    handle
+                        .submit(Transaction::new(
+                            TransactionKind::Deposit { amount },
+                            (tx_id % 3) as u16,
+                            tx_id,
+                        ))
+                        .await
+                })
+            })
+            .collect();
+
+        for task in tasks {
+            assert_eq!(task.await.unwrap(), Ok(ProcessOutcome::Applied));
+        }
+
+        let handle = Arc::try_unwrap(handle).ok().unwrap();
+        let accounts = handle.shutdown().await.unwrap();
+
+        let total: Amount = accounts.accounts().values().map(|acc| acc.total()).sum();
+        assert_eq!(total, Amount::new(100, 0));
+    }
+}
@@ -0,0 +1,58 @@
+use std::collections::BTreeSet;
+
+use super::transaction::{Timestamp, TransactionID};
+
+/// Number of seconds in a day
+pub const DAY: Timestamp = 24 * 60 * 60;
+
+/// Time windows enforced by [`crate::Engine`], transactions without timestamp never expire
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ExpiryPolicy {
+    /// Maximal age of transaction which can be disputed, in seconds
+    pub dispute_window: Option<Timestamp>,
+    /// Age of authorization after which its remaining hold is released, in seconds
+    pub hold_expiry: Option<Timestamp>,
+}
+
+/// Authorizations ordered by time at which their holds expire
+#[derive(Debug, Default)]
+pub(crate) struct HoldDeadlines {
+    deadlines: BTreeSet<(Timestamp, TransactionID)>,
+}
+
+impl HoldDeadlines {
+    pub fn push(&mut self, deadline: Timestamp, tx_id: TransactionID) {
+        self.deadlines.insert((deadline, tx_id));
+    }
+
+    /// Removes and returns authorization with the earliest deadline if it has passed at given time
+    pub fn pop_expired(&mut self, now: Timestamp) -> Option<TransactionID> {
+        let &(deadline, tx_id) = self.deadlines.first()?;
+
+        if deadline > now {
+            return None;
+        }
+
+        self.deadlines.pop_first();
+        Some(tx_id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::HoldDeadlines;
+
+    #[test]
+    fn holds_should_expire_in_order_of_deadlines() {
+        let mut holds = HoldDeadlines::default();
+        holds.push(30, 1);
+        holds.push(10, 2);
+        holds.push(20, 3);
+
+        assert_eq!(holds.pop_expired(5), None);
+        assert_eq!(holds.pop_expired(20), Some(2));
+        assert_eq!(holds.pop_expired(20), Some(3));
+        assert_eq!(holds.pop_expired(20), None);
+        assert_eq!(holds.pop_expired(30), Some(1));
+    }
+}
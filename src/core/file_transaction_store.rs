@@ -0,0 +1,1019 @@
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    fs::{File, OpenOptions},
+    io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write},
+    path::Path,
+};
+
+use rust_decimal::Decimal;
+
+use super::{
+    account::Amount,
+    atomic_file::{directory, sync_directory},
+    compact_transaction_store::MIN_DENSE_LEN,
+    transaction::{
+        DisputedAmount, PositiveAmount, Timestamp, Transaction, TransactionID, TransactionKind,
+        TransactionState,
+    },
+    transaction_store::{Store, StoreError},
+};
+
+// Record layout, all integers are little endian
+//
+// Insert:   tag(1) | tx_id(4) | client_id(2) | kind(1) | state(1) | amount(16)
+// State:    tag(1) | tx_id(4) | state(1)
+// Transfer: insert record followed by destination client_id(2)
+// Dispute:  state record followed by total(16) | outstanding(16) | charged_back(16)
+// Capture:  state record followed by captured(16)
+// Time:     tag(1) | tx_id(4) | timestamp(8), directly follows insert record of transaction with timestamp
+const INSERT_TAG: u8 = 1;
+const STATE_TAG: u8 = 2;
+const TRANSFER_TAG: u8 = 3;
+const DISPUTE_TAG: u8 = 4;
+const CAPTURE_TAG: u8 = 5;
+const TIME_TAG: u8 = 6;
+const INSERT_LEN: usize = 25;
+const STATE_LEN: usize = 6;
+const TRANSFER_LEN: usize = 27;
+const DISPUTE_LEN: usize = 54;
+const CAPTURE_LEN: usize = 22;
+const TIME_LEN: usize = 13;
+
+const DEPOSIT: u8 = 0;
+const WITHDRAWAL: u8 = 1;
+const TRANSFER: u8 = 2;
+const AUTHORIZATION: u8 = 3;
+const FREEZE: u8 = 4;
+const UNLOCK: u8 = 5;
+const CLOSE: u8 = 6;
+
+/// Offset of insert record in the log and latest state of transaction packed into 8 bytes
+///
+/// Offset is kept in the highest bits and state in the four lowest bits, zero means that slot is empty.
+#[derive(Clone, Copy, Default)]
+struct IndexEntry(u64);
+
+impl IndexEntry {
+    const EMPTY: Self = Self(0);
+
+    fn new(offset: u64, state: TransactionState) -> Self {
+        Self(offset << 4 | state_to_bits(state))
+    }
+
+    fn offset(self) -> u64 {
+        self.0 >> 4
+    }
+
+    fn state(self) -> Option<TransactionState> {
+        ((self.0 & 0b1111) as u8)
+            .checked_sub(1)
+            .and_then(byte_to_state)
+    }
+
+    fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+
+    fn set_state(&mut self, state: TransactionState) {
+        *self = Self::new(self.offset(), state);
+    }
+}
+
+/// In-memory part of the log rebuilt on open
+///
+/// Entries are kept in a vector indexed by [`TransactionID`] as long as ids are dense, the same way as
+/// in [`crate::CompactTransactionStore`], remaining ones fall back to a hash map.
+#[derive(Default)]
+struct Index {
+    dense: Vec<IndexEntry>,
+    sparse: HashMap<TransactionID, IndexEntry>,
+    // Latest disputed amounts, only disputed transactions have them
+    disputed: HashMap<TransactionID, DisputedAmount>,
+    // Latest captured amounts, only captured authorizations have them
+    captured: HashMap<TransactionID, Amount>,
+    len: usize,
+}
+
+impl Index {
+    fn entry(&self, tx_id: TransactionID) -> Option<IndexEntry> {
+        match self.dense.get(tx_id as usize) {
+            Some(entry) if !entry.is_empty() => Some(*entry),
+            // Transaction could be indexed in hash map before dense storage has grown
+            _ => self.sparse.get(&tx_id).copied(),
+        }
+    }
+
+    fn entry_mut(&mut self, tx_id: TransactionID) -> Option<&mut IndexEntry> {
+        match self.dense.get_mut(tx_id as usize) {
+            Some(entry) if !entry.is_empty() => Some(entry),
+            _ => self.sparse.get_mut(&tx_id),
+        }
+    }
+
+    fn insert(&mut self, tx_id: TransactionID, entry: IndexEntry) {
+        let required = tx_id as usize + 1;
+
+        if required <= self.dense.len() {
+            self.dense[tx_id as usize] = entry;
+        } else if required <= MIN_DENSE_LEN.max(2 * (self.len + 1)) {
+            self.dense.resize(required, IndexEntry::EMPTY);
+            self.dense[tx_id as usize] = entry;
+        } else {
+            self.sparse.insert(tx_id, entry);
+        }
+
+        self.len += 1;
+    }
+
+    fn set_state(&mut self, tx_id: TransactionID, state: TransactionState) {
+        if let Some(entry) = self.entry_mut(tx_id) {
+            entry.set_state(state);
+        }
+    }
+}
+
+/// Transaction storage backed by append-only log file
+///
+/// Every inserted transaction and every change of its state is appended to the log, only offsets and
+/// latest states packed into 8 bytes per transaction, disputed and captured amounts are kept in memory.
+/// Transactions, their timestamps and destinations of transfers are read from the log. Log is replayed
+/// when store is opened, so transactions survive restarts. Log is synced by [`Store::sync`].
+pub struct FileTransactionStore {
+    index: Index,
+    writer: RefCell<BufWriter<File>>,
+    reader: RefCell<File>,
+    // Length of the log including buffered records
+    len: u64,
+    // Length of the log which has been already flushed to file
+    flushed: RefCell<u64>,
+}
+
+impl FileTransactionStore {
+    /// Opens existing log or creates a new one
+    ///
+    /// Incomplete record at the end of log (e.g. after crash during write) is discarded.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, StoreError> {
+        let file = OpenOptions::new()
+            .read(true)
+            .append(true)
+            .create(true)
+            .open(path.as_ref())?;
+        // Log might have been just created
+        sync_directory(directory(path.as_ref()))?;
+
+        let (index, len) = replay(BufReader::new(&file))?;
+
+        Self::from_log(file, index, len)
+    }
+
+    /// Opens existing log and rewinds it to given length, e.g. length recorded by snapshot when log was synced
+    ///
+    /// Records appended after given length are discarded, log which is not complete up to it is corrupted.
+    pub fn resume<P: AsRef<Path>>(path: P, len: u64) -> Result<Self, StoreError> {
+        let file = OpenOptions::new()
+            .read(true)
+            .append(true)
+            .open(path.as_ref())?;
+
+        let (index, valid) = replay(BufReader::new(&file).take(len))?;
+
+        if valid < len {
+            return Err(StoreError::Corrupted(valid));
+        }
+
+        Self::from_log(file, index, len)
+    }
+
+    /// Creates empty log, existing log at given path is discarded
+    pub fn create<P: AsRef<Path>>(path: P) -> Result<Self, StoreError> {
+        File::create(path.as_ref())?;
+        Self::open(path)
+    }
+
+    /// Discards records after valid part of the log and prepares it for appending
+    fn from_log(file: File, index: Index, len: u64) -> Result<Self, StoreError> {
+        if len < file.metadata()?.len() {
+            file.set_len(len)?;
+        }
+
+        let reader = file.try_clone()?;
+
+        Ok(Self {
+            index,
+            writer: RefCell::new(BufWriter::new(file)),
+            reader: RefCell::new(reader),
+            len,
+            flushed: RefCell::new(len),
+        })
+    }
+
+    /// Returns true if log has no records
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Flushes buffered records to the log file
+    pub fn flush(&mut self) -> Result<(), StoreError> {
+        self.writer.get_mut().flush()?;
+        *self.flushed.get_mut() = self.len;
+        Ok(())
+    }
+
+    fn append(&mut self, record: &[u8]) -> Result<(), StoreError> {
+        self.writer.get_mut().write_all(record)?;
+        self.len += record.len() as u64;
+        Ok(())
+    }
+
+    /// Reads insert record at given offset together with timestamp record which may follow it
+    fn read_insert(
+        &self,
+        offset: u64,
+    ) -> Result<([u8; TRANSFER_LEN], Option<Timestamp>), StoreError> {
+        // Insert record might be the last one
+        let len = (self.len - offset).min((TRANSFER_LEN + TIME_LEN) as u64) as usize;
+
+        // Records might still be buffered
+        if offset + len as u64 > *self.flushed.borrow() {
+            self.writer.borrow_mut().flush()?;
+            *self.flushed.borrow_mut() = self.len;
+        }
+
+        let mut records = [0; TRANSFER_LEN + TIME_LEN];
+        let mut reader = self.reader.borrow_mut();
+        reader.seek(SeekFrom::Start(offset))?;
+        reader.read_exact(&mut records[..len])?;
+
+        let insert_len = if records[0] == TRANSFER_TAG {
+            TRANSFER_LEN
+        } else {
+            INSERT_LEN
+        };
+        let time = &records[insert_len..insert_len + TIME_LEN];
+        let timestamp =
+            (len >= insert_len + TIME_LEN && time[0] == TIME_TAG && time[1..5] == records[1..5])
+                .then(|| {
+                    let mut timestamp = [0; 8];
+                    timestamp.copy_from_slice(&time[5..13]);
+                    Timestamp::from_le_bytes(timestamp)
+                });
+
+        let mut record = [0; TRANSFER_LEN];
+        record.copy_from_slice(&records[..TRANSFER_LEN]);
+
+        Ok((record, timestamp))
+    }
+
+    fn transaction(
+        &self,
+        tx_id: TransactionID,
+        entry: IndexEntry,
+    ) -> Result<Transaction, StoreError> {
+        let (record, timestamp) = self.read_insert(entry.offset())?;
+        let corrupted = || StoreError::Corrupted(entry.offset());
+
+        let client_id = u16::from_le_bytes([record[5], record[6]]);
+        let amount = || PositiveAmount::new(read_amount(&record[9..25])).map_err(|_| corrupted());
+
+        let kind = match record[7] {
+            DEPOSIT => TransactionKind::Deposit { amount: amount()? },
+            WITHDRAWAL => TransactionKind::Withdrawal { amount: amount()? },
+            TRANSFER if record[0] == TRANSFER_TAG => TransactionKind::Transfer {
+                amount: amount()?,
+                destination: u16::from_le_bytes([record[25], record[26]]),
+            },
+            AUTHORIZATION => TransactionKind::Authorize { amount: amount()? },
+            FREEZE => TransactionKind::Freeze,
+            UNLOCK => TransactionKind::Unlock,
+            CLOSE => TransactionKind::Close,
+            _ => return Err(corrupted()),
+        };
+
+        let mut transaction = Transaction::new(kind, client_id, tx_id);
+        transaction.state = entry.state().ok_or_else(corrupted)?;
+        transaction.metadata.timestamp = timestamp;
+        transaction.disputed = self.index.disputed.get(&tx_id).copied().unwrap_or_default();
+        transaction.captured = self.index.captured.get(&tx_id).copied().unwrap_or_default();
+
+        Ok(transaction)
+    }
+}
+
+impl Store for FileTransactionStore {
+    fn insert(&mut self, transaction: Transaction) -> Result<(), StoreError> {
+        if self.index.entry(transaction.tx_id()).is_some() {
+            return Ok(());
+        }
+
+        let mut record = [0; TRANSFER_LEN];
+
+        let (kind, amount) = match transaction.kind {
+            TransactionKind::Deposit { amount } => (DEPOSIT, amount.value()),
+            TransactionKind::Withdrawal { amount } => (WITHDRAWAL, amount.value()),
+            TransactionKind::Transfer {
+                amount,
+                destination,
+            } => {
+                record[25..27].copy_from_slice(&destination.to_le_bytes());
+                (TRANSFER, amount.value())
+            }
+            TransactionKind::Authorize { amount } => (AUTHORIZATION, amount.value()),
+            // Administrative changes are stored only to keep their ids, they have no amount
+            TransactionKind::Freeze => (FREEZE, Amount::ZERO),
+            TransactionKind::Unlock => (UNLOCK, Amount::ZERO),
+            TransactionKind::Close => (CLOSE, Amount::ZERO),
+            // Other transactions only refer to ids of stored ones
+            _ => return Ok(()),
+        };
+
+        let record_len = if kind == TRANSFER {
+            record[0] = TRANSFER_TAG;
+            TRANSFER_LEN
+        } else {
+            record[0] = INSERT_TAG;
+            INSERT_LEN
+        };
+        record[1..5].copy_from_slice(&transaction.tx_id().to_le_bytes());
+        record[5..7].copy_from_slice(&transaction.client_id().to_le_bytes());
+        record[7] = kind;
+        record[8] = state_to_byte(transaction.state);
+        record[9..25].copy_from_slice(&amount.serialize());
+
+        let offset = self.len;
+        self.append(&record[..record_len])?;
+        self.index.insert(
+            transaction.tx_id(),
+            IndexEntry::new(offset, transaction.state),
+        );
+
+        if let Some(timestamp) = transaction.timestamp() {
+            let mut record = [0; TIME_LEN];
+            record[0] = TIME_TAG;
+            record[1..5].copy_from_slice(&transaction.tx_id().to_le_bytes());
+            record[5..13].copy_from_slice(&timestamp.to_le_bytes());
+            self.append(&record)?;
+        }
+
+        Ok(())
+    }
+
+    fn contains(&self, tx_id: &TransactionID) -> Result<bool, StoreError> {
+        Ok(self.index.entry(*tx_id).is_some())
+    }
+
+    fn get(&self, tx_id: &TransactionID) -> Result<Option<Transaction>, StoreError> {
+        self.index
+            .entry(*tx_id)
+            .map(|entry| self.transaction(*tx_id, entry))
+            .transpose()
+    }
+
+    fn set_state(
+        &mut self,
+        tx_id: &TransactionID,
+        state: TransactionState,
+    ) -> Result<(), StoreError> {
+        if self.index.entry(*tx_id).is_none() {
+            return Ok(());
+        }
+
+        let mut record = [0; STATE_LEN];
+        record[0] = STATE_TAG;
+        record[1..5].copy_from_slice(&tx_id.to_le_bytes());
+        record[5] = state_to_byte(state);
+        self.append(&record)?;
+
+        self.index.set_state(*tx_id, state);
+
+        Ok(())
+    }
+
+    fn set_disputed(
+        &mut self,
+        tx_id: &TransactionID,
+        state: TransactionState,
+        disputed: DisputedAmount,
+    ) -> Result<(), StoreError> {
+        if self.index.entry(*tx_id).is_none() {
+            return Ok(());
+        }
+
+        let mut record = [0; DISPUTE_LEN];
+        record[0] = DISPUTE_TAG;
+        record[1..5].copy_from_slice(&tx_id.to_le_bytes());
+        record[5] = state_to_byte(state);
+        record[6..22].copy_from_slice(&disputed.total.serialize());
+        record[22..38].copy_from_slice(&disputed.outstanding.serialize());
+        record[38..54].copy_from_slice(&disputed.charged_back.serialize());
+        self.append(&record)?;
+
+        self.index.set_state(*tx_id, state);
+        self.index.disputed.insert(*tx_id, disputed);
+
+        Ok(())
+    }
+
+    fn set_captured(
+        &mut self,
+        tx_id: &TransactionID,
+        state: TransactionState,
+        captured: Amount,
+    ) -> Result<(), StoreError> {
+        if self.index.entry(*tx_id).is_none() {
+            return Ok(());
+        }
+
+        let mut record = [0; CAPTURE_LEN];
+        record[0] = CAPTURE_TAG;
+        record[1..5].copy_from_slice(&tx_id.to_le_bytes());
+        record[5] = state_to_byte(state);
+        record[6..22].copy_from_slice(&captured.serialize());
+        self.append(&record)?;
+
+        self.index.set_state(*tx_id, state);
+        self.index.captured.insert(*tx_id, captured);
+
+        Ok(())
+    }
+
+    fn sync(&mut self) -> Result<Option<u64>, StoreError> {
+        self.flush()?;
+        self.writer.get_mut().get_ref().sync_data()?;
+        Ok(Some(self.len))
+    }
+
+    fn iter(&self) -> Box<dyn Iterator<Item = Result<Transaction, StoreError>> + '_> {
+        let dense = self
+            .index
+            .dense
+            .iter()
+            .enumerate()
+            .filter(|(_, entry)| !entry.is_empty())
+            .map(|(tx_id, entry)| self.transaction(tx_id as TransactionID, *entry));
+        let sparse = self
+            .index
+            .sparse
+            .iter()
+            .map(|(tx_id, entry)| self.transaction(*tx_id, *entry));
+
+        Box::new(dense.chain(sparse))
+    }
+}
+
+impl Drop for FileTransactionStore {
+    fn drop(&mut self) {
+        if let Err(err) = self.flush() {
+            log::error!("{}", err);
+        }
+    }
+}
+
+/// Rebuilds index from the log, returns index and length of the valid part of the log
+fn replay<R: Read>(mut log: R) -> Result<(Index, u64), StoreError> {
+    let mut index = Index::default();
+    let mut offset = 0;
+    let mut record = [0; DISPUTE_LEN];
+
+    loop {
+        if log.read(&mut record[..1])? == 0 {
+            break;
+        }
+
+        let record_len = match record[0] {
+            INSERT_TAG => INSERT_LEN,
+            STATE_TAG => STATE_LEN,
+            TRANSFER_TAG => TRANSFER_LEN,
+            DISPUTE_TAG => DISPUTE_LEN,
+            CAPTURE_TAG => CAPTURE_LEN,
+            TIME_TAG => TIME_LEN,
+            _ => return Err(StoreError::Corrupted(offset)),
+        };
+
+        match log.read_exact(&mut record[1..record_len]) {
+            Ok(_) => {}
+            // Incomplete record at the end of log
+            Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => break,
+            Err(err) => return Err(err.into()),
+        }
+
+        let tx_id = TransactionID::from_le_bytes([record[1], record[2], record[3], record[4]]);
+
+        if record[0] == TIME_TAG {
+            // Timestamps are read together with insert records
+        } else if matches!(record[0], STATE_TAG | DISPUTE_TAG | CAPTURE_TAG) {
+            let state = byte_to_state(record[5]).ok_or(StoreError::Corrupted(offset))?;
+            if let Some(entry) = index.entry_mut(tx_id) {
+                entry.set_state(state);
+
+                if record[0] == DISPUTE_TAG {
+                    let disputed = DisputedAmount {
+                        total: read_amount(&record[6..22]),
+                        outstanding: read_amount(&record[22..38]),
+                        charged_back: read_amount(&record[38..54]),
+                    };
+                    index.disputed.insert(tx_id, disputed);
+                } else if record[0] == CAPTURE_TAG {
+                    index.captured.insert(tx_id, read_amount(&record[6..22]));
+                }
+            }
+        } else {
+            let state = byte_to_state(record[8]).ok_or(StoreError::Corrupted(offset))?;
+            if index.entry(tx_id).is_none() {
+                index.insert(tx_id, IndexEntry::new(offset, state));
+            }
+        }
+
+        offset += record_len as u64;
+    }
+
+    Ok((index, offset))
+}
+
+fn read_amount(bytes: &[u8]) -> Decimal {
+    let mut amount = [0; 16];
+    amount.copy_from_slice(bytes);
+    Decimal::deserialize(amount)
+}
+
+fn state_to_byte(state: TransactionState) -> u8 {
+    match state {
+        TransactionState::New => 0,
+        TransactionState::Succeeded => 1,
+        TransactionState::Failed => 2,
+        TransactionState::Disputed => 3,
+        TransactionState::Resolved => 4,
+        TransactionState::Chargeback => 5,
+        TransactionState::Reverted => 6,
+        TransactionState::Captured => 7,
+        TransactionState::Voided => 8,
+    }
+}
+
+// State in index entry is shifted by one, zero is reserved for empty slot
+fn state_to_bits(state: TransactionState) -> u64 {
+    state_to_byte(state) as u64 + 1
+}
+
+fn byte_to_state(byte: u8) -> Option<TransactionState> {
+    match byte {
+        0 => Some(TransactionState::New),
+        1 => Some(TransactionState::Succeeded),
+        2 => Some(TransactionState::Failed),
+        3 => Some(TransactionState::Disputed),
+        4 => Some(TransactionState::Resolved),
+        5 => Some(TransactionState::Chargeback),
+        6 => Some(TransactionState::Reverted),
+        7 => Some(TransactionState::Captured),
+        8 => Some(TransactionState::Voided),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        fs::{self, OpenOptions},
+        io::Write,
+        path::PathBuf,
+    };
+
+    use test_case::test_case;
+
+    use crate::core::{
+        account::Amount,
+        account_store::AccountStore,
+        engine::Engine,
+        tests::{positive, transaction},
+        transaction::{DisputedAmount, TransactionKind, TransactionState},
+        transaction_store::{Store, StoreError},
+    };
+
+    use super::{FileTransactionStore, IndexEntry};
+
+    // Helper returning unique path of log file in temporary directory
+    fn log_path(name: &str) -> PathBuf {
+        let path =
+            std::env::temp_dir().join(format!("transactions-{}-{}.log", name, std::process::id()));
+        let _ = fs::remove_file(&path);
+        path
+    }
+
+    #[test]
+    fn insert_and_get_transaction() -> Result<(), StoreError> {
+        let path = log_path("insert");
+        let mut store = FileTransactionStore::open(&path)?;
+
+        store.insert(transaction(
+            TransactionKind::Withdrawal {
+                amount: positive(Amount::new(12345, 4)),
+            },
+            7,
+            3,
+        ))?;
+
+        let transaction = store.get(&7)?.unwrap();
+
+        assert_eq!(transaction.client_id(), 3);
+        assert_eq!(
+            transaction.kind,
+            TransactionKind::Withdrawal {
+                amount: positive(Amount::new(12345, 4))
+            }
+        );
+        assert!(store.contains(&7)?);
+        assert!(store.get(&8)?.is_none());
+
+        fs::remove_file(&path).ok();
+        Ok(())
+    }
+
+    #[test]
+    fn transactions_and_states_should_survive_reopen() -> Result<(), StoreError> {
+        let path = log_path("reopen");
+
+        {
+            let mut store = FileTransactionStore::open(&path)?;
+            store.insert(transaction(
+                TransactionKind::Deposit {
+                    amount: positive(Amount::ONE),
+                },
+                1,
+                1,
+            ))?;
+            store.insert(transaction(
+                TransactionKind::Deposit {
+                    amount: positive(Amount::TWO),
+                },
+                2,
+                1,
+            ))?;
+            store.set_state(&1, TransactionState::Succeeded)?;
+            store.set_state(&1, TransactionState::Disputed)?;
+        }
+
+        let store = FileTransactionStore::open(&path)?;
+
+        assert_eq!(store.get(&1)?.unwrap().state, TransactionState::Disputed);
+        assert_eq!(store.get(&2)?.unwrap().state, TransactionState::New);
+
+        fs::remove_file(&path).ok();
+        Ok(())
+    }
+
+    #[test]
+    fn transfer_should_survive_reopen() -> Result<(), StoreError> {
+        let path = log_path("transfer");
+        let kind = TransactionKind::Transfer {
+            amount: positive(Amount::TWO),
+            destination: 9,
+        };
+
+        {
+            let mut store = FileTransactionStore::open(&path)?;
+            store.insert(transaction(kind, 1, 3))?;
+            store.set_state(&1, TransactionState::Disputed)?;
+            store.insert(transaction(
+                TransactionKind::Deposit {
+                    amount: positive(Amount::ONE),
+                },
+                2,
+                3,
+            ))?;
+            assert_eq!(store.get(&1)?.unwrap().kind, kind);
+        }
+
+        let store = FileTransactionStore::open(&path)?;
+        let transfer = store.get(&1)?.unwrap();
+
+        assert_eq!(transfer.kind, kind);
+        assert_eq!(transfer.client_id(), 3);
+        assert_eq!(transfer.state, TransactionState::Disputed);
+        assert!(store.get(&2)?.is_some());
+
+        fs::remove_file(&path).ok();
+        Ok(())
+    }
+
+    #[test]
+    fn disputed_amount_should_survive_reopen() -> Result<(), StoreError> {
+        let path = log_path("disputed");
+        let disputed = DisputedAmount {
+            total: Amount::TWO,
+            outstanding: Amount::ONE,
+            charged_back: Amount::ZERO,
+        };
+
+        {
+            let mut store = FileTransactionStore::open(&path)?;
+            store.insert(transaction(
+                TransactionKind::Deposit {
+                    amount: positive(Amount::TEN),
+                },
+                1,
+                3,
+            ))?;
+            store.set_disputed(&1, TransactionState::Disputed, disputed)?;
+        }
+
+        let store = FileTransactionStore::open(&path)?;
+        let transaction = store.get(&1)?.unwrap();
+
+        assert_eq!(transaction.state, TransactionState::Disputed);
+        assert_eq!(transaction.disputed, disputed);
+
+        fs::remove_file(&path).ok();
+        Ok(())
+    }
+
+    #[test]
+    fn captured_amount_should_survive_reopen() -> Result<(), StoreError> {
+        let path = log_path("captured");
+        let kind = TransactionKind::Authorize {
+            amount: positive(Amount::TEN),
+        };
+
+        {
+            let mut store = FileTransactionStore::open(&path)?;
+            store.insert(transaction(kind, 1, 3))?;
+            store.set_captured(&1, TransactionState::Succeeded, Amount::TWO)?;
+        }
+
+        let store = FileTransactionStore::open(&path)?;
+        let transaction = store.get(&1)?.unwrap();
+
+        assert_eq!(transaction.kind, kind);
+        assert_eq!(transaction.state, TransactionState::Succeeded);
+        assert_eq!(transaction.captured, Amount::TWO);
+
+        fs::remove_file(&path).ok();
+        Ok(())
+    }
+
+    #[test_case(TransactionKind::Freeze ; "freeze")]
+    #[test_case(TransactionKind::Unlock ; "unlock")]
+    #[test_case(TransactionKind::Close ; "close")]
+    fn administrative_change_should_survive_reopen(
+        kind: TransactionKind,
+    ) -> Result<(), StoreError> {
+        let path = log_path(&format!("administrative-{:?}", kind));
+
+        {
+            let mut store = FileTransactionStore::open(&path)?;
+            store.insert(transaction(kind, 1, 3))?;
+        }
+
+        let store = FileTransactionStore::open(&path)?;
+        let transaction = store.get(&1)?.unwrap();
+
+        assert_eq!(transaction.kind, kind);
+        assert_eq!(transaction.client_id(), 3);
+
+        fs::remove_file(&path).ok();
+        Ok(())
+    }
+
+    #[test]
+    fn timestamp_should_survive_reopen() -> Result<(), StoreError> {
+        let path = log_path("timestamp");
+        let kind = TransactionKind::Deposit {
+            amount: positive(Amount::ONE),
+        };
+
+        {
+            let mut store = FileTransactionStore::open(&path)?;
+            store.insert(transaction(kind, 1, 3).with_timestamp(1_700_000_000))?;
+            store.insert(transaction(kind, 2, 3))?;
+            store.set_state(&1, TransactionState::Disputed)?;
+        }
+
+        let store = FileTransactionStore::open(&path)?;
+        let transaction = store.get(&1)?.unwrap();
+
+        assert_eq!(transaction.timestamp(), Some(1_700_000_000));
+        assert_eq!(transaction.state, TransactionState::Disputed);
+        assert_eq!(store.get(&2)?.unwrap().timestamp(), None);
+
+        fs::remove_file(&path).ok();
+        Ok(())
+    }
+
+    #[test]
+    fn incomplete_record_should_be_discarded_on_open() -> Result<(), StoreError> {
+        let path = log_path("incomplete");
+
+        {
+            let mut store = FileTransactionStore::open(&path)?;
+            store.insert(transaction(
+                TransactionKind::Deposit {
+                    amount: positive(Amount::ONE),
+                },
+                1,
+                1,
+            ))?;
+        }
+
+        // Simulates crash in the middle of write
+        OpenOptions::new()
+            .append(true)
+            .open(&path)?
+            .write_all(&[1, 2, 0])?;
+
+        let mut store = FileTransactionStore::open(&path)?;
+        store.insert(transaction(
+            TransactionKind::Deposit {
+                amount: positive(Amount::TEN),
+            },
+            2,
+            1,
+        ))?;
+
+        assert!(store.get(&1)?.is_some());
+        assert_eq!(store.get(&2)?.unwrap().client_id(), 1);
+
+        fs::remove_file(&path).ok();
+        Ok(())
+    }
+
+    #[test]
+    fn created_log_should_discard_existing_one() -> Result<(), StoreError> {
+        let path = log_path("create");
+
+        {
+            let mut store = FileTransactionStore::open(&path)?;
+            assert!(store.is_empty());
+
+            store.insert(transaction(
+                TransactionKind::Deposit {
+                    amount: positive(Amount::ONE),
+                },
+                1,
+                1,
+            ))?;
+        }
+
+        assert!(!FileTransactionStore::open(&path)?.is_empty());
+
+        let store = FileTransactionStore::create(&path)?;
+
+        assert!(store.is_empty());
+        assert!(store.get(&1)?.is_none());
+
+        fs::remove_file(&path).ok();
+        Ok(())
+    }
+
+    #[test]
+    fn index_entry_should_fit_in_8_bytes() {
+        assert_eq!(std::mem::size_of::<IndexEntry>(), 8);
+    }
+
+    #[test]
+    fn sparse_transactions_should_survive_reopen() -> Result<(), StoreError> {
+        let path = log_path("sparse");
+        let kind = TransactionKind::Deposit {
+            amount: positive(Amount::ONE),
+        };
+
+        {
+            let mut store = FileTransactionStore::open(&path)?;
+            store.insert(transaction(kind, 1, 1))?;
+            store.insert(transaction(kind, 5_000_000, 2).with_timestamp(1_700_000_000))?;
+            store.insert(transaction(kind, u32::MAX, 3))?;
+            store.set_state(&5_000_000, TransactionState::Disputed)?;
+        }
+
+        let store = FileTransactionStore::open(&path)?;
+        let sparse = store.get(&5_000_000)?.unwrap();
+
+        assert_eq!(sparse.client_id(), 2);
+        assert_eq!(sparse.state, TransactionState::Disputed);
+        assert_eq!(sparse.timestamp(), Some(1_700_000_000));
+        assert_eq!(store.get(&u32::MAX)?.unwrap().client_id(), 3);
+        assert_eq!(store.iter().count(), 3);
+
+        fs::remove_file(&path).ok();
+        Ok(())
+    }
+
+    #[test]
+    fn resumed_log_should_discard_records_after_synced_length() -> Result<(), StoreError> {
+        let path = log_path("resume");
+        let kind = TransactionKind::Deposit {
+            amount: positive(Amount::ONE),
+        };
+
+        let len = {
+            let mut store = FileTransactionStore::open(&path)?;
+            store.insert(transaction(kind, 1, 1).with_timestamp(1_700_000_000))?;
+            let len = store.sync()?.unwrap();
+
+            store.insert(transaction(kind, 2, 1))?;
+            store.set_state(&1, TransactionState::Disputed)?;
+            len
+        };
+
+        let store = FileTransactionStore::resume(&path, len)?;
+        let transaction = store.get(&1)?.unwrap();
+
+        assert_eq!(transaction.state, TransactionState::New);
+        assert_eq!(transaction.timestamp(), Some(1_700_000_000));
+        assert!(store.get(&2)?.is_none());
+        assert_eq!(fs::metadata(&path)?.len(), len);
+
+        fs::remove_file(&path).ok();
+        Ok(())
+    }
+
+    #[test]
+    fn log_shorter_than_synced_length_should_not_be_resumed() -> Result<(), StoreError> {
+        let path = log_path("short");
+
+        let len = {
+            let mut store = FileTransactionStore::open(&path)?;
+            store.insert(transaction(
+                TransactionKind::Deposit {
+                    amount: positive(Amount::ONE),
+                },
+                1,
+                1,
+            ))?;
+            store.sync()?.unwrap()
+        };
+
+        OpenOptions::new()
+            .write(true)
+            .open(&path)?
+            .set_len(len - 3)?;
+
+        assert!(matches!(
+            FileTransactionStore::resume(&path, len),
+            Err(StoreError::Corrupted(0))
+        ));
+
+        fs::remove_file(&path).ok();
+        Ok(())
+    }
+
+    #[test]
+    fn engine_resumed_from_log_should_match_uninterrupted_one() -> Result<(), StoreError> {
+        let path = log_path("engine");
+        let rows = [
+            transaction(
+                TransactionKind::Deposit {
+                    amount: positive(Amount::TEN),
+                },
+                1,
+                1,
+            ),
+            transaction(TransactionKind::Dispute { amount: None }, 1, 1),
+            transaction(
+                TransactionKind::Deposit {
+                    amount: positive(Amount::TWO),
+                },
+                2,
+                1,
+            ),
+            transaction(TransactionKind::Resolve { amount: None }, 1, 1),
+        ];
+
+        let mut uninterrupted = Engine::new(
+            FileTransactionStore::open(log_path("uninterrupted"))?,
+            AccountStore::default(),
+        );
+        for row in &rows {
+            uninterrupted.process_transaction(row.clone());
+        }
+
+        let snapshot = {
+            let mut engine =
+                Engine::new(FileTransactionStore::open(&path)?, AccountStore::default());
+            engine.process_transaction(rows[0].clone());
+            engine.process_transaction(rows[1].clone());
+            let snapshot = engine.checkpoint()?;
+            // Rows processed after checkpoint are lost in crash
+            engine.process_transaction(rows[2].clone());
+            snapshot
+        };
+
+        let len = snapshot.log_len.unwrap();
+        let mut resumed = Engine::new(
+            FileTransactionStore::resume(&path, len)?,
+            AccountStore::default(),
+        );
+        resumed.restore(snapshot)?;
+
+        // Transactions already in the log are not written again
+        assert_eq!(fs::metadata(&path)?.len(), len);
+
+        for row in &rows[2..] {
+            resumed.process_transaction(row.clone());
+        }
+
+        assert_eq!(resumed.accounts(), uninterrupted.accounts());
+
+        fs::remove_file(&path).ok();
+        fs::remove_file(log_path("uninterrupted")).ok();
+        Ok(())
+    }
+}
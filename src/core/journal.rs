@@ -0,0 +1,174 @@
+use super::{
+    account::{Account, AccountDelta, ClientID},
+    engine::ProcessOutcome,
+    transaction::{TransactionID, TransactionKind},
+};
+
+/// Single processed transaction together with its delta and outcome
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct JournalEntry {
+    pub sequence: u64,
+    pub tx_id: TransactionID,
+    pub client_id: ClientID,
+    pub kind: TransactionKind,
+    pub delta: AccountDelta,
+    pub outcome: ProcessOutcome,
+}
+
+/// Append-only journal of processed transactions
+///
+/// Every processed transaction gets consecutive sequence number starting from 1. Only entries with
+/// [`ProcessOutcome::Applied`] have changed user account, so replaying them recreates user account
+/// up to any given point.
+#[derive(Default, Debug)]
+pub struct Journal {
+    entries: Vec<JournalEntry>,
+}
+
+impl Journal {
+    /// Appends entry and returns its sequence number
+    pub fn record(
+        &mut self,
+        tx_id: TransactionID,
+        client_id: ClientID,
+        kind: TransactionKind,
+        delta: AccountDelta,
+        outcome: ProcessOutcome,
+    ) -> u64 {
+        let sequence = self.entries.len() as u64 + 1;
+
+        self.entries.push(JournalEntry {
+            sequence,
+            tx_id,
+            client_id,
+            kind,
+            delta,
+            outcome,
+        });
+
+        sequence
+    }
+
+    /// Returns all entries in order of processing
+    pub fn entries(&self) -> &[JournalEntry] {
+        &self.entries
+    }
+
+    /// Returns entry with given sequence number
+    pub fn get(&self, sequence: u64) -> Option<&JournalEntry> {
+        let index = sequence.checked_sub(1)?;
+        self.entries.get(index as usize)
+    }
+
+    /// Returns all entries referring to given transaction id, e.g. deposit and its dispute
+    pub fn entries_for_tx(&self, tx_id: TransactionID) -> impl Iterator<Item = &JournalEntry> {
+        self.entries
+            .iter()
+            .filter(move |entry| entry.tx_id == tx_id)
+    }
+
+    /// Returns user account as it was right after entry with given sequence number
+    pub fn account_at(&self, client_id: ClientID, sequence: u64) -> Account {
+        let mut account = Account::new(client_id);
+
+        self.entries
+            .iter()
+            .take_while(|entry| entry.sequence <= sequence)
+            .filter(|entry| {
+                entry.client_id == client_id && entry.outcome == ProcessOutcome::Applied
+            })
+            // Delta has been already accepted by the account in the same state, possibly using
+            // its credit line which journal doesn't know about
+            .for_each(|entry| account.replay(entry.delta));
+
+        account
+    }
+
+    /// Returns user account as it was right before entry with given sequence number
+    pub fn account_before(&self, client_id: ClientID, sequence: u64) -> Account {
+        self.account_at(client_id, sequence.saturating_sub(1))
+    }
+
+    /// Returns user account as it was right after the latest entry referring to given transaction id
+    pub fn account_at_tx(&self, client_id: ClientID, tx_id: TransactionID) -> Option<Account> {
+        let entry = self
+            .entries_for_tx(tx_id)
+            .filter(|entry| entry.client_id == client_id)
+            .last()?;
+
+        Some(self.account_at(client_id, entry.sequence))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::core::{
+        account::{AccountDelta, AccountError, Amount},
+        engine::ProcessOutcome,
+        tests::positive,
+        transaction::TransactionKind,
+    };
+
+    use super::Journal;
+
+    fn deposit(amount: Amount) -> TransactionKind {
+        TransactionKind::Deposit {
+            amount: positive(amount),
+        }
+    }
+
+    #[test]
+    fn account_should_be_recreated_up_to_sequence() {
+        let mut journal = Journal::default();
+
+        journal.record(
+            1,
+            1,
+            deposit(Amount::TEN),
+            AccountDelta::deposit(Amount::TEN),
+            ProcessOutcome::Applied,
+        );
+        journal.record(
+            2,
+            2,
+            deposit(Amount::ONE),
+            AccountDelta::deposit(Amount::ONE),
+            ProcessOutcome::Applied,
+        );
+        journal.record(
+            3,
+            1,
+            TransactionKind::Withdrawal {
+                amount: positive(Amount::ONE_HUNDRED),
+            },
+            AccountDelta::withdrawal(Amount::ONE_HUNDRED),
+            ProcessOutcome::Rejected(AccountError::InsufficientFunds(Amount::ONE)),
+        );
+        let dispute = journal.record(
+            1,
+            1,
+            TransactionKind::Dispute { amount: None },
+            AccountDelta::dispute_deposit(Amount::TEN),
+            ProcessOutcome::Applied,
+        );
+
+        assert_eq!(dispute, 4);
+
+        let before_dispute = journal.account_before(1, dispute);
+        assert_eq!(before_dispute.available(), Amount::TEN);
+        assert_eq!(before_dispute.held(), Amount::ZERO);
+
+        let after_dispute = journal.account_at(1, dispute);
+        assert_eq!(after_dispute.available(), Amount::ZERO);
+        assert_eq!(after_dispute.held(), Amount::TEN);
+
+        assert_eq!(journal.account_at(2, 1).total(), Amount::ZERO);
+        assert_eq!(journal.account_at(2, 2).total(), Amount::ONE);
+
+        assert_eq!(journal.account_at_tx(1, 1), Some(after_dispute));
+        assert_eq!(journal.account_at_tx(1, 2), None);
+        assert_eq!(journal.entries_for_tx(1).count(), 2);
+        assert_eq!(journal.get(3).unwrap().tx_id, 3);
+        assert!(journal.get(0).is_none());
+    }
+}
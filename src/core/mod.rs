@@ -1,6 +1,15 @@
 pub mod account;
 pub mod account_store;
+pub mod atomic_file;
+pub mod compact_transaction_store;
 pub mod engine;
+#[cfg(feature = "async")]
+pub mod engine_handle;
+pub mod expiry;
+pub mod file_transaction_store;
+pub mod journal;
+pub mod sharded_engine;
+pub mod snapshot;
 
 pub mod transaction;
 pub mod transaction_processor;
@@ -9,10 +18,17 @@ pub mod transaction_store;
 #[cfg(test)]
 mod tests {
     use super::{
-        account::ClientID,
-        transaction::{Transaction, TransactionID, TransactionKind, TransactionMetadata},
+        account::{Amount, ClientID},
+        transaction::{
+            PositiveAmount, Transaction, TransactionID, TransactionKind, TransactionMetadata,
+        },
     };
 
+    // Helper for creating transaction amount
+    pub fn positive(amount: Amount) -> PositiveAmount {
+        PositiveAmount::new(amount).unwrap()
+    }
+
     // Helper for creating transaction
     pub fn transaction(
         kind: TransactionKind,
@@ -21,8 +37,14 @@ mod tests {
     ) -> Transaction {
         Transaction {
             kind,
-            metadata: TransactionMetadata { client_id, tx_id },
+            metadata: TransactionMetadata {
+                client_id,
+                tx_id,
+                timestamp: None,
+            },
             state: Default::default(),
+            disputed: Default::default(),
+            captured: Default::default(),
         }
     }
 }
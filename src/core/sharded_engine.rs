@@ -0,0 +1,776 @@
+use std::{
+    collections::HashMap,
+    mem,
+    sync::{
+        mpsc::{self, Receiver, Sender, SyncSender},
+        Arc,
+    },
+    thread::{self, JoinHandle},
+};
+
+use super::{
+    account::{AccountError, ClientID},
+    account_store,
+    compact_transaction_store::MIN_DENSE_LEN,
+    engine::{Engine, ProcessOutcome},
+    transaction::{Timestamp, Transaction, TransactionID, TransactionKind},
+    transaction_processor::Counterpart,
+    transaction_store::{self, StoreError},
+};
+
+type Observer = dyn Fn(TransactionID, &ProcessOutcome) + Send + Sync;
+
+// Transactions are sent to shards in batches to reduce synchronization overhead
+const BATCH_LEN: usize = 256;
+// Number of batches which can wait for each shard before reader is blocked
+const CHANNEL_CAPACITY: usize = 16;
+
+#[derive(thiserror::Error, Debug, PartialEq, Eq)]
+pub enum ShardError {
+    #[error("{0}")]
+    Store(#[from] StoreError),
+    #[error("Shard {0} has stopped")]
+    Disconnected(usize),
+    #[error("Shard {0} has panicked")]
+    Panicked(usize),
+}
+
+/// Counterpart for account of other shard together with channel for its result
+type CounterpartRequest = (Counterpart, Sender<Result<(), AccountError>>);
+
+enum Command {
+    /// Transaction which touches only accounts of the shard
+    Process(Transaction),
+    /// Transaction whose counterpart is applied by given shard
+    Coordinated {
+        transaction: Transaction,
+        other: usize,
+        counterparts: Sender<CounterpartRequest>,
+    },
+    /// Applies counterparts of transaction processed by other shard, until it is processed
+    Counterparts(Receiver<CounterpartRequest>),
+    /// Transaction reuses id stored by other shard, it is only reported in order of its client
+    Duplicate(Transaction),
+    /// Moves clock of the shard forward, transaction of other shard has happened at given time
+    Advance(Timestamp),
+}
+
+struct Shard<A> {
+    sender: SyncSender<Vec<Command>>,
+    handle: JoinHandle<Result<A, ShardError>>,
+    batch: Vec<Command>,
+}
+
+/// Runs several [`Engine`]s in parallel, each one on its own thread
+///
+/// Every client is assigned to one shard and its transactions are processed in the same order
+/// as they were submitted. Accounts from all shards are merged once processing is finished.
+///
+/// Transfer between clients of different shards, as well as dispute, resolve and chargeback of it,
+/// is coordinated: destination shard waits at the same point of its own order until source shard
+/// has processed the transaction, and applies change of destination account only once source
+/// account has accepted its own, so either both accounts change or none.
+///
+/// Ids of transactions are tracked across shards, reuse of id by client of other shard is reported
+/// as [`ProcessOutcome::Duplicate`]. Time of every transaction is passed to all shards, so holds
+/// of authorizations expire and disputes are dated the same way as in a single engine.
+pub struct ShardedEngine<A> {
+    shards: Vec<Shard<A>>,
+    // Shard which stores transaction with given id
+    owners: Owners,
+    // Shard of destination for transfers between shards, their disputes need to be coordinated as well
+    transfers: HashMap<TransactionID, usize>,
+    // Time of the latest transaction with timestamp
+    clock: Option<Timestamp>,
+}
+
+impl<A> ShardedEngine<A>
+where
+    A: account_store::Store + Default + Send + 'static,
+{
+    /// Starts given number of shards, each one with engine created by `engine`
+    ///
+    /// `observer` is invoked on shard thread with outcome of every processed transaction.
+    /// Shard stops on the first [`ProcessOutcome::Failed`].
+    pub fn new<T, E, O>(shards: usize, engine: E, observer: O) -> Self
+    where
+        T: transaction_store::Store + Send + 'static,
+        E: Fn() -> Engine<T, A>,
+        O: Fn(TransactionID, &ProcessOutcome) + Send + Sync + 'static,
+    {
+        let observer: Arc<Observer> = Arc::new(observer);
+
+        // Owners keep index of shard in two bytes
+        let shards = (0..shards.clamp(1, u16::MAX as usize))
+            .map(|_| {
+                let (sender, receiver) = mpsc::sync_channel::<Vec<Command>>(CHANNEL_CAPACITY);
+                let engine = engine();
+                let observer = observer.clone();
+
+                Shard {
+                    sender,
+                    handle: thread::spawn(move || run_shard(engine, receiver, &*observer)),
+                    batch: Vec::with_capacity(BATCH_LEN),
+                }
+            })
+            .collect();
+
+        Self {
+            shards,
+            owners: Owners::default(),
+            transfers: HashMap::new(),
+            clock: None,
+        }
+    }
+
+    /// Returns number of shards
+    pub fn shards(&self) -> usize {
+        self.shards.len()
+    }
+
+    /// Routes transaction to shard of its client
+    ///
+    /// Error is returned when shard has stopped, reason of it is returned by [`ShardedEngine::finish`].
+    pub fn process_transaction(&mut self, transaction: Transaction) -> Result<(), ShardError> {
+        let index = self.shard_of(transaction.client_id());
+        let tx_id = transaction.tx_id();
+
+        let duplicate = self.is_duplicate(&transaction, index);
+
+        // Duplicate moves clock as well, the same way as in a single engine
+        if let Some(timestamp) = transaction.timestamp() {
+            if self.clock < Some(timestamp) {
+                self.clock = Some(timestamp);
+
+                // Shard of transaction moves its clock by itself
+                for other in (0..self.shards.len()).filter(|other| *other != index) {
+                    self.push(other, Command::Advance(timestamp))?;
+                }
+            }
+        }
+
+        if duplicate {
+            return self.push(index, Command::Duplicate(transaction));
+        }
+
+        let other = match transaction.kind {
+            TransactionKind::Transfer { destination, .. } => {
+                let other = self.shard_of(destination);
+                if other != index {
+                    // Duplicate id is refused by shard, destination of the original transfer is kept
+                    self.transfers.entry(tx_id).or_insert(other);
+                }
+                Some(other)
+            }
+            TransactionKind::Dispute { .. }
+            | TransactionKind::Resolve { .. }
+            | TransactionKind::Chargeback { .. } => self.transfers.get(&tx_id).copied(),
+            _ => None,
+        };
+
+        match other {
+            Some(other) if other != index => {
+                let (counterparts, requests) = mpsc::channel();
+
+                self.push(other, Command::Counterparts(requests))?;
+                self.push(
+                    index,
+                    Command::Coordinated {
+                        transaction,
+                        other,
+                        counterparts,
+                    },
+                )?;
+
+                // Both shards wait for each other, so neither command can be left in batch
+                self.flush(other)?;
+                self.flush(index)
+            }
+            _ => self.push(index, Command::Process(transaction)),
+        }
+    }
+
+    /// Waits until all submitted transactions are processed and returns merged accounts of all shards
+    ///
+    /// When several shards have stopped, the reason of the one which stopped first is returned.
+    pub fn finish(self) -> Result<A, ShardError> {
+        let mut handles = Vec::with_capacity(self.shards.len());
+
+        for shard in self.shards {
+            // Stopped shard reports its reason once joined
+            let _ = shard.sender.send(shard.batch);
+            handles.push(shard.handle);
+        }
+
+        let mut merged = A::default();
+        let mut error = None;
+
+        for (index, handle) in handles.into_iter().enumerate() {
+            let accounts = match handle.join() {
+                Ok(Ok(accounts)) => accounts,
+                Ok(Err(err)) => {
+                    // Shard which waited for stopped one only reports disconnection
+                    if matches!(error, None | Some(ShardError::Disconnected(_))) {
+                        error = Some(err);
+                    }
+                    continue;
+                }
+                Err(_) => {
+                    error.get_or_insert(ShardError::Panicked(index));
+                    continue;
+                }
+            };
+
+            for account in accounts.iter() {
+                *merged.get_mut_or_new(account.id()) = account.clone();
+            }
+        }
+
+        match error {
+            Some(err) => Err(err),
+            None => Ok(merged),
+        }
+    }
+
+    /// Returns whether transaction reuses id of transaction stored by other shard
+    ///
+    /// Reuse within the same shard is left to its engine, which knows whether it is an exact replay.
+    fn is_duplicate(&mut self, transaction: &Transaction, index: usize) -> bool {
+        match transaction.kind {
+            TransactionKind::Transfer { destination, .. }
+                if destination == transaction.client_id() =>
+            {
+                // Transfer to itself is refused before its id is stored
+                false
+            }
+            TransactionKind::Deposit { .. }
+            | TransactionKind::Withdrawal { .. }
+            | TransactionKind::Transfer { .. }
+            | TransactionKind::Authorize { .. }
+            | TransactionKind::Freeze
+            | TransactionKind::Unlock
+            | TransactionKind::Close => self.owners.owner(transaction.tx_id(), index) != index,
+            _ => false,
+        }
+    }
+
+    /// Adds command to batch of shard, batch is sent once it is full
+    fn push(&mut self, index: usize, command: Command) -> Result<(), ShardError> {
+        self.shards[index].batch.push(command);
+
+        if self.shards[index].batch.len() >= BATCH_LEN {
+            self.flush(index)?;
+        }
+
+        Ok(())
+    }
+
+    fn flush(&mut self, index: usize) -> Result<(), ShardError> {
+        let shard = &mut self.shards[index];
+        let batch = mem::replace(&mut shard.batch, Vec::with_capacity(BATCH_LEN));
+
+        shard
+            .sender
+            .send(batch)
+            .map_err(|_| ShardError::Disconnected(index))
+    }
+
+    fn shard_of(&self, client_id: ClientID) -> usize {
+        client_id as usize % self.shards.len()
+    }
+}
+
+/// Shards which store transactions, ids are kept as compactly as by [`crate::CompactTransactionStore`]
+///
+/// Shard of every id is kept in a vector indexed by [`TransactionID`] as long as ids are dense
+/// (at least half of the slots are occupied), remaining ones fall back to a hash map.
+#[derive(Default)]
+struct Owners {
+    // Index of shard increased by one, zero marks unknown id
+    dense: Vec<u16>,
+    sparse: HashMap<TransactionID, u16>,
+    len: usize,
+}
+
+impl Owners {
+    /// Returns shard which stores given id, given shard becomes owner of id which is not known yet
+    fn owner(&mut self, tx_id: TransactionID, index: usize) -> usize {
+        let slot = tx_id as usize;
+
+        let known = match self.dense.get(slot) {
+            Some(owner) if *owner != 0 => Some(*owner),
+            // Id could be stored in hash map before dense storage has grown
+            _ => self.sparse.get(&tx_id).copied(),
+        };
+        if let Some(owner) = known {
+            return owner as usize - 1;
+        }
+
+        let owner = index as u16 + 1;
+        if slot < self.dense.len() {
+            self.dense[slot] = owner;
+        } else if slot < MIN_DENSE_LEN.max(2 * (self.len + 1)) {
+            self.dense.resize(slot + 1, 0);
+            self.dense[slot] = owner;
+        } else {
+            self.sparse.insert(tx_id, owner);
+        }
+        self.len += 1;
+
+        index
+    }
+}
+
+/// Processes commands of one shard until all of them are received, returns accounts of the shard
+fn run_shard<T, A>(
+    mut engine: Engine<T, A>,
+    receiver: Receiver<Vec<Command>>,
+    observer: &Observer,
+) -> Result<A, ShardError>
+where
+    T: transaction_store::Store,
+    A: account_store::Store,
+{
+    for command in receiver.into_iter().flatten() {
+        let (tx_id, outcome) = match command {
+            Command::Process(transaction) => {
+                (transaction.tx_id(), engine.process_transaction(transaction))
+            }
+            Command::Coordinated {
+                transaction,
+                other,
+                counterparts,
+            } => {
+                let tx_id = transaction.tx_id();
+                let mut stopped = false;
+
+                let outcome = engine.process_coordinated(transaction, &mut |counterpart| {
+                    let (reply, result) = mpsc::channel();
+                    counterparts
+                        .send((counterpart, reply))
+                        .ok()
+                        .and_then(|_| result.recv().ok())
+                        .unwrap_or_else(|| {
+                            // Outcome is discarded, the whole run fails with stopped shard
+                            stopped = true;
+                            Ok(())
+                        })
+                });
+
+                if stopped {
+                    return Err(ShardError::Disconnected(other));
+                }
+                (tx_id, outcome)
+            }
+            Command::Counterparts(requests) => {
+                for (counterpart, reply) in requests {
+                    let _ = reply.send(engine.apply_counterpart(counterpart));
+                }
+                continue;
+            }
+            Command::Duplicate(transaction) => {
+                (transaction.tx_id(), engine.process_duplicate(transaction))
+            }
+            Command::Advance(now) => {
+                engine.advance_clock(now)?;
+                continue;
+            }
+        };
+
+        observer(tx_id, &outcome);
+
+        if let ProcessOutcome::Failed(err) = outcome {
+            return Err(err.into());
+        }
+    }
+
+    Ok(engine.into_accounts())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+
+    use test_case::test_case;
+
+    use crate::core::{
+        account::{AccountError, Amount},
+        account_store::{AccountStore, Store},
+        compact_transaction_store::CompactTransactionStore,
+        engine::{Engine, ProcessOutcome},
+        expiry::ExpiryPolicy,
+        tests::positive,
+        transaction::{Transaction, TransactionKind},
+    };
+
+    use super::{Owners, ShardedEngine};
+
+    #[test_case(1 ; "single_shard")]
+    #[test_case(4 ; "four_shards")]
+    #[test_case(7 ; "seven_shards")]
+    fn sharded_engine_should_match_single_engine(shards: usize) {
+        let mut transactions = Vec::new();
+
+        for tx_id in 0..10_000 {
+            let client_id = (tx_id % 13) as u16;
+            let amount = positive(Amount::new(tx_id as i64 % 7 + 1, 0));
+
+            transactions.push(Transaction::new(
+                TransactionKind::Deposit { amount },
+                client_id,
+                tx_id,
+            ));
+            if tx_id % 3 == 0 {
+                transactions.push(Transaction::new(
+                    TransactionKind::Withdrawal { amount },
+                    client_id,
+                    tx_id + 100_000,
+                ));
+            }
+            if tx_id % 11 == 0 {
+                transactions.push(Transaction::new(
+                    TransactionKind::Dispute { amount: None },
+                    client_id,
+                    tx_id,
+                ));
+            }
+            if tx_id % 22 == 0 {
+                transactions.push(Transaction::new(
+                    TransactionKind::Chargeback { amount: None },
+                    client_id,
+                    tx_id,
+                ));
+            }
+            if tx_id % 2 == 0 {
+                transactions.push(Transaction::new(
+                    TransactionKind::Transfer {
+                        amount: positive(Amount::ONE),
+                        destination: (client_id + (tx_id % 5) as u16 + 1) % 13,
+                    },
+                    client_id,
+                    tx_id + 200_000,
+                ));
+            }
+            if tx_id % 8 == 0 {
+                transactions.push(Transaction::new(
+                    TransactionKind::Dispute { amount: None },
+                    client_id,
+                    tx_id + 200_000,
+                ));
+                let kind = if tx_id % 16 == 0 {
+                    TransactionKind::Chargeback { amount: None }
+                } else {
+                    TransactionKind::Resolve { amount: None }
+                };
+                transactions.push(Transaction::new(kind, client_id, tx_id + 200_000));
+            }
+        }
+
+        let mut single = Engine::<CompactTransactionStore, AccountStore>::default();
+        let processed = Arc::new(AtomicUsize::new(0));
+        let counter = processed.clone();
+        let mut sharded = ShardedEngine::new(
+            shards,
+            Engine::<CompactTransactionStore, AccountStore>::default,
+            move |_, _| {
+                counter.fetch_add(1, Ordering::Relaxed);
+            },
+        );
+
+        for transaction in transactions.iter() {
+            single.process_transaction(transaction.clone());
+            sharded.process_transaction(transaction.clone()).unwrap();
+        }
+
+        let accounts: AccountStore = sharded.finish().unwrap();
+
+        assert_eq!(accounts.accounts(), single.accounts());
+        assert_eq!(processed.load(Ordering::Relaxed), transactions.len());
+    }
+
+    #[test]
+    fn observer_should_receive_outcomes_in_client_order() {
+        let outcomes = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let collected = outcomes.clone();
+
+        let mut sharded: ShardedEngine<AccountStore> = ShardedEngine::new(
+            2,
+            Engine::<CompactTransactionStore, AccountStore>::default,
+            move |tx_id, outcome: &ProcessOutcome| {
+                collected.lock().unwrap().push((tx_id, outcome.clone()));
+            },
+        );
+
+        let amount = positive(Amount::ONE);
+        sharded
+            .process_transaction(Transaction::new(TransactionKind::Deposit { amount }, 1, 1))
+            .unwrap();
+        sharded
+            .process_transaction(Transaction::new(
+                TransactionKind::Withdrawal { amount },
+                1,
+                2,
+            ))
+            .unwrap();
+        sharded
+            .process_transaction(Transaction::new(
+                TransactionKind::Withdrawal { amount },
+                1,
+                3,
+            ))
+            .unwrap();
+
+        let accounts = sharded.finish().unwrap();
+
+        assert_eq!(accounts.accounts()[&1].total(), Amount::ZERO);
+        assert_eq!(
+            outcomes.lock().unwrap().last().map(|(tx_id, _)| *tx_id),
+            Some(3)
+        );
+        assert!(matches!(
+            outcomes.lock().unwrap().last(),
+            Some((_, ProcessOutcome::Rejected(_)))
+        ));
+    }
+
+    fn deposit(client_id: u16, tx_id: u32, amount: Amount) -> Transaction {
+        Transaction::new(
+            TransactionKind::Deposit {
+                amount: positive(amount),
+            },
+            client_id,
+            tx_id,
+        )
+    }
+
+    fn transfer(client_id: u16, tx_id: u32, destination: u16) -> Transaction {
+        Transaction::new(
+            TransactionKind::Transfer {
+                amount: positive(Amount::ONE),
+                destination,
+            },
+            client_id,
+            tx_id,
+        )
+    }
+
+    #[test]
+    fn transfer_should_be_applied_within_and_across_shards() {
+        let mut sharded: ShardedEngine<AccountStore> = ShardedEngine::new(
+            2,
+            Engine::<CompactTransactionStore, AccountStore>::default,
+            |_, _| {},
+        );
+
+        sharded
+            .process_transaction(deposit(1, 1, Amount::TWO))
+            .unwrap();
+        sharded.process_transaction(transfer(1, 2, 3)).unwrap();
+        sharded.process_transaction(transfer(1, 3, 2)).unwrap();
+        // Transferred funds can be spent by destination right away
+        sharded
+            .process_transaction(Transaction::new(
+                TransactionKind::Withdrawal {
+                    amount: positive(Amount::ONE),
+                },
+                2,
+                4,
+            ))
+            .unwrap();
+
+        let accounts = sharded.finish().unwrap();
+
+        assert_eq!(accounts.accounts()[&1].total(), Amount::ZERO);
+        assert_eq!(accounts.accounts()[&2].total(), Amount::ZERO);
+        assert_eq!(accounts.accounts()[&3].total(), Amount::ONE);
+    }
+
+    #[test]
+    fn transfer_across_shards_refused_by_destination_should_change_neither_account() {
+        let outcomes = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let collected = outcomes.clone();
+
+        let mut sharded: ShardedEngine<AccountStore> = ShardedEngine::new(
+            2,
+            Engine::<CompactTransactionStore, AccountStore>::default,
+            move |tx_id, outcome: &ProcessOutcome| {
+                collected.lock().unwrap().push((tx_id, outcome.clone()));
+            },
+        );
+
+        sharded
+            .process_transaction(deposit(1, 1, Amount::TWO))
+            .unwrap();
+        sharded
+            .process_transaction(deposit(2, 2, Amount::ONE))
+            .unwrap();
+        sharded
+            .process_transaction(Transaction::new(TransactionKind::Close, 2, 3))
+            .unwrap();
+        sharded.process_transaction(transfer(1, 4, 2)).unwrap();
+
+        let accounts = sharded.finish().unwrap();
+
+        assert_eq!(accounts.accounts()[&1].available(), Amount::TWO);
+        assert_eq!(accounts.accounts()[&2].available(), Amount::ZERO);
+        assert!(outcomes
+            .lock()
+            .unwrap()
+            .contains(&(4, ProcessOutcome::Rejected(AccountError::Closed))));
+    }
+
+    #[test]
+    fn dispute_of_transfer_across_shards_should_hold_funds_of_destination() {
+        let mut sharded: ShardedEngine<AccountStore> = ShardedEngine::new(
+            2,
+            Engine::<CompactTransactionStore, AccountStore>::default,
+            |_, _| {},
+        );
+
+        sharded
+            .process_transaction(deposit(1, 1, Amount::TWO))
+            .unwrap();
+        sharded.process_transaction(transfer(1, 2, 2)).unwrap();
+        sharded
+            .process_transaction(Transaction::new(
+                TransactionKind::Dispute { amount: None },
+                1,
+                2,
+            ))
+            .unwrap();
+
+        let accounts = sharded.finish().unwrap();
+
+        assert_eq!(accounts.accounts()[&1].available(), Amount::ONE);
+        assert_eq!(accounts.accounts()[&2].available(), Amount::ZERO);
+        assert_eq!(accounts.accounts()[&2].held(), Amount::ONE);
+    }
+
+    #[test]
+    fn id_reused_by_client_of_other_shard_should_be_duplicate() {
+        let outcomes = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let collected = outcomes.clone();
+
+        let mut sharded: ShardedEngine<AccountStore> = ShardedEngine::new(
+            2,
+            Engine::<CompactTransactionStore, AccountStore>::default,
+            move |tx_id, outcome: &ProcessOutcome| {
+                collected.lock().unwrap().push((tx_id, outcome.clone()));
+            },
+        );
+
+        sharded
+            .process_transaction(deposit(1, 1, Amount::ONE))
+            .unwrap();
+        sharded
+            .process_transaction(deposit(2, 1, Amount::ONE))
+            .unwrap();
+
+        let accounts = sharded.finish().unwrap();
+
+        assert_eq!(accounts.accounts()[&1].total(), Amount::ONE);
+        // Account is opened by duplicate as in a single engine
+        assert_eq!(accounts.accounts()[&2].total(), Amount::ZERO);
+        // Shards report independently, so outcomes of different clients have no order
+        let outcomes = outcomes.lock().unwrap();
+        assert_eq!(outcomes.len(), 2);
+        assert!(outcomes.contains(&(1, ProcessOutcome::Applied)));
+        assert!(outcomes.contains(&(1, ProcessOutcome::Duplicate)));
+    }
+
+    #[test]
+    fn hold_should_expire_on_later_transaction_of_other_shard() {
+        let mut sharded: ShardedEngine<AccountStore> = ShardedEngine::new(
+            2,
+            || {
+                let mut engine = Engine::<CompactTransactionStore, AccountStore>::default();
+                engine.set_expiry_policy(ExpiryPolicy {
+                    hold_expiry: Some(10),
+                    ..Default::default()
+                });
+                engine
+            },
+            |_, _| {},
+        );
+
+        sharded
+            .process_transaction(deposit(1, 1, Amount::TWO).with_timestamp(0))
+            .unwrap();
+        sharded
+            .process_transaction(
+                Transaction::new(
+                    TransactionKind::Authorize {
+                        amount: positive(Amount::ONE),
+                    },
+                    1,
+                    2,
+                )
+                .with_timestamp(0),
+            )
+            .unwrap();
+        sharded
+            .process_transaction(deposit(2, 3, Amount::ONE).with_timestamp(20))
+            .unwrap();
+
+        let accounts = sharded.finish().unwrap();
+
+        assert_eq!(accounts.accounts()[&1].available(), Amount::TWO);
+        assert_eq!(accounts.accounts()[&1].held(), Amount::ZERO);
+    }
+
+    #[test]
+    fn duplicate_with_later_timestamp_should_expire_hold_as_single_engine() {
+        let engine = || {
+            let mut engine = Engine::<CompactTransactionStore, AccountStore>::default();
+            engine.set_expiry_policy(ExpiryPolicy {
+                hold_expiry: Some(10),
+                ..Default::default()
+            });
+            engine
+        };
+        let transactions = [
+            deposit(1, 1, Amount::TEN).with_timestamp(0),
+            Transaction::new(
+                TransactionKind::Authorize {
+                    amount: positive(Amount::new(4, 0)),
+                },
+                1,
+                2,
+            )
+            .with_timestamp(0),
+            // Reuses id of deposit of client from other shard
+            deposit(2, 1, Amount::ONE).with_timestamp(100),
+        ];
+
+        let mut single = engine();
+        let mut sharded: ShardedEngine<AccountStore> = ShardedEngine::new(3, engine, |_, _| {});
+
+        for transaction in transactions {
+            single.process_transaction(transaction.clone());
+            sharded.process_transaction(transaction).unwrap();
+        }
+
+        let accounts = sharded.finish().unwrap();
+
+        assert_eq!(accounts.accounts(), single.accounts());
+        assert_eq!(accounts.accounts()[&1].available(), Amount::TEN);
+        assert_eq!(accounts.accounts()[&1].held(), Amount::ZERO);
+    }
+
+    #[test]
+    fn owners_should_keep_first_shard_of_dense_and_sparse_ids() {
+        let mut owners = Owners::default();
+
+        assert_eq!(owners.owner(1, 0), 0);
+        assert_eq!(owners.owner(1, 1), 0);
+        assert_eq!(owners.owner(u32::MAX, 2), 2);
+        assert_eq!(owners.owner(u32::MAX, 0), 2);
+        assert_eq!(owners.owner(2, 1), 1);
+
+        assert_eq!(owners.dense.len(), 3);
+        assert_eq!(owners.sparse.len(), 1);
+    }
+}
@@ -0,0 +1,347 @@
+use std::{
+    fs::File,
+    io::{self, BufReader, Read, Write},
+    path::Path,
+};
+
+use serde::{Deserialize, Serialize};
+
+use super::{
+    account::{Account, AccountStatus, Amount, ClientID, Lock},
+    atomic_file::AtomicFile,
+    transaction::{
+        DisputedAmount, Transaction, TransactionKind, TransactionMetadata, TransactionState,
+    },
+    transaction_store::StoreError,
+};
+
+/// Version of snapshot format, snapshots of version 1 are migrated on read and other versions are refused
+///
+/// Version 2 introduced status of accounts, version 1 only had locked flag.
+pub const SNAPSHOT_VERSION: u32 = 2;
+
+/// Version of snapshots taken before account statuses have been introduced
+const LOCKED_FLAG_VERSION: u32 = 1;
+
+#[derive(thiserror::Error, Debug)]
+pub enum SnapshotError {
+    #[error("Snapshot I/O error: {0}")]
+    Io(#[from] io::Error),
+    #[error("Snapshot is malformed: {0}")]
+    Malformed(#[from] serde_json::Error),
+    #[error("Snapshot version {0} is not supported, expected {SNAPSHOT_VERSION}")]
+    UnsupportedVersion(u32),
+    #[error("{0}")]
+    Store(#[from] StoreError),
+}
+
+/// Balances of user account, total is recalculated on restore
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+struct AccountSnapshot {
+    client: ClientID,
+    available: Amount,
+    held: Amount,
+    // Only read from snapshots of version 1, it is replaced by status during migration
+    #[serde(default, skip_serializing)]
+    locked: bool,
+    #[serde(default)]
+    status: AccountStatus,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    lock: Option<Lock>,
+}
+
+impl From<&Account> for AccountSnapshot {
+    fn from(account: &Account) -> Self {
+        Self {
+            client: account.id,
+            available: account.available,
+            held: account.held,
+            locked: account.is_locked(),
+            status: account.status,
+            lock: account.lock,
+        }
+    }
+}
+
+impl From<AccountSnapshot> for Account {
+    fn from(snapshot: AccountSnapshot) -> Self {
+        Self {
+            id: snapshot.client,
+            available: snapshot.available,
+            held: snapshot.held,
+            total: snapshot.available + snapshot.held,
+            status: snapshot.status,
+            // Credit lines come from configuration of engine, not from snapshot
+            credit_line: None,
+            lock: snapshot.lock,
+        }
+    }
+}
+
+/// Stored transaction together with its state
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct TransactionSnapshot {
+    #[serde(flatten)]
+    kind: TransactionKind,
+    #[serde(flatten)]
+    metadata: TransactionMetadata,
+    state: TransactionState,
+    #[serde(default, skip_serializing_if = "DisputedAmount::is_none")]
+    disputed: DisputedAmount,
+    #[serde(default, skip_serializing_if = "Amount::is_zero")]
+    captured: Amount,
+}
+
+impl From<Transaction> for TransactionSnapshot {
+    fn from(transaction: Transaction) -> Self {
+        Self {
+            kind: transaction.kind,
+            metadata: transaction.metadata,
+            state: transaction.state,
+            disputed: transaction.disputed,
+            captured: transaction.captured,
+        }
+    }
+}
+
+impl From<TransactionSnapshot> for Transaction {
+    fn from(snapshot: TransactionSnapshot) -> Self {
+        let mut transaction = Transaction::new(
+            snapshot.kind,
+            snapshot.metadata.client_id,
+            snapshot.metadata.tx_id,
+        );
+        transaction.metadata = snapshot.metadata;
+        transaction.state = snapshot.state;
+        transaction.disputed = snapshot.disputed;
+        transaction.captured = snapshot.captured;
+        transaction
+    }
+}
+
+/// State of [`crate::Engine`] at given point: user accounts and transactions which can be referenced later on
+///
+/// Snapshot is stored as versioned JSON, see [`crate::Engine::snapshot`] and [`crate::Engine::restore`].
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Snapshot {
+    version: u32,
+    /// Number of input rows processed when snapshot has been taken, it is set by caller
+    pub rows: u64,
+    /// Length of persisted transaction log when snapshot has been taken, see [`crate::Engine::checkpoint`]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub log_len: Option<u64>,
+    accounts: Vec<AccountSnapshot>,
+    transactions: Vec<TransactionSnapshot>,
+}
+
+impl Snapshot {
+    pub(crate) fn new<'a>(
+        accounts: impl Iterator<Item = &'a Account>,
+        transactions: impl Iterator<Item = Result<Transaction, StoreError>>,
+    ) -> Result<Self, StoreError> {
+        let mut accounts: Vec<AccountSnapshot> = accounts.map(AccountSnapshot::from).collect();
+        accounts.sort_by_key(|account| account.client);
+
+        let mut transactions = transactions
+            .map(|transaction| transaction.map(TransactionSnapshot::from))
+            .collect::<Result<Vec<_>, _>>()?;
+        transactions.sort_by_key(|transaction| transaction.metadata.tx_id);
+
+        Ok(Self {
+            version: SNAPSHOT_VERSION,
+            rows: 0,
+            log_len: None,
+            accounts,
+            transactions,
+        })
+    }
+
+    pub(crate) fn into_parts(self) -> (Vec<Account>, Vec<Transaction>) {
+        (
+            self.accounts.into_iter().map(Account::from).collect(),
+            self.transactions
+                .into_iter()
+                .map(Transaction::from)
+                .collect(),
+        )
+    }
+
+    /// Returns number of stored transactions in snapshot
+    pub fn transaction_count(&self) -> usize {
+        self.transactions.len()
+    }
+
+    /// Writes snapshot to given writer
+    pub fn write<W: Write>(&self, writer: W) -> Result<(), SnapshotError> {
+        serde_json::to_writer(writer, self)?;
+        Ok(())
+    }
+
+    /// Reads snapshot from given reader, snapshot of version 1 is migrated and other versions are refused
+    pub fn read<R: Read>(reader: R) -> Result<Self, SnapshotError> {
+        let mut snapshot: Self = serde_json::from_reader(reader)?;
+
+        match snapshot.version {
+            SNAPSHOT_VERSION => {}
+            LOCKED_FLAG_VERSION => snapshot.migrate_locked_flag(),
+            version => return Err(SnapshotError::UnsupportedVersion(version)),
+        }
+
+        Ok(snapshot)
+    }
+
+    /// Converts locked flag of version 1 to status, every other account is active
+    fn migrate_locked_flag(&mut self) {
+        for account in &mut self.accounts {
+            account.status = if account.locked {
+                AccountStatus::Locked
+            } else {
+                AccountStatus::Active
+            };
+        }
+        self.version = SNAPSHOT_VERSION;
+    }
+
+    /// Saves snapshot to file
+    ///
+    /// Snapshot is written through [`AtomicFile`], so crash during save never leaves broken snapshot
+    /// behind and concurrent saves to the same path don't share temporary file.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), SnapshotError> {
+        let mut file = AtomicFile::create(path.as_ref())?;
+        self.write(&mut file)?;
+        file.commit()?;
+
+        Ok(())
+    }
+
+    /// Loads snapshot from file
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, SnapshotError> {
+        Self::read(BufReader::new(File::open(path)?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::core::{
+        account::{Account, AccountStatus, Amount},
+        tests::{positive, transaction},
+        transaction::{TransactionKind, TransactionState},
+    };
+
+    use super::{Snapshot, SnapshotError};
+
+    #[test]
+    fn snapshot_should_survive_write_and_read() -> Result<(), SnapshotError> {
+        let account = Account {
+            id: 2,
+            available: Amount::new(23456, 5),
+            held: Amount::ONE,
+            total: Amount::new(123456, 5),
+            status: AccountStatus::Frozen,
+            credit_line: None,
+            lock: None,
+        };
+
+        let mut deposit = transaction(
+            TransactionKind::Deposit {
+                amount: positive(Amount::new(123456, 5)),
+            },
+            7,
+            2,
+        )
+        .with_timestamp(1_700_000_000);
+        deposit.state = TransactionState::Disputed;
+
+        let mut freeze = transaction(TransactionKind::Freeze, 8, 2);
+        freeze.state = TransactionState::Succeeded;
+
+        let snapshot = Snapshot::new(
+            [&account].into_iter(),
+            [Ok(deposit), Ok(freeze)].into_iter(),
+        )?;
+
+        let mut data = Vec::new();
+        snapshot.write(&mut data)?;
+
+        let (accounts, transactions) = Snapshot::read(data.as_slice())?.into_parts();
+
+        assert_eq!(accounts, vec![account]);
+        assert_eq!(transactions.len(), 2);
+        assert_eq!(transactions[0].tx_id(), 7);
+        assert_eq!(transactions[0].client_id(), 2);
+        assert_eq!(transactions[0].state, TransactionState::Disputed);
+        assert_eq!(transactions[0].timestamp(), Some(1_700_000_000));
+        assert_eq!(
+            transactions[0].kind,
+            TransactionKind::Deposit {
+                amount: positive(Amount::new(123456, 5))
+            }
+        );
+        assert_eq!(transactions[1].kind, TransactionKind::Freeze);
+        assert_eq!(transactions[1].state, TransactionState::Succeeded);
+
+        Ok(())
+    }
+
+    #[test]
+    fn saved_snapshot_should_replace_previous_one_without_temporary_files(
+    ) -> Result<(), SnapshotError> {
+        let directory = std::env::temp_dir().join(format!("snapshot-save-{}", std::process::id()));
+        std::fs::create_dir_all(&directory)?;
+        let path = directory.join("snapshot.json");
+        std::fs::write(&path, "previous")?;
+
+        let account = Account::new(1);
+        Snapshot::new([&account].into_iter(), [].into_iter())?.save(&path)?;
+        let (accounts, _) = Snapshot::load(&path)?.into_parts();
+        let entries: Vec<_> = std::fs::read_dir(&directory)?
+            .map(|entry| entry.map(|entry| entry.file_name()))
+            .collect::<Result<_, _>>()?;
+
+        assert_eq!(accounts, vec![account]);
+        assert_eq!(entries, ["snapshot.json"]);
+
+        std::fs::remove_dir_all(&directory).ok();
+
+        Ok(())
+    }
+
+    #[test]
+    fn snapshot_of_version_1_should_be_migrated() -> Result<(), SnapshotError> {
+        let data = concat!(
+            r#"{"version":1,"rows":0,"accounts":["#,
+            r#"{"client":1,"available":"1","held":"0","locked":true},"#,
+            r#"{"client":2,"available":"1","held":"0","locked":false}],"transactions":[]}"#
+        );
+
+        let (accounts, _) = Snapshot::read(data.as_bytes())?.into_parts();
+
+        assert_eq!(accounts[0].status(), AccountStatus::Locked);
+        assert_eq!(accounts[1].status(), AccountStatus::Active);
+
+        Ok(())
+    }
+
+    #[test]
+    fn locked_flag_should_be_ignored_in_current_version() -> Result<(), SnapshotError> {
+        let data = r#"{"version":2,"rows":0,"accounts":[{"client":1,"available":"1","held":"0","locked":true,"status":"frozen"}],"transactions":[]}"#;
+
+        let (accounts, _) = Snapshot::read(data.as_bytes())?.into_parts();
+
+        assert_eq!(accounts[0].status(), AccountStatus::Frozen);
+
+        Ok(())
+    }
+
+    #[test]
+    fn snapshot_of_other_version_should_be_refused() {
+        let data = r#"{"version":999,"rows":0,"accounts":[],"transactions":[]}"#;
+
+        let result = Snapshot::read(data.as_bytes());
+
+        assert!(matches!(
+            result,
+            Err(SnapshotError::UnsupportedVersion(999))
+        ));
+    }
+}
@@ -1,49 +1,496 @@
-use rust_decimal::Decimal;
-use serde::Deserialize;
+use std::fmt;
 
-use super::account::ClientID;
+use serde::{
+    de::{self, Visitor},
+    Deserialize, Deserializer, Serialize,
+};
+use serde_json::value::RawValue;
+
+use super::account::{Amount, ClientID};
 
 pub type TransactionID = u32;
 
+/// Time of transaction in seconds since Unix epoch
+pub type Timestamp = u64;
+
+/// Default maximal number of decimal places of transaction amount
+pub const DEFAULT_MAX_SCALE: u32 = 4;
+
+#[derive(thiserror::Error, Debug, PartialEq, Eq)]
+pub enum AmountError {
+    #[error("Amount {0} has to be greater than zero")]
+    NotPositive(Amount),
+    #[error("Amount {amount} exceeds {max_scale} decimal places")]
+    TooPrecise { amount: Amount, max_scale: u32 },
+}
+
+/// Amount of deposit or withdrawal, it is always greater than zero
+///
+/// Amount is deserialized from its text, so its sign and scale are checked on exact value.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(into = "Amount")]
+pub struct PositiveAmount(Amount);
+
+impl PositiveAmount {
+    pub fn new(amount: Amount) -> Result<Self, AmountError> {
+        if amount <= Amount::ZERO {
+            return Err(AmountError::NotPositive(amount));
+        }
+
+        Ok(Self(amount))
+    }
+
+    pub fn value(self) -> Amount {
+        self.0
+    }
+
+    /// Returns error when amount has more decimal places than allowed
+    pub fn validate_scale(self, max_scale: u32) -> Result<(), AmountError> {
+        // Trailing zeros do not change the value, 1.50000 is as precise as 1.5
+        if self.0.normalize().scale() > max_scale {
+            return Err(AmountError::TooPrecise {
+                amount: self.0,
+                max_scale,
+            });
+        }
+
+        Ok(())
+    }
+}
+
+impl TryFrom<Amount> for PositiveAmount {
+    type Error = AmountError;
+
+    fn try_from(amount: Amount) -> Result<Self, Self::Error> {
+        Self::new(amount)
+    }
+}
+
+impl From<PositiveAmount> for Amount {
+    fn from(amount: PositiveAmount) -> Self {
+        amount.0
+    }
+}
+
+impl<'de> Deserialize<'de> for PositiveAmount {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer
+            .deserialize_str(AmountVisitor)?
+            .ok_or_else(|| de::Error::custom("Missing amount"))
+            .and_then(|amount| Self::new(amount).map_err(de::Error::custom))
+    }
+}
+
+/// Reads amount from its text, number is never rounded through float as CSV would do when guessing type,
+/// empty text is treated as missing amount
+struct AmountVisitor;
+
+impl<'de> Visitor<'de> for AmountVisitor {
+    type Value = Option<Amount>;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("amount written as decimal string")
+    }
+
+    fn visit_str<E: de::Error>(self, text: &str) -> Result<Self::Value, E> {
+        let text = text.trim();
+
+        if text.is_empty() {
+            return Ok(None);
+        }
+
+        Amount::from_str_exact(text)
+            .map(Some)
+            .map_err(|_| E::custom(format!("Invalid amount {}", text)))
+    }
+
+    fn visit_none<E: de::Error>(self) -> Result<Self::Value, E> {
+        Ok(None)
+    }
+
+    fn visit_unit<E: de::Error>(self) -> Result<Self::Value, E> {
+        Ok(None)
+    }
+
+    fn visit_some<D>(self, deserializer: D) -> Result<Self::Value, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_str(self)
+    }
+}
+
+/// Deserializes optional field, empty field is treated as missing value
+fn optional<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    // Empty CSV field is read as empty string
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Field<T> {
+        Value(T),
+        Text(String),
+    }
+
+    match Option::<Field<T>>::deserialize(deserializer)? {
+        Some(Field::Value(value)) => Ok(Some(value)),
+        Some(Field::Text(text)) if text.trim().is_empty() => Ok(None),
+        Some(Field::Text(text)) => Err(de::Error::custom(format!("Invalid value {}", text))),
+        None => Ok(None),
+    }
+}
+
+/// Deserializes optional amount, empty field is treated as missing amount
+fn optional_amount<'de, D>(deserializer: D) -> Result<Option<PositiveAmount>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    deserializer
+        .deserialize_option(AmountVisitor)?
+        .map(PositiveAmount::new)
+        .transpose()
+        .map_err(de::Error::custom)
+}
+
+/// Parts of transaction amount affected by disputes
+///
+/// Resolved part is what is left from total once outstanding and charged back parts are subtracted.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct DisputedAmount {
+    /// Sum of all disputes, it never exceeds amount of transaction
+    pub total: Amount,
+    /// Part of disputes which is still held on user account
+    pub outstanding: Amount,
+    /// Part of disputes which has been charged back
+    pub charged_back: Amount,
+}
+
+impl DisputedAmount {
+    /// Returns disputed amount of transaction which has been disputed as a whole and is in given state
+    pub fn whole(amount: Amount, state: TransactionState) -> Self {
+        match state {
+            TransactionState::Disputed => Self {
+                total: amount,
+                outstanding: amount,
+                charged_back: Amount::ZERO,
+            },
+            TransactionState::Resolved => Self {
+                total: amount,
+                ..Default::default()
+            },
+            TransactionState::Chargeback => Self {
+                total: amount,
+                charged_back: amount,
+                ..Default::default()
+            },
+            _ => Self::default(),
+        }
+    }
+
+    pub fn resolved(&self) -> Amount {
+        self.total - self.outstanding - self.charged_back
+    }
+
+    /// Returns true if transaction has never been disputed
+    pub fn is_none(&self) -> bool {
+        self.total.is_zero()
+    }
+}
+
 /// Represents model of incoming transaction
-#[derive(Deserialize, Debug)]
+///
+/// It is deserialized from CSV row, JSON rows with numeric amounts are read by [`Transaction::from_json`].
+#[derive(Deserialize, Debug, Clone)]
+#[serde(try_from = "TransactionRecord<TextAmount>")]
 pub struct Transaction {
-    #[serde(flatten)]
     pub kind: TransactionKind,
-    #[serde(flatten)]
     pub metadata: TransactionMetadata,
-    #[serde(skip)]
     pub state: TransactionState,
+    pub disputed: DisputedAmount,
+    /// Part of authorization which has been already captured
+    pub captured: Amount,
+}
+
+/// Incoming transaction as it is written in CSV or JSON row
+///
+/// Row is read field by field instead of through tagged [`TransactionKind`], which would buffer the row
+/// and let CSV read amount as float.
+#[derive(Deserialize)]
+struct TransactionRecord<A> {
+    #[serde(rename = "type")]
+    kind: String,
+    client: ClientID,
+    tx: TransactionID,
+    #[serde(default)]
+    amount: A,
+    #[serde(default, deserialize_with = "optional")]
+    to: Option<ClientID>,
+    #[serde(default, deserialize_with = "optional")]
+    timestamp: Option<Timestamp>,
+}
+
+/// Amount read from text of CSV field or JSON string
+#[derive(Default)]
+struct TextAmount(Option<PositiveAmount>);
+
+impl<'de> Deserialize<'de> for TextAmount {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        optional_amount(deserializer).map(Self)
+    }
+}
+
+impl From<TextAmount> for Option<PositiveAmount> {
+    fn from(amount: TextAmount) -> Self {
+        amount.0
+    }
+}
+
+/// Amount of JSON row written either as string or as number
+///
+/// Number is read from its raw text with the same checks as string, so it is never rounded through float.
+#[derive(Default)]
+struct JsonAmount(Option<PositiveAmount>);
+
+impl<'de> Deserialize<'de> for JsonAmount {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let raw = match Option::<Box<RawValue>>::deserialize(deserializer)? {
+            Some(raw) => raw,
+            None => return Ok(Self(None)),
+        };
+
+        let amount = if raw.get().starts_with('"') {
+            optional_amount(&mut serde_json::Deserializer::from_str(raw.get()))
+                .map_err(de::Error::custom)?
+        } else {
+            AmountVisitor
+                .visit_str::<D::Error>(raw.get())?
+                .map(PositiveAmount::new)
+                .transpose()
+                .map_err(de::Error::custom)?
+        };
+
+        Ok(Self(amount))
+    }
+}
+
+impl From<JsonAmount> for Option<PositiveAmount> {
+    fn from(amount: JsonAmount) -> Self {
+        amount.0
+    }
+}
+
+/// Transaction read from JSON row, see [`Transaction::from_json`]
+#[derive(Deserialize)]
+#[serde(try_from = "TransactionRecord<JsonAmount>")]
+struct JsonTransaction(Transaction);
+
+impl TryFrom<TransactionRecord<JsonAmount>> for JsonTransaction {
+    type Error = String;
+
+    fn try_from(record: TransactionRecord<JsonAmount>) -> Result<Self, Self::Error> {
+        Transaction::try_from(record).map(Self)
+    }
+}
+
+impl<A> TryFrom<TransactionRecord<A>> for Transaction
+where
+    A: Into<Option<PositiveAmount>>,
+{
+    type Error = String;
+
+    fn try_from(record: TransactionRecord<A>) -> Result<Self, Self::Error> {
+        let amount = record.amount.into();
+        let required = || amount.ok_or_else(|| "missing field `amount`".to_string());
+
+        let kind = match record.kind.as_str() {
+            "deposit" => TransactionKind::Deposit {
+                amount: required()?,
+            },
+            "withdrawal" => TransactionKind::Withdrawal {
+                amount: required()?,
+            },
+            "transfer" => TransactionKind::Transfer {
+                amount: required()?,
+                destination: record.to.ok_or("missing field `to`")?,
+            },
+            "dispute" => TransactionKind::Dispute { amount },
+            "resolve" => TransactionKind::Resolve { amount },
+            "chargeback" => TransactionKind::Chargeback { amount },
+            "authorize" => TransactionKind::Authorize {
+                amount: required()?,
+            },
+            "capture" => TransactionKind::Capture { amount },
+            "void" => TransactionKind::Void,
+            "freeze" => TransactionKind::Freeze,
+            "unlock" => TransactionKind::Unlock,
+            "close" => TransactionKind::Close,
+            kind => return Err(format!("unknown transaction type `{}`", kind)),
+        };
+
+        let transaction = Transaction::new(kind, record.client, record.tx);
+
+        Ok(match record.timestamp {
+            Some(timestamp) => transaction.with_timestamp(timestamp),
+            None => transaction,
+        })
+    }
 }
 
 impl Transaction {
+    /// Reads transaction from JSON object, amount can be written either as string or as number
+    pub fn from_json(json: &str) -> Result<Self, serde_json::Error> {
+        serde_json::from_str::<JsonTransaction>(json).map(|transaction| transaction.0)
+    }
+
+    pub fn new(kind: TransactionKind, client_id: ClientID, tx_id: TransactionID) -> Self {
+        Self {
+            kind,
+            metadata: TransactionMetadata {
+                client_id,
+                tx_id,
+                timestamp: None,
+            },
+            state: Default::default(),
+            disputed: Default::default(),
+            captured: Default::default(),
+        }
+    }
+
     pub fn tx_id(&self) -> TransactionID {
         self.metadata.tx_id
     }
     pub fn client_id(&self) -> ClientID {
         self.metadata.client_id
     }
+    pub fn timestamp(&self) -> Option<Timestamp> {
+        self.metadata.timestamp
+    }
+
+    /// Returns transaction which happened at given time
+    pub fn with_timestamp(mut self, timestamp: Timestamp) -> Self {
+        self.metadata.timestamp = Some(timestamp);
+        self
+    }
+
+    /// Returns amount of deposit, withdrawal or transfer
+    pub fn amount(&self) -> Option<PositiveAmount> {
+        match self.kind {
+            TransactionKind::Deposit { amount }
+            | TransactionKind::Withdrawal { amount }
+            | TransactionKind::Transfer { amount, .. } => Some(amount),
+            _ => None,
+        }
+    }
+
+    /// Validates amount of transaction against given number of decimal places
+    pub fn validate(&self, max_scale: u32) -> Result<(), AmountError> {
+        let amount = match self.kind {
+            TransactionKind::Dispute { amount }
+            | TransactionKind::Resolve { amount }
+            | TransactionKind::Chargeback { amount }
+            | TransactionKind::Capture { amount } => amount,
+            TransactionKind::Authorize { amount } => Some(amount),
+            _ => self.amount(),
+        };
+
+        match amount {
+            Some(amount) => amount.validate_scale(max_scale),
+            None => Ok(()),
+        }
+    }
 }
 
 /// Determinates type of transaction
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
 #[serde(rename_all = "lowercase")]
 #[serde(tag = "type")]
 pub enum TransactionKind {
-    Deposit { amount: Decimal },
-    Withdrawal { amount: Decimal },
-    Dispute,
-    Resolve,
-    Chargeback,
+    Deposit {
+        amount: PositiveAmount,
+    },
+    Withdrawal {
+        amount: PositiveAmount,
+    },
+    /// Moves funds from client of transaction to destination client
+    Transfer {
+        amount: PositiveAmount,
+        #[serde(rename = "to")]
+        destination: ClientID,
+    },
+    /// Disputes given part of referenced transaction, whole amount when it is not set
+    Dispute {
+        #[serde(default, deserialize_with = "optional_amount")]
+        amount: Option<PositiveAmount>,
+    },
+    /// Resolves given part of disputed amount, everything what is still disputed when it is not set
+    Resolve {
+        #[serde(default, deserialize_with = "optional_amount")]
+        amount: Option<PositiveAmount>,
+    },
+    /// Charges back given part of disputed amount, everything what is still disputed when it is not set
+    Chargeback {
+        #[serde(default, deserialize_with = "optional_amount")]
+        amount: Option<PositiveAmount>,
+    },
+    /// Holds funds of user account until they are captured or released
+    Authorize {
+        amount: PositiveAmount,
+    },
+    /// Settles given part of referenced authorization, everything what is still held when it is not set
+    Capture {
+        #[serde(default, deserialize_with = "optional_amount")]
+        amount: Option<PositiveAmount>,
+    },
+    /// Releases remaining hold of referenced authorization
+    Void,
+    /// Freezes user account, it accepts only incoming credits until it is unlocked
+    Freeze,
+    /// Moves frozen or locked user account back to active status
+    Unlock,
+    /// Pays out available funds of user account and closes it
+    Close,
+    /// Reverts effect of referenced transaction, it is issued by [`crate::Engine::revert`]
+    #[serde(skip_deserializing)]
+    Revert,
+    /// Releases remaining hold of expired authorization, it is issued by [`crate::Engine`]
+    #[serde(skip_deserializing)]
+    Expire,
+}
+
+impl TransactionKind {
+    /// Returns true for administrative change of existing user account, i.e. freeze, unlock or close
+    pub fn is_administrative(&self) -> bool {
+        matches!(
+            self,
+            TransactionKind::Freeze | TransactionKind::Unlock | TransactionKind::Close
+        )
+    }
 }
 
-/// Metadata keeps client and transaction ids
-#[derive(Deserialize, Debug)]
+/// Metadata keeps client and transaction ids, timestamp is optional
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
 pub struct TransactionMetadata {
     #[serde(rename = "client")]
     pub client_id: ClientID,
     #[serde(rename = "tx")]
     pub tx_id: TransactionID,
+    #[serde(
+        default,
+        deserialize_with = "optional",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub timestamp: Option<Timestamp>,
 }
 
 /// States of transaction
@@ -53,18 +500,289 @@ pub struct TransactionMetadata {
 /// Disputed - transaction is being disputed
 /// Resolved - dispute has been resolved
 /// Chargeback - transaction has been chargedback
-#[derive(Debug, PartialEq, Eq)]
+/// Reverted - effect of transaction has been reverted
+/// Captured - authorization has been fully captured
+/// Voided - remaining hold of authorization has been released
+///
+/// Legal transitions:
+/// ```text
+///                 ┌──► Captured
+///                 ├──► Voided
+/// New ──► Succeeded ──► Disputed ◄─► Resolved
+///  │                       │
+///  ▼                       ▼
+/// Failed               Chargeback
+/// ```
+///
+/// Resolved transaction can be disputed again only for part of its amount which has not been disputed yet.
+/// Succeeded, Disputed, Resolved and Chargeback transactions can be moved to Reverted.
+#[derive(Debug, Default, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
 pub enum TransactionState {
+    #[default]
     New,
     Succeeded,
     Failed,
     Disputed,
     Resolved,
     Chargeback,
+    Reverted,
+    Captured,
+    Voided,
+}
+
+#[derive(thiserror::Error, Debug, Clone, Copy, PartialEq, Eq)]
+#[error("Transaction can't move from {from:?} to {to:?} state")]
+pub struct TransitionError {
+    pub from: TransactionState,
+    pub to: TransactionState,
 }
 
-impl Default for TransactionState {
-    fn default() -> Self {
-        TransactionState::New
+impl TransactionState {
+    /// Returns true if transaction in current state can be moved to the next one
+    pub fn can_transition_to(self, next: TransactionState) -> bool {
+        use TransactionState::*;
+
+        matches!(
+            (self, next),
+            (New, Succeeded)
+                | (New, Failed)
+                | (Succeeded, Disputed)
+                | (Disputed, Resolved)
+                | (Resolved, Disputed)
+                | (Disputed, Chargeback)
+                | (Succeeded, Captured)
+                | (Succeeded, Voided)
+                | (Succeeded | Disputed | Resolved | Chargeback, Reverted)
+        )
+    }
+
+    /// Returns next state if transition is legal
+    pub fn transition(self, next: TransactionState) -> Result<TransactionState, TransitionError> {
+        if self.can_transition_to(next) {
+            Ok(next)
+        } else {
+            Err(TransitionError {
+                from: self,
+                to: next,
+            })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use test_case::test_case;
+
+    use super::{
+        AmountError, PositiveAmount, Transaction, TransactionKind,
+        TransactionState::{self, *},
+    };
+    use crate::core::account::Amount;
+
+    fn read_transaction(data: &str) -> Result<Transaction, csv::Error> {
+        csv::ReaderBuilder::new()
+            .flexible(true)
+            .trim(csv::Trim::All)
+            .from_reader(data.as_bytes())
+            .deserialize()
+            .next()
+            .unwrap()
+    }
+
+    #[test_case("deposit,1,1,-1.0" ; "negative_deposit")]
+    #[test_case("withdrawal,1,1,-1.0" ; "negative_withdrawal")]
+    #[test_case("deposit,1,1,0" ; "zero_deposit")]
+    #[test_case("withdrawal,1,1,0.0000" ; "zero_withdrawal")]
+    fn non_positive_amount_should_be_rejected(row: &str) {
+        let result = read_transaction(&format!("type,client,tx,amount\n{}", row));
+
+        let err = result.unwrap_err();
+        assert!(err.to_string().contains("greater than zero"));
+        assert_eq!(err.position().map(|p| p.line()), Some(2));
+    }
+
+    #[test]
+    fn transfer_should_be_read_with_destination() -> Result<(), csv::Error> {
+        let transaction = read_transaction("type,client,tx,amount,to\ntransfer,1,7,1.5,2")?;
+
+        assert_eq!(transaction.client_id(), 1);
+        assert_eq!(
+            transaction.kind,
+            TransactionKind::Transfer {
+                amount: PositiveAmount::new(Amount::new(15, 1)).unwrap(),
+                destination: 2
+            }
+        );
+
+        Ok(())
+    }
+
+    #[test_case("dispute,1,7,", None ; "dispute_without_amount")]
+    #[test_case("dispute,1,7,1.5", Some(Amount::new(15, 1)) ; "partial_dispute")]
+    fn dispute_should_be_read_with_optional_amount(row: &str, amount: Option<Amount>) {
+        let transaction = read_transaction(&format!("type,client,tx,amount\n{}", row)).unwrap();
+
+        assert_eq!(
+            transaction.kind,
+            TransactionKind::Dispute {
+                amount: amount.map(|amount| PositiveAmount::new(amount).unwrap())
+            }
+        );
+    }
+
+    #[test_case("dispute,1,7,-1.5" ; "negative_amount")]
+    #[test_case("resolve,1,7,abc" ; "invalid_amount")]
+    fn invalid_dispute_amount_should_be_rejected(row: &str) {
+        let result = read_transaction(&format!("type,client,tx,amount\n{}", row));
+
+        assert!(result.is_err());
+    }
+
+    #[test_case("type,client,tx,amount\ndeposit,1,7,1.5", None ; "without_timestamp_column")]
+    #[test_case("type,client,tx,amount,timestamp\ndeposit,1,7,1.5,", None ; "empty_timestamp")]
+    #[test_case("type,client,tx,amount,timestamp\ndeposit,1,7,1.5,1700000000", Some(1_700_000_000) ; "timestamp")]
+    #[test_case("type,client,tx,amount,timestamp\ndispute,1,7,,1700000000", Some(1_700_000_000) ; "dispute_with_timestamp")]
+    fn transaction_should_be_read_with_optional_timestamp(data: &str, timestamp: Option<u64>) {
+        let transaction = read_transaction(data).unwrap();
+
+        assert_eq!(transaction.timestamp(), timestamp);
+    }
+
+    #[test]
+    fn invalid_timestamp_should_be_rejected() {
+        let result = read_transaction("type,client,tx,amount,timestamp\ndeposit,1,7,1.5,yesterday");
+
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn transfer_without_destination_should_be_rejected() {
+        let result = read_transaction("type,client,tx,amount\ntransfer,1,7,1.5");
+
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn positive_amount_should_be_accepted() -> Result<(), csv::Error> {
+        let transaction = read_transaction("type,client,tx,amount\ndeposit,1,1,1.5")?;
+
+        assert!(transaction.validate(4).is_ok());
+
+        Ok(())
+    }
+
+    #[test]
+    fn large_amount_should_be_read_exactly() -> Result<(), csv::Error> {
+        let transaction =
+            read_transaction("type,client,tx,amount\ndeposit,1,1,12345678901234.5678")?;
+
+        assert_eq!(
+            transaction.amount().map(PositiveAmount::value),
+            Some(Amount::new(123456789012345678, 4))
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn large_amount_should_be_checked_for_scale_before_rounding() -> Result<(), csv::Error> {
+        let transaction =
+            read_transaction("type,client,tx,amount\ndeposit,1,1,12345678901234.56789")?;
+
+        assert!(transaction.validate(4).is_err());
+
+        Ok(())
+    }
+
+    #[test_case(r#"{"type":"deposit","client":1,"tx":1,"amount":"12345678901234.5678"}"# ; "string")]
+    #[test_case(r#"{"type":"deposit","client":1,"tx":1,"amount":12345678901234.5678}"# ; "number")]
+    fn json_amount_should_be_read_exactly(line: &str) {
+        let transaction = Transaction::from_json(line).unwrap();
+
+        assert_eq!(
+            transaction.amount().map(PositiveAmount::value),
+            Some(Amount::new(123456789012345678, 4))
+        );
+    }
+
+    #[test_case(r#"{"type":"deposit","client":1,"tx":1,"amount":12345678901234.56789}"# ; "number")]
+    #[test_case(r#"{"type":"deposit","client":1,"tx":1,"amount":"12345678901234.56789"}"# ; "string")]
+    fn json_amount_with_too_many_decimals_should_fail_validation(line: &str) {
+        let transaction = Transaction::from_json(line).unwrap();
+
+        assert!(transaction.validate(4).is_err());
+    }
+
+    #[test_case(r#"{"type":"deposit","client":1,"tx":1,"amount":-1.5}"# ; "negative")]
+    #[test_case(r#"{"type":"deposit","client":1,"tx":1,"amount":0}"# ; "zero")]
+    #[test_case(r#"{"type":"deposit","client":1,"tx":1,"amount":0.0}"# ; "zero_with_decimals")]
+    #[test_case(r#"{"type":"deposit","client":1,"tx":1,"amount":1e3}"# ; "exponent")]
+    #[test_case(r#"{"type":"deposit","client":1,"tx":1,"amount":true}"# ; "boolean")]
+    fn invalid_json_amount_should_be_refused(line: &str) {
+        assert!(Transaction::from_json(line).is_err());
+    }
+
+    #[test_case(r#"{"type":"dispute","client":1,"tx":1}"# ; "missing")]
+    #[test_case(r#"{"type":"dispute","client":1,"tx":1,"amount":null}"# ; "null")]
+    #[test_case(r#"{"type":"dispute","client":1,"tx":1,"amount":""}"# ; "empty")]
+    fn missing_json_amount_should_be_read_as_none(line: &str) {
+        let transaction = Transaction::from_json(line).unwrap();
+
+        assert_eq!(transaction.kind, TransactionKind::Dispute { amount: None });
+    }
+
+    #[test_case(Amount::new(12345, 4), 4, true ; "scale_within_limit")]
+    #[test_case(Amount::new(150000, 5), 4, true ; "trailing_zeros_are_ignored")]
+    #[test_case(Amount::new(123456, 5), 4, false ; "scale_over_limit")]
+    #[test_case(Amount::new(15, 1), 0, false ; "fraction_when_no_decimals_allowed")]
+    fn scale_validation(amount: Amount, max_scale: u32, valid: bool) {
+        let amount = PositiveAmount::new(amount).unwrap();
+
+        assert_eq!(amount.validate_scale(max_scale).is_ok(), valid);
+    }
+
+    #[test]
+    fn too_precise_error_should_contain_limit() {
+        let amount = PositiveAmount::new(Amount::new(123456, 5)).unwrap();
+
+        assert_eq!(
+            amount.validate_scale(4),
+            Err(AmountError::TooPrecise {
+                amount: Amount::new(123456, 5),
+                max_scale: 4
+            })
+        );
+    }
+
+    #[test_case(New, Succeeded ; "new_to_succeeded")]
+    #[test_case(New, Failed ; "new_to_failed")]
+    #[test_case(Succeeded, Disputed ; "succeeded_to_disputed")]
+    #[test_case(Disputed, Resolved ; "disputed_to_resolved")]
+    #[test_case(Disputed, Chargeback ; "disputed_to_chargeback")]
+    #[test_case(Resolved, Disputed ; "resolved_to_disputed")]
+    #[test_case(Succeeded, Reverted ; "succeeded_to_reverted")]
+    #[test_case(Chargeback, Reverted ; "chargeback_to_reverted")]
+    #[test_case(Succeeded, Captured ; "succeeded_to_captured")]
+    #[test_case(Succeeded, Voided ; "succeeded_to_voided")]
+    fn legal_transition(from: TransactionState, to: TransactionState) {
+        assert_eq!(from.transition(to), Ok(to));
+    }
+
+    #[test_case(New, Disputed ; "new_to_disputed")]
+    #[test_case(Failed, Disputed ; "failed_to_disputed")]
+    #[test_case(Disputed, Disputed ; "disputed_to_disputed")]
+    #[test_case(Chargeback, Disputed ; "chargeback_to_disputed")]
+    #[test_case(Succeeded, Resolved ; "succeeded_to_resolved")]
+    #[test_case(Succeeded, Chargeback ; "succeeded_to_chargeback")]
+    #[test_case(Resolved, Chargeback ; "resolved_to_chargeback")]
+    #[test_case(Succeeded, Failed ; "succeeded_to_failed")]
+    #[test_case(Failed, Reverted ; "failed_to_reverted")]
+    #[test_case(Reverted, Reverted ; "reverted_to_reverted")]
+    #[test_case(Reverted, Disputed ; "reverted_to_disputed")]
+    #[test_case(Captured, Voided ; "captured_to_voided")]
+    #[test_case(Voided, Captured ; "voided_to_captured")]
+    fn illegal_transition(from: TransactionState, to: TransactionState) {
+        assert!(from.transition(to).is_err());
     }
 }
@@ -1,59 +1,356 @@
 use super::{
-    account::AccountDelta,
-    transaction::{Transaction, TransactionID, TransactionKind, TransactionState},
-    transaction_store::Store,
+    account::{AccountDelta, AccountStatus, Amount, ClientID},
+    transaction::{
+        DisputedAmount, PositiveAmount, Timestamp, Transaction, TransactionID, TransactionKind,
+        TransactionState, TransitionError,
+    },
+    transaction_store::{Store, StoreError},
 };
 
+/// Reasons for which transaction can't be turned into [`AccountDelta`]
+#[derive(thiserror::Error, Debug, Clone, PartialEq, Eq)]
+pub enum TransactionError {
+    #[error("Referenced transaction does not exist")]
+    UnknownTransaction,
+    #[error("Referenced transaction belongs to other client")]
+    ClientMismatch,
+    #[error("Referenced transaction can't be disputed")]
+    NotDisputable,
+    #[error("Referenced transaction is too old to be disputed")]
+    DisputeWindowExpired,
+    #[error("Referenced transaction can't be reverted")]
+    NotRevertible,
+    #[error("Transfer source and destination are the same client")]
+    SelfTransfer,
+    #[error("Amount exceeds {0} which can be disputed, resolved or charged back")]
+    AmountExceeded(Amount),
+    #[error("Referenced transaction is not an authorization")]
+    NotAuthorization,
+    #[error("Amount exceeds {0} which is still authorized")]
+    CaptureExceeded(Amount),
+    #[error("{0}")]
+    InvalidTransition(#[from] TransitionError),
+    #[error("Transaction with the same id already exists")]
+    Duplicate,
+    #[error("Transaction has been already processed")]
+    Replayed,
+    #[error("{0}")]
+    Store(#[from] StoreError),
+}
+
+/// Change of transaction storage which should be committed only when delta has been applied to user account
+#[must_use]
+#[derive(Debug)]
+pub enum PendingTransition {
+    /// New transaction needs to be stored
+    Insert(Transaction),
+    /// Already stored transaction moves to given state, tracked amount is changed only when it is set
+    Update {
+        tx_id: TransactionID,
+        state: TransactionState,
+        tracked: Option<TrackedAmount>,
+    },
+}
+
+/// Amount of stored transaction which changes together with its state
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TrackedAmount {
+    Disputed(DisputedAmount),
+    Captured(Amount),
+}
+
+/// Change of other user account which has to be applied together with delta of proposal, e.g. transfer destination
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Counterpart {
+    pub client_id: ClientID,
+    pub delta: AccountDelta,
+}
+
+/// Outcome of transaction processing, nothing is persisted until transition is committed
+#[must_use]
+#[derive(Debug)]
+pub struct Proposal {
+    pub delta: AccountDelta,
+    pub counterpart: Option<Counterpart>,
+    pub transition: PendingTransition,
+}
+
+impl Proposal {
+    fn insert(delta: AccountDelta, transaction: Transaction) -> Self {
+        Self {
+            delta,
+            counterpart: None,
+            transition: PendingTransition::Insert(transaction),
+        }
+    }
+
+    fn update(
+        delta: AccountDelta,
+        tx_id: TransactionID,
+        state: TransactionState,
+        tracked: Option<TrackedAmount>,
+    ) -> Self {
+        Self {
+            delta,
+            counterpart: None,
+            transition: PendingTransition::Update {
+                tx_id,
+                state,
+                tracked,
+            },
+        }
+    }
+
+    fn status(status: AccountStatus, transaction: Transaction) -> Self {
+        Self::insert(AccountDelta::status(status), transaction)
+    }
+
+    fn with_counterpart(self, client_id: ClientID, delta: AccountDelta) -> Self {
+        Self {
+            counterpart: Some(Counterpart { client_id, delta }),
+            ..self
+        }
+    }
+}
+
 /// Processes transactions and produces delta of user balance as a outcome of transaction
+///
+/// Processing is split into two phases, [`TransactionProcessor::produce_delta`] only proposes changes
+/// and [`TransactionProcessor::commit`] or [`TransactionProcessor::rollback`] needs to be invoked
+/// depending on whether delta has been applied to user account.
 #[derive(Default)]
 pub struct TransactionProcessor<S>
 where
-    S: Store + Default,
+    S: Store,
 {
     transactions: S,
+    // Exact replays of already processed transactions are accepted as no-ops
+    idempotent: bool,
+    // Maximal age of transaction which can be disputed, in seconds
+    dispute_window: Option<Timestamp>,
 }
 
 impl<S> TransactionProcessor<S>
 where
-    S: Store + Default,
+    S: Store,
 {
+    pub fn new(transactions: S) -> Self {
+        Self {
+            transactions,
+            idempotent: false,
+            dispute_window: None,
+        }
+    }
+
+    /// Enables or disables idempotent mode
+    ///
+    /// In idempotent mode transaction which is an exact replay (same id, kind, amount and client)
+    /// of already stored transaction is reported as [`TransactionError::Replayed`] instead of [`TransactionError::Duplicate`].
+    pub fn set_idempotent(&mut self, idempotent: bool) {
+        self.idempotent = idempotent;
+    }
+
+    /// Sets maximal age of transaction which can be disputed, in seconds
+    ///
+    /// Dispute which happened later than given window after referenced transaction is refused
+    /// with [`TransactionError::DisputeWindowExpired`]. Window is not enforced when either
+    /// of transactions has no timestamp.
+    pub fn set_dispute_window(&mut self, dispute_window: Option<Timestamp>) {
+        self.dispute_window = dispute_window;
+    }
+
+    /// Makes storage keep timestamps of transactions, see [`Store::keep_timestamps`]
+    pub fn keep_timestamps(&mut self) {
+        self.transactions.keep_timestamps();
+    }
+
     /// Returns delta of balance based on transaction thats should be applied to user account
-    pub fn produce_delta(&mut self, transaction: Transaction) -> AccountDelta {
+    /// together with pending transition of transaction storage
+    pub fn produce_delta(&self, transaction: Transaction) -> Result<Proposal, TransactionError> {
         match transaction.kind {
             TransactionKind::Deposit { amount } => {
-                self.transactions.insert(transaction);
-                AccountDelta::deposit(amount)
+                self.check_duplicate(&transaction)?;
+                Ok(Proposal::insert(
+                    AccountDelta::deposit(amount.into()),
+                    transaction,
+                ))
             }
             TransactionKind::Withdrawal { amount } => {
-                self.transactions.insert(transaction);
-                AccountDelta::withdrawal(amount)
+                self.check_duplicate(&transaction)?;
+                Ok(Proposal::insert(
+                    AccountDelta::withdrawal(amount.into()),
+                    transaction,
+                ))
+            }
+            TransactionKind::Transfer {
+                amount,
+                destination,
+            } => {
+                if destination == transaction.client_id() {
+                    return Err(TransactionError::SelfTransfer);
+                }
+                self.check_duplicate(&transaction)?;
+                Ok(
+                    Proposal::insert(AccountDelta::withdrawal(amount.into()), transaction)
+                        .with_counterpart(destination, AccountDelta::deposit(amount.into())),
+                )
+            }
+
+            TransactionKind::Authorize { amount } => {
+                self.check_duplicate(&transaction)?;
+                Ok(Proposal::insert(
+                    AccountDelta::authorize(amount.into()),
+                    transaction,
+                ))
             }
 
-            TransactionKind::Dispute => self.dispute(&transaction),
-            TransactionKind::Resolve => self.resolve(&transaction),
-            TransactionKind::Chargeback => self.chargeback(&transaction),
+            // Payout of closed account is added by engine, as balance of account is not known here
+            TransactionKind::Freeze => {
+                self.check_duplicate(&transaction)?;
+                Ok(Proposal::status(AccountStatus::Frozen, transaction))
+            }
+            TransactionKind::Unlock => {
+                self.check_duplicate(&transaction)?;
+                Ok(Proposal::status(AccountStatus::Active, transaction))
+            }
+            TransactionKind::Close => {
+                self.check_duplicate(&transaction)?;
+                Ok(Proposal::status(AccountStatus::Closed, transaction))
+            }
+
+            TransactionKind::Dispute { amount } => self.dispute(&transaction, amount),
+            TransactionKind::Resolve { amount } => self.resolve(&transaction, amount),
+            TransactionKind::Chargeback { amount } => self.chargeback(&transaction, amount),
+            TransactionKind::Capture { amount } => self.capture(&transaction, amount),
+            TransactionKind::Void | TransactionKind::Expire => self.void(&transaction),
+            TransactionKind::Revert => self.revert(&transaction),
         }
     }
 
-    pub fn succeed(&mut self, tx_id: TransactionID) {
-        self.set_state(tx_id, TransactionState::Succeeded)
+    /// Returns stored transaction with given id
+    pub fn get(&self, tx_id: &TransactionID) -> Result<Option<Transaction>, StoreError> {
+        self.transactions.get(tx_id)
     }
 
-    pub fn failed(&mut self, tx_id: TransactionID) {
-        self.set_state(tx_id, TransactionState::Failed)
+    /// Returns iterator over all stored transactions
+    pub fn iter(&self) -> Box<dyn Iterator<Item = Result<Transaction, StoreError>> + '_> {
+        self.transactions.iter()
     }
 
-    fn set_state(&mut self, tx_id: TransactionID, state: TransactionState) {
-        if let Some(tx) = self.transactions.get_mut(&tx_id) {
-            // New transaction can either succes or fail, can't be anything else as it is not fully processed yet.
-            if tx.state == TransactionState::New {
-                tx.state = state;
+    /// Persists stored transactions, see [`Store::sync`]
+    pub fn sync(&mut self) -> Result<Option<u64>, StoreError> {
+        self.transactions.sync()
+    }
+
+    /// Stores transaction as it is, state of already stored transaction is overwritten
+    ///
+    /// Transaction which is already stored in the same state is left untouched, so restoring into
+    /// persisted storage doesn't write it again.
+    pub fn restore(&mut self, transaction: Transaction) -> Result<(), StoreError> {
+        let tx_id = transaction.tx_id();
+        let state = transaction.state;
+        let disputed = transaction.disputed;
+        let captured = transaction.captured;
+
+        match self.transactions.get(&tx_id)? {
+            Some(stored)
+                if stored.state == state
+                    && stored.disputed == disputed
+                    && stored.captured == captured =>
+            {
+                return Ok(())
+            }
+            Some(_) => self.transactions.set_state(&tx_id, state)?,
+            None => self.transactions.insert(transaction)?,
+        }
+
+        // Tracked amounts are stored separately from transaction
+        if !disputed.is_none() {
+            self.transactions.set_disputed(&tx_id, state, disputed)?;
+        }
+        if !captured.is_zero() {
+            self.transactions.set_captured(&tx_id, state, captured)?;
+        }
+
+        Ok(())
+    }
+
+    /// Persists transition, should be invoked once delta has been applied to user account
+    pub fn commit(&mut self, transition: PendingTransition) -> Result<(), StoreError> {
+        match transition {
+            PendingTransition::Insert(mut transaction) => {
+                transaction.state = TransactionState::Succeeded;
+                self.transactions.insert(transaction)
+            }
+            PendingTransition::Update {
+                tx_id,
+                state,
+                tracked,
+            } => match tracked {
+                None => self.transactions.set_state(&tx_id, state),
+                Some(TrackedAmount::Disputed(disputed)) => {
+                    self.transactions.set_disputed(&tx_id, state, disputed)
+                }
+                Some(TrackedAmount::Captured(captured)) => {
+                    self.transactions.set_captured(&tx_id, state, captured)
+                }
+            },
+        }
+    }
+
+    /// Discards transition, should be invoked when user account rejected delta
+    ///
+    /// New transaction is still stored as [`TransactionState::Failed`] to keep track of used ids,
+    /// state of already stored transaction remains untouched.
+    pub fn rollback(&mut self, transition: PendingTransition) -> Result<(), StoreError> {
+        if let PendingTransition::Insert(mut transaction) = transition {
+            transaction.state = TransactionState::Failed;
+            self.transactions.insert(transaction)?;
+        }
+        Ok(())
+    }
+
+    /// Returns error if transaction with the same id has been already stored
+    fn check_duplicate(&self, transaction: &Transaction) -> Result<(), TransactionError> {
+        match self.transactions.get(&transaction.tx_id())? {
+            None => Ok(()),
+            Some(existing)
+                if self.idempotent
+                    && existing.kind == transaction.kind
+                    && existing.client_id() == transaction.client_id() =>
+            {
+                Err(TransactionError::Replayed)
+            }
+            Some(_) => Err(TransactionError::Duplicate),
+        }
+    }
+
+    /// Returns referenced transaction if it exists and belongs to the same client
+    fn referenced(&self, transaction: &Transaction) -> Result<Transaction, TransactionError> {
+        let mut referenced = self
+            .transactions
+            .get(&transaction.tx_id())?
+            .ok_or(TransactionError::UnknownTransaction)?;
+
+        if referenced.client_id() != transaction.client_id() {
+            return Err(TransactionError::ClientMismatch);
+        }
+
+        // Transactions stored before partial disputes were introduced have been always disputed as a whole
+        if referenced.disputed.is_none() {
+            if let Some(amount) = referenced.amount() {
+                referenced.disputed = DisputedAmount::whole(amount.into(), referenced.state);
             }
         }
+
+        Ok(referenced)
     }
 
     /// Returns delta for disputed transaction
-    /// Only deposit and withdrawal transaction can be disputed, for others delta is empty
+    /// Only deposit, withdrawal and transfer can be disputed, for others error is returned
+    ///
+    /// Transaction can be disputed several times as long as sum of disputes does not exceed its amount,
+    /// even after previous disputes have been resolved. Dispute without amount disputes the whole transaction.
     ///
     /// In case of dispute of deposit then following operation should be invoked:
     /// - Decrease available funds by disputed amount
@@ -62,541 +359,823 @@ where
     /// In case of dispute of withdrawal:
     /// - Increase held funds by disputed amount
     ///
-    /// [`TransactionState`] is set to [`TransactionState::Disputed`].
-    fn dispute(&mut self, disputed_transaction: &Transaction) -> AccountDelta {
-        if let Some(transaction) = self.transactions.get_mut(&disputed_transaction.tx_id()) {
-            if disputed_transaction.client_id() != transaction.client_id() {
-                return AccountDelta::none()
+    /// Transfer is disputed by its source client as a whole, transferred funds are held on destination account
+    /// the same way as disputed deposit.
+    ///
+    /// [`TransactionState`] is set to [`TransactionState::Disputed`] once committed.
+    fn dispute(
+        &self,
+        disputed_transaction: &Transaction,
+        amount: Option<PositiveAmount>,
+    ) -> Result<Proposal, TransactionError> {
+        let transaction = self.referenced(disputed_transaction)?;
+
+        if let (Some(window), Some(original), Some(disputed_at)) = (
+            self.dispute_window,
+            transaction.timestamp(),
+            disputed_transaction.timestamp(),
+        ) {
+            if disputed_at > original.saturating_add(window) {
+                return Err(TransactionError::DisputeWindowExpired);
             }
+        }
 
-            if transaction.state == TransactionState::Resolved
-                || transaction.state == TransactionState::Chargeback
-                || transaction.state == TransactionState::Disputed
-            {
-                return AccountDelta::none();
+        let original: Amount = transaction
+            .amount()
+            .ok_or(TransactionError::NotDisputable)?
+            .into();
+        let mut disputed = transaction.disputed;
+
+        let state = match transaction.state {
+            // Further dispute keeps transaction disputed
+            TransactionState::Disputed => TransactionState::Disputed,
+            // Resolved transaction can be disputed again only for part which has not been disputed yet
+            TransactionState::Resolved if disputed.total >= original => {
+                return Err(TransitionError {
+                    from: TransactionState::Resolved,
+                    to: TransactionState::Disputed,
+                }
+                .into())
             }
+            state => state.transition(TransactionState::Disputed)?,
+        };
 
-            match transaction.kind {
-                TransactionKind::Deposit { amount } => {
-                    transaction.state = TransactionState::Disputed;
-                    AccountDelta::dispute_deposit(amount)
-                }
-                TransactionKind::Withdrawal { amount } => {
-                    transaction.state = TransactionState::Disputed;
-                    AccountDelta::dispute_withdrawal(amount)
-                }
-                _ => AccountDelta::none(),
+        let amount = limited(
+            amount.map_or(original, Amount::from),
+            original - disputed.total,
+        )?;
+        disputed.total += amount;
+        disputed.outstanding += amount;
+
+        let proposal = match transaction.kind {
+            TransactionKind::Deposit { .. } => AccountDelta::dispute_deposit(amount),
+            TransactionKind::Withdrawal { .. } => AccountDelta::dispute_withdrawal(amount),
+            TransactionKind::Transfer { destination, .. } => {
+                return Ok(Proposal::update(
+                    AccountDelta::none(),
+                    transaction.tx_id(),
+                    state,
+                    Some(TrackedAmount::Disputed(disputed)),
+                )
+                .with_counterpart(destination, AccountDelta::dispute_deposit(amount)))
             }
-        } else {
-            AccountDelta::none()
-        }
+            _ => return Err(TransactionError::NotDisputable),
+        };
+
+        Ok(Proposal::update(
+            proposal,
+            transaction.tx_id(),
+            state,
+            Some(TrackedAmount::Disputed(disputed)),
+        ))
     }
 
     /// Returns delta for resolved transaction
-    /// Only deposit and withdrawal transaction can be resolved and their [`TransactionState`] needs to be set to [`TransactionState::Disputed`]
+    /// Only deposit, withdrawal and transfer can be resolved and their [`TransactionState`] needs to be set to [`TransactionState::Disputed`]
+    ///
+    /// Resolve without amount resolves everything what is still disputed, transaction remains disputed
+    /// until all disputes are resolved. Transaction which has been partially charged back ends up in
+    /// [`TransactionState::Chargeback`] once the rest is resolved.
     ///
     /// In case of dispute of deposit then following operation are invoked:
-    /// - Increase available funds by disputed amount
-    /// - Decrease held funds by disputed amount
+    /// - Increase available funds by resolved amount
+    /// - Decrease held funds by resolved amount
     ///
     /// In case of dispute of withdrawal:
-    /// - Increase available funds by disputed amount
-    /// - Decrease held funds by disputed amount
+    /// - Increase available funds by resolved amount
+    /// - Decrease held funds by resolved amount
     ///
-    fn resolve(&mut self, resolve_transaction: &Transaction) -> AccountDelta {
-        if let Some(transaction) = self.transactions.get_mut(&resolve_transaction.tx_id()) {
-            if resolve_transaction.client_id() != transaction.client_id() {
-                return AccountDelta::none()
-            }
-
-            if transaction.state != TransactionState::Disputed {
-                return AccountDelta::none();
-            }
-
-            match transaction.kind {
-                TransactionKind::Deposit { amount } | TransactionKind::Withdrawal { amount } => {
-                    transaction.state = TransactionState::Resolved;
-
-                    return AccountDelta::resolve(amount);
-                }
+    /// In case of dispute of transfer held funds are released on destination account.
+    ///
+    fn resolve(
+        &self,
+        resolve_transaction: &Transaction,
+        amount: Option<PositiveAmount>,
+    ) -> Result<Proposal, TransactionError> {
+        let transaction = self.referenced(resolve_transaction)?;
+
+        transaction.state.transition(TransactionState::Resolved)?;
+
+        let mut disputed = transaction.disputed;
+        let amount = limited(
+            amount.map_or(disputed.outstanding, Amount::from),
+            disputed.outstanding,
+        )?;
+        disputed.outstanding -= amount;
+
+        let state = if !disputed.outstanding.is_zero() {
+            TransactionState::Disputed
+        } else if disputed.charged_back.is_zero() {
+            TransactionState::Resolved
+        } else {
+            TransactionState::Chargeback
+        };
 
-                _ => return AccountDelta::none(),
+        match transaction.kind {
+            TransactionKind::Deposit { .. } | TransactionKind::Withdrawal { .. } => {
+                Ok(Proposal::update(
+                    AccountDelta::resolve(amount),
+                    transaction.tx_id(),
+                    state,
+                    Some(TrackedAmount::Disputed(disputed)),
+                ))
             }
+            TransactionKind::Transfer { destination, .. } => Ok(Proposal::update(
+                AccountDelta::none(),
+                transaction.tx_id(),
+                state,
+                Some(TrackedAmount::Disputed(disputed)),
+            )
+            .with_counterpart(destination, AccountDelta::resolve(amount))),
+
+            _ => Err(TransactionError::NotDisputable),
         }
-        AccountDelta::none()
     }
 
     /// Returns delta for chargeback transaction.
-    /// Only deposit and withdrawal transaction can be chargedback and their [`TransactionState`] needs to be set to [`TransactionState::Disputed`]
+    /// Only deposit, withdrawal and transfer can be chargedback and their [`TransactionState`] needs to be set to [`TransactionState::Disputed`]
     /// Held funds are being withdrawn and user account is immediately locked after this operation
     ///
-    fn chargeback(&mut self, chargeback_transaction: &Transaction) -> AccountDelta {
-        if let Some(transaction) = self.transactions.get_mut(&chargeback_transaction.tx_id()) {
-            if chargeback_transaction.client_id() != transaction.client_id() {
-                return AccountDelta::none()
+    /// Chargeback without amount charges back everything what is still disputed, otherwise remaining
+    /// part of disputes stays held and transaction remains disputed until it is resolved or charged back too.
+    ///
+    /// Chargeback of transfer withdraws held funds from destination account and locks it,
+    /// funds are returned to source account.
+    ///
+    fn chargeback(
+        &self,
+        chargeback_transaction: &Transaction,
+        amount: Option<PositiveAmount>,
+    ) -> Result<Proposal, TransactionError> {
+        let transaction = self.referenced(chargeback_transaction)?;
+
+        transaction.state.transition(TransactionState::Chargeback)?;
+
+        let mut disputed = transaction.disputed;
+        let amount = limited(
+            amount.map_or(disputed.outstanding, Amount::from),
+            disputed.outstanding,
+        )?;
+        disputed.outstanding -= amount;
+        disputed.charged_back += amount;
+
+        let state = if disputed.outstanding.is_zero() {
+            TransactionState::Chargeback
+        } else {
+            TransactionState::Disputed
+        };
+
+        match transaction.kind {
+            TransactionKind::Deposit { .. } | TransactionKind::Withdrawal { .. } => {
+                Ok(Proposal::update(
+                    AccountDelta::chargeback(transaction.tx_id(), amount),
+                    transaction.tx_id(),
+                    state,
+                    Some(TrackedAmount::Disputed(disputed)),
+                ))
             }
+            TransactionKind::Transfer { destination, .. } => Ok(Proposal::update(
+                AccountDelta::deposit(amount),
+                transaction.tx_id(),
+                state,
+                Some(TrackedAmount::Disputed(disputed)),
+            )
+            .with_counterpart(
+                destination,
+                AccountDelta::chargeback(transaction.tx_id(), amount),
+            )),
+
+            _ => Err(TransactionError::NotDisputable),
+        }
+    }
 
-            if transaction.state == TransactionState::Disputed {
-                let change = match transaction.kind {
-                    TransactionKind::Deposit { amount }
-                    | TransactionKind::Withdrawal { amount } => {
-                        transaction.state = TransactionState::Chargeback;
+    /// Returns delta for captured authorization
+    /// Captured amount is withdrawn from held funds, capture without amount captures everything what is still held.
+    ///
+    /// Authorization can be captured several times as long as sum of captures does not exceed it,
+    /// [`TransactionState`] is set to [`TransactionState::Captured`] once nothing is held anymore.
+    fn capture(
+        &self,
+        capture_transaction: &Transaction,
+        amount: Option<PositiveAmount>,
+    ) -> Result<Proposal, TransactionError> {
+        let (transaction, authorized) = self.authorization(capture_transaction)?;
+
+        transaction.state.transition(TransactionState::Captured)?;
+
+        let held = authorized - transaction.captured;
+        let amount = amount.map_or(held, Amount::from);
+        if amount > held {
+            return Err(TransactionError::CaptureExceeded(held));
+        }
+        let captured = transaction.captured + amount;
 
-                        AccountDelta::chargeback(amount)
-                    }
+        let state = if captured == authorized {
+            TransactionState::Captured
+        } else {
+            TransactionState::Succeeded
+        };
 
-                    _ => AccountDelta::none(),
-                };
-                return change;
-            }
+        Ok(Proposal::update(
+            AccountDelta::capture(amount),
+            transaction.tx_id(),
+            state,
+            Some(TrackedAmount::Captured(captured)),
+        ))
+    }
+
+    /// Returns delta for voided or expired authorization
+    /// Part of authorization which has not been captured is released back to available funds.
+    ///
+    /// [`TransactionState`] is set to [`TransactionState::Voided`] once committed.
+    fn void(&self, void_transaction: &Transaction) -> Result<Proposal, TransactionError> {
+        let (transaction, authorized) = self.authorization(void_transaction)?;
+
+        let state = transaction.state.transition(TransactionState::Voided)?;
+
+        Ok(Proposal::update(
+            AccountDelta::void(authorized - transaction.captured),
+            transaction.tx_id(),
+            state,
+            None,
+        ))
+    }
+
+    /// Returns referenced authorization together with its amount
+    fn authorization(
+        &self,
+        transaction: &Transaction,
+    ) -> Result<(Transaction, Amount), TransactionError> {
+        let transaction = self.referenced(transaction)?;
+
+        match transaction.kind {
+            TransactionKind::Authorize { amount } => Ok((transaction, amount.into())),
+            _ => Err(TransactionError::NotAuthorization),
         }
-        AccountDelta::none()
     }
+
+    /// Returns delta which reverts effect of referenced transaction
+    /// Only deposit and withdrawal transaction can be reverted, effect includes every step of dispute
+    /// which transaction went through, so reverted chargeback releases its lock of user account.
+    ///
+    /// [`TransactionState`] is set to [`TransactionState::Reverted`] once committed.
+    fn revert(&self, revert_transaction: &Transaction) -> Result<Proposal, TransactionError> {
+        let transaction = self.referenced(revert_transaction)?;
+
+        let state = transaction.state.transition(TransactionState::Reverted)?;
+
+        let disputed = transaction.disputed;
+        let (posting, dispute) = match transaction.kind {
+            TransactionKind::Deposit { amount } => (
+                AccountDelta::deposit(amount.into()),
+                AccountDelta::dispute_deposit(disputed.total),
+            ),
+            TransactionKind::Withdrawal { amount } => (
+                AccountDelta::withdrawal(amount.into()),
+                AccountDelta::dispute_withdrawal(disputed.total),
+            ),
+            _ => return Err(TransactionError::NotRevertible),
+        };
+
+        let disputed_posting = posting
+            .merge(dispute)
+            .merge(AccountDelta::resolve(disputed.resolved()));
+
+        let applied = match transaction.state {
+            // Partially charged back transaction remains disputed
+            TransactionState::Disputed if !disputed.charged_back.is_zero() => disputed_posting
+                .merge(AccountDelta::chargeback(
+                    transaction.tx_id(),
+                    disputed.charged_back,
+                )),
+            TransactionState::Disputed | TransactionState::Resolved => disputed_posting,
+            TransactionState::Chargeback => disputed_posting.merge(AccountDelta::chargeback(
+                transaction.tx_id(),
+                disputed.charged_back,
+            )),
+            _ => posting,
+        };
+
+        Ok(Proposal::update(
+            applied.inverse(),
+            transaction.tx_id(),
+            state,
+            None,
+        ))
+    }
+}
+
+/// Returns amount if it does not exceed given limit
+fn limited(amount: Amount, limit: Amount) -> Result<Amount, TransactionError> {
+    if amount > limit {
+        return Err(TransactionError::AmountExceeded(limit));
+    }
+
+    Ok(amount)
 }
 
 #[cfg(test)]
 mod tests {
-    use super::TransactionProcessor;
+    use test_case::test_case;
+
+    use super::{Counterpart, TransactionError, TransactionProcessor};
     use crate::core::{
-        account::Amount, tests::transaction, transaction, transaction_store::TransactionStore,
+        account::{AccountDelta, AccountStatus, Amount, LockChange},
+        tests::{positive, transaction},
+        transaction::{
+            self, DisputedAmount, Transaction,
+            TransactionState::{self, *},
+            TransitionError,
+        },
+        transaction_store::{Store, TransactionStore},
     };
 
+    // Helper for creating expected transition error
+    fn invalid_transition(from: TransactionState, to: TransactionState) -> TransactionError {
+        TransactionError::InvalidTransition(TransitionError { from, to })
+    }
+
+    // Helper which produces delta and commits transition as if delta has been applied
+    fn produce_and_commit(
+        processor: &mut TransactionProcessor<TransactionStore>,
+        transaction: Transaction,
+    ) -> Result<AccountDelta, TransactionError> {
+        let proposal = processor.produce_delta(transaction)?;
+        processor.commit(proposal.transition)?;
+        Ok(proposal.delta)
+    }
+
     #[test]
-    fn deposit_should_create_deposit_change() {
+    fn deposit_should_create_deposit_change() -> Result<(), TransactionError> {
         let mut processor = TransactionProcessor::<TransactionStore>::default();
 
         let transaction = transaction(
             transaction::TransactionKind::Deposit {
-                amount: Amount::new(3, 1),
+                amount: positive(Amount::new(3, 1)),
             },
             1,
             1,
         );
 
-        let change = processor.produce_delta(transaction);
+        let change = produce_and_commit(&mut processor, transaction)?;
 
         assert_eq!(change.available.unwrap_or_default(), Amount::new(3, 1));
+
+        Ok(())
     }
 
     #[test]
-    fn withdraw_should_create_widthdraw_change() {
+    fn withdraw_should_create_widthdraw_change() -> Result<(), TransactionError> {
         let mut processor = TransactionProcessor::<TransactionStore>::default();
 
         let transaction = transaction(
             transaction::TransactionKind::Withdrawal {
-                amount: Amount::new(5, 1),
+                amount: positive(Amount::new(5, 1)),
             },
             1,
             1,
         );
 
-        let change = processor.produce_delta(transaction);
+        let change = produce_and_commit(&mut processor, transaction)?;
 
         assert_eq!(change.available.unwrap_or_default(), Amount::new(-5, 1));
+
+        Ok(())
     }
 
     #[test]
     fn dispute_on_not_existing_transaction_should_do_nothing() {
-        let mut processor = TransactionProcessor::<TransactionStore>::default();
+        let processor = TransactionProcessor::<TransactionStore>::default();
 
-        let transaction = transaction(transaction::TransactionKind::Dispute, 1, 1);
+        let transaction = transaction(transaction::TransactionKind::Dispute { amount: None }, 1, 1);
 
         let change = processor.produce_delta(transaction);
 
-        assert!(change.available.is_none());
-        assert!(change.held.is_none());
-        assert!(change.locked.is_none());
+        assert_eq!(change.err(), Some(TransactionError::UnknownTransaction));
     }
 
     #[test]
-    fn dispute_on_deposit_transaction_should_incr_held_funds_and_decr_available() {
+    fn dispute_on_deposit_transaction_should_incr_held_funds_and_decr_available(
+    ) -> Result<(), TransactionError> {
         let mut processor = TransactionProcessor::<TransactionStore>::default();
 
         let deposit = transaction(
             transaction::TransactionKind::Deposit {
-                amount: Amount::new(3, 1),
+                amount: positive(Amount::new(3, 1)),
             },
             1,
             1,
         );
 
-        let deposit_change = processor.produce_delta(deposit);
+        let deposit_change = produce_and_commit(&mut processor, deposit)?;
         assert_eq!(
             deposit_change.available.unwrap_or_default(),
             Amount::new(3, 1)
         );
         assert!(deposit_change.held.is_none());
-        assert!(deposit_change.locked.is_none());
+        assert!(deposit_change.status.is_none());
 
-        let dispute = transaction(transaction::TransactionKind::Dispute, 1, 1);
-        let dispute_change = processor.produce_delta(dispute);
+        let dispute = transaction(transaction::TransactionKind::Dispute { amount: None }, 1, 1);
+        let dispute_change = produce_and_commit(&mut processor, dispute)?;
 
         assert_eq!(
             dispute_change.available.unwrap_or_default(),
             Amount::new(-3, 1)
         );
         assert_eq!(dispute_change.held.unwrap_or_default(), Amount::new(3, 1));
-        assert!(dispute_change.locked.is_none());
+        assert!(dispute_change.status.is_none());
+
+        Ok(())
     }
 
     #[test]
-    fn dispute_on_incorrect_client_transaction_should_do_nothing() {
+    fn dispute_on_incorrect_client_transaction_should_do_nothing() -> Result<(), TransactionError> {
         let mut processor = TransactionProcessor::<TransactionStore>::default();
 
         let deposit = transaction(
             transaction::TransactionKind::Deposit {
-                amount: Amount::new(3, 1),
+                amount: positive(Amount::new(3, 1)),
             },
             1,
             1,
         );
 
-        let deposit_change = processor.produce_delta(deposit);
+        let deposit_change = produce_and_commit(&mut processor, deposit)?;
         assert_eq!(
             deposit_change.available.unwrap_or_default(),
             Amount::new(3, 1)
         );
         assert!(deposit_change.held.is_none());
-        assert!(deposit_change.locked.is_none());
+        assert!(deposit_change.status.is_none());
 
-        let dispute = transaction(transaction::TransactionKind::Dispute, 1, 2);
+        let dispute = transaction(transaction::TransactionKind::Dispute { amount: None }, 1, 2);
         let dispute_change = processor.produce_delta(dispute);
 
-        assert!(
-            dispute_change.available.is_none(),
-        );
-        assert!(dispute_change.held.is_none());
-        assert!(dispute_change.locked.is_none());
-    }
+        assert_eq!(dispute_change.err(), Some(TransactionError::ClientMismatch));
 
+        Ok(())
+    }
 
     #[test]
-    fn dispute_on_withdrawal_transaction_should_increase_held_funds() {
+    fn dispute_on_withdrawal_transaction_should_increase_held_funds() -> Result<(), TransactionError>
+    {
         let mut processor = TransactionProcessor::<TransactionStore>::default();
 
         let withdrawal = transaction(
             transaction::TransactionKind::Withdrawal {
-                amount: Amount::new(3, 1),
+                amount: positive(Amount::new(3, 1)),
             },
             1,
             1,
         );
 
-        let withdrawal_change = processor.produce_delta(withdrawal);
+        let withdrawal_change = produce_and_commit(&mut processor, withdrawal)?;
         assert_eq!(
             withdrawal_change.available.unwrap_or_default(),
             Amount::new(-3, 1)
         );
         assert!(withdrawal_change.held.is_none());
-        assert!(withdrawal_change.locked.is_none());
+        assert!(withdrawal_change.status.is_none());
 
-        let dispute = transaction(transaction::TransactionKind::Dispute, 1, 1);
-        let dispute_change = processor.produce_delta(dispute);
+        let dispute = transaction(transaction::TransactionKind::Dispute { amount: None }, 1, 1);
+        let dispute_change = produce_and_commit(&mut processor, dispute)?;
 
         assert!(dispute_change.available.is_none());
         assert_eq!(dispute_change.held.unwrap_or_default(), Amount::new(3, 1));
-        assert!(dispute_change.locked.is_none());
+        assert!(dispute_change.status.is_none());
+
+        Ok(())
     }
 
     #[test]
     fn resolve_on_not_existing_transaction_should_do_nothing() {
-        let mut processor = TransactionProcessor::<TransactionStore>::default();
+        let processor = TransactionProcessor::<TransactionStore>::default();
 
-        let transaction = transaction(transaction::TransactionKind::Resolve, 1, 1);
+        let transaction = transaction(transaction::TransactionKind::Resolve { amount: None }, 1, 1);
 
         let change = processor.produce_delta(transaction);
 
-        assert!(change.available.is_none());
-        assert!(change.held.is_none());
-        assert!(change.locked.is_none());
+        assert_eq!(change.err(), Some(TransactionError::UnknownTransaction));
     }
 
     #[test]
-    fn resolve_on_not_disputed_transaction_should_do_nothing() {
+    fn resolve_on_not_disputed_transaction_should_do_nothing() -> Result<(), TransactionError> {
         let mut processor = TransactionProcessor::<TransactionStore>::default();
 
         let withdrawal = transaction(
             transaction::TransactionKind::Withdrawal {
-                amount: Amount::new(3, 1),
+                amount: positive(Amount::new(3, 1)),
             },
             1,
             1,
         );
 
-        let withdrawal_change = processor.produce_delta(withdrawal);
+        let withdrawal_change = produce_and_commit(&mut processor, withdrawal)?;
         assert_eq!(
             withdrawal_change.available.unwrap_or_default(),
             Amount::new(-3, 1)
         );
         assert!(withdrawal_change.held.is_none());
-        assert!(withdrawal_change.locked.is_none());
+        assert!(withdrawal_change.status.is_none());
 
-        let resolve = transaction(transaction::TransactionKind::Resolve, 1, 1);
+        let resolve = transaction(transaction::TransactionKind::Resolve { amount: None }, 1, 1);
         let resolve_change = processor.produce_delta(resolve);
 
-        assert!(resolve_change.available.is_none());
-        assert!(resolve_change.held.is_none());
-        assert!(resolve_change.locked.is_none());
+        assert_eq!(
+            resolve_change.err(),
+            Some(invalid_transition(Succeeded, Resolved))
+        );
+
+        Ok(())
     }
 
     #[test]
-    fn resolve_on_incorrect_client_transaction_should_do_nothing() {
+    fn resolve_on_incorrect_client_transaction_should_do_nothing() -> Result<(), TransactionError> {
         let mut processor = TransactionProcessor::<TransactionStore>::default();
 
         let deposit = transaction(
             transaction::TransactionKind::Deposit {
-                amount: Amount::new(3, 1),
+                amount: positive(Amount::new(3, 1)),
             },
             1,
             1,
         );
 
-        let deposit_change = processor.produce_delta(deposit);
+        let deposit_change = produce_and_commit(&mut processor, deposit)?;
         assert_eq!(
             deposit_change.available.unwrap_or_default(),
             Amount::new(3, 1)
         );
         assert!(deposit_change.held.is_none());
-        assert!(deposit_change.locked.is_none());
+        assert!(deposit_change.status.is_none());
 
-        let resolve = transaction(transaction::TransactionKind::Resolve, 1, 2);
+        let resolve = transaction(transaction::TransactionKind::Resolve { amount: None }, 1, 2);
         let resolve_change = processor.produce_delta(resolve);
 
-        assert!(
-            resolve_change.available.is_none(),
-        );
-        assert!(resolve_change.held.is_none());
-        assert!(resolve_change.locked.is_none());
-    }
+        assert_eq!(resolve_change.err(), Some(TransactionError::ClientMismatch));
 
+        Ok(())
+    }
 
     #[test]
-    fn resolve_on_dispute_of_deposit_transaction_should_increase_available_funds_and_decr_held() {
+    fn resolve_on_dispute_of_deposit_transaction_should_increase_available_funds_and_decr_held(
+    ) -> Result<(), TransactionError> {
         let mut processor = TransactionProcessor::<TransactionStore>::default();
 
         let deposit = transaction(
             transaction::TransactionKind::Deposit {
-                amount: Amount::new(3, 1),
+                amount: positive(Amount::new(3, 1)),
             },
             1,
             1,
         );
-        let deposit_change = processor.produce_delta(deposit);
+        let deposit_change = produce_and_commit(&mut processor, deposit)?;
 
         assert_eq!(
             deposit_change.available.unwrap_or_default(),
             Amount::new(3, 1)
         );
         assert!(deposit_change.held.is_none());
-        assert!(deposit_change.locked.is_none());
+        assert!(deposit_change.status.is_none());
 
-        let dispute = transaction(transaction::TransactionKind::Dispute, 1, 1);
-        let dispute_change = processor.produce_delta(dispute);
+        let dispute = transaction(transaction::TransactionKind::Dispute { amount: None }, 1, 1);
+        let dispute_change = produce_and_commit(&mut processor, dispute)?;
 
         assert_eq!(
             dispute_change.available.unwrap_or_default(),
             Amount::new(-3, 1)
         );
         assert_eq!(dispute_change.held.unwrap_or_default(), Amount::new(3, 1));
-        assert!(dispute_change.locked.is_none());
+        assert!(dispute_change.status.is_none());
 
-        let resolve = transaction(transaction::TransactionKind::Resolve, 1, 1);
-        let resolve_change = processor.produce_delta(resolve);
+        let resolve = transaction(transaction::TransactionKind::Resolve { amount: None }, 1, 1);
+        let resolve_change = produce_and_commit(&mut processor, resolve)?;
 
         assert_eq!(
             resolve_change.available.unwrap_or_default(),
             Amount::new(3, 1)
         );
         assert_eq!(resolve_change.held.unwrap_or_default(), Amount::new(-3, 1));
-        assert!(dispute_change.locked.is_none());
+        assert!(dispute_change.status.is_none());
+
+        Ok(())
     }
 
     #[test]
-    fn resolve_on_dispute_of_withdrawal_transaction_should_increase_available_funds_and_decr_held()
-    {
+    fn resolve_on_dispute_of_withdrawal_transaction_should_increase_available_funds_and_decr_held(
+    ) -> Result<(), TransactionError> {
         let mut processor = TransactionProcessor::<TransactionStore>::default();
 
         let withdrawal = transaction(
             transaction::TransactionKind::Withdrawal {
-                amount: Amount::new(3, 1),
+                amount: positive(Amount::new(3, 1)),
             },
             1,
             1,
         );
-        let withdrawal_change = processor.produce_delta(withdrawal);
+        let withdrawal_change = produce_and_commit(&mut processor, withdrawal)?;
 
         assert_eq!(
             withdrawal_change.available.unwrap_or_default(),
             Amount::new(-3, 1)
         );
         assert!(withdrawal_change.held.is_none());
-        assert!(withdrawal_change.locked.is_none());
+        assert!(withdrawal_change.status.is_none());
 
-        let dispute = transaction(transaction::TransactionKind::Dispute, 1, 1);
-        let dispute_change = processor.produce_delta(dispute);
+        let dispute = transaction(transaction::TransactionKind::Dispute { amount: None }, 1, 1);
+        let dispute_change = produce_and_commit(&mut processor, dispute)?;
 
         assert!(dispute_change.available.is_none());
         assert_eq!(dispute_change.held.unwrap_or_default(), Amount::new(3, 1));
-        assert!(dispute_change.locked.is_none());
+        assert!(dispute_change.status.is_none());
 
-        let resolve = transaction(transaction::TransactionKind::Resolve, 1, 1);
-        let resolve_change = processor.produce_delta(resolve);
+        let resolve = transaction(transaction::TransactionKind::Resolve { amount: None }, 1, 1);
+        let resolve_change = produce_and_commit(&mut processor, resolve)?;
 
         assert_eq!(
             resolve_change.available.unwrap_or_default(),
             Amount::new(3, 1)
         );
         assert_eq!(resolve_change.held.unwrap_or_default(), Amount::new(-3, 1));
-        assert!(dispute_change.locked.is_none());
+        assert!(dispute_change.status.is_none());
+
+        Ok(())
     }
 
     #[test]
-    fn resolve_on_resolved_dispute_of_deposit_transaction_should_do_nothing() {
+    fn resolve_on_resolved_dispute_of_deposit_transaction_should_do_nothing(
+    ) -> Result<(), TransactionError> {
         let mut processor = TransactionProcessor::<TransactionStore>::default();
 
         let deposit = transaction(
             transaction::TransactionKind::Deposit {
-                amount: Amount::new(3, 1),
+                amount: positive(Amount::new(3, 1)),
             },
             1,
             1,
         );
-        let deposit_change = processor.produce_delta(deposit);
+        let deposit_change = produce_and_commit(&mut processor, deposit)?;
 
         assert_eq!(
             deposit_change.available.unwrap_or_default(),
             Amount::new(3, 1)
         );
         assert!(deposit_change.held.is_none());
-        assert!(deposit_change.locked.is_none());
+        assert!(deposit_change.status.is_none());
 
-        let dispute = transaction(transaction::TransactionKind::Dispute, 1, 1);
-        let dispute_change = processor.produce_delta(dispute);
+        let dispute = transaction(transaction::TransactionKind::Dispute { amount: None }, 1, 1);
+        let dispute_change = produce_and_commit(&mut processor, dispute)?;
 
         assert_eq!(
             dispute_change.available.unwrap_or_default(),
             Amount::new(-3, 1)
         );
         assert_eq!(dispute_change.held.unwrap_or_default(), Amount::new(3, 1));
-        assert!(dispute_change.locked.is_none());
+        assert!(dispute_change.status.is_none());
 
-        let resolve = transaction(transaction::TransactionKind::Resolve, 1, 1);
-        let resolve_change = processor.produce_delta(resolve);
+        let resolve = transaction(transaction::TransactionKind::Resolve { amount: None }, 1, 1);
+        let resolve_change = produce_and_commit(&mut processor, resolve)?;
 
         assert_eq!(
             resolve_change.available.unwrap_or_default(),
             Amount::new(3, 1)
         );
         assert_eq!(resolve_change.held.unwrap_or_default(), Amount::new(-3, 1));
-        assert!(dispute_change.locked.is_none());
+        assert!(dispute_change.status.is_none());
 
-        let dispute2 = transaction(transaction::TransactionKind::Dispute, 1, 1);
+        let dispute2 = transaction(transaction::TransactionKind::Dispute { amount: None }, 1, 1);
         let dispute2_change = processor.produce_delta(dispute2);
 
-        assert!(dispute2_change.available.is_none());
-        assert!(dispute2_change.held.is_none());
-        assert!(dispute2_change.locked.is_none());
+        assert_eq!(
+            dispute2_change.err(),
+            Some(invalid_transition(Resolved, Disputed))
+        );
+
+        Ok(())
     }
 
     #[test]
     fn chargeback_on_not_existing_transaction_should_do_nothing() {
-        let mut processor = TransactionProcessor::<TransactionStore>::default();
+        let processor = TransactionProcessor::<TransactionStore>::default();
 
-        let chargeback = transaction(transaction::TransactionKind::Chargeback, 1, 1);
+        let chargeback = transaction(
+            transaction::TransactionKind::Chargeback { amount: None },
+            1,
+            1,
+        );
         let chargeback_change = processor.produce_delta(chargeback);
 
-        assert!(chargeback_change.available.is_none());
-        assert!(chargeback_change.held.is_none());
-        assert!(chargeback_change.locked.is_none());
+        assert_eq!(
+            chargeback_change.err(),
+            Some(TransactionError::UnknownTransaction)
+        );
     }
 
     #[test]
-    fn chargeback_on_not_disputed_transaction_should_do_nothing() {
+    fn chargeback_on_not_disputed_transaction_should_do_nothing() -> Result<(), TransactionError> {
         let mut processor = TransactionProcessor::<TransactionStore>::default();
 
         let withdrawal = transaction(
             transaction::TransactionKind::Withdrawal {
-                amount: Amount::new(3, 1),
+                amount: positive(Amount::new(3, 1)),
             },
             1,
             1,
         );
 
-        let withdrawal_change = processor.produce_delta(withdrawal);
+        let withdrawal_change = produce_and_commit(&mut processor, withdrawal)?;
         assert_eq!(
             withdrawal_change.available.unwrap_or_default(),
             Amount::new(-3, 1)
         );
         assert!(withdrawal_change.held.is_none());
-        assert!(withdrawal_change.locked.is_none());
+        assert!(withdrawal_change.status.is_none());
 
-        let chargeback = transaction(transaction::TransactionKind::Chargeback, 1, 1);
+        let chargeback = transaction(
+            transaction::TransactionKind::Chargeback { amount: None },
+            1,
+            1,
+        );
         let chargeback_change = processor.produce_delta(chargeback);
 
-        assert!(chargeback_change.available.is_none());
-        assert!(chargeback_change.held.is_none());
-        assert!(chargeback_change.locked.is_none());
+        assert_eq!(
+            chargeback_change.err(),
+            Some(invalid_transition(Succeeded, Chargeback))
+        );
+
+        Ok(())
     }
 
     #[test]
-    fn chargeback_on_incorrect_client_transaction_should_do_nothing() {
+    fn chargeback_on_incorrect_client_transaction_should_do_nothing() -> Result<(), TransactionError>
+    {
         let mut processor = TransactionProcessor::<TransactionStore>::default();
 
         let deposit = transaction(
             transaction::TransactionKind::Deposit {
-                amount: Amount::new(3, 1),
+                amount: positive(Amount::new(3, 1)),
             },
             1,
             1,
         );
 
-        let deposit_change = processor.produce_delta(deposit);
+        let deposit_change = produce_and_commit(&mut processor, deposit)?;
         assert_eq!(
             deposit_change.available.unwrap_or_default(),
             Amount::new(3, 1)
         );
         assert!(deposit_change.held.is_none());
-        assert!(deposit_change.locked.is_none());
+        assert!(deposit_change.status.is_none());
 
-        let chargeback = transaction(transaction::TransactionKind::Chargeback, 1, 2);
+        let chargeback = transaction(
+            transaction::TransactionKind::Chargeback { amount: None },
+            1,
+            2,
+        );
         let chargeback_change = processor.produce_delta(chargeback);
 
-        assert!(
-            chargeback_change.available.is_none(),
+        assert_eq!(
+            chargeback_change.err(),
+            Some(TransactionError::ClientMismatch)
         );
-        assert!(chargeback_change.held.is_none());
-        assert!(chargeback_change.locked.is_none());
+
+        Ok(())
     }
 
     #[test]
-    fn chargeback_on_dispute_should_withdraw_held_funds_and_lock_acc() {
+    fn chargeback_on_dispute_should_withdraw_held_funds_and_lock_acc(
+    ) -> Result<(), TransactionError> {
         let mut processor = TransactionProcessor::<TransactionStore>::default();
 
         let withdrawal = transaction(
             transaction::TransactionKind::Withdrawal {
-                amount: Amount::new(3, 1),
+                amount: positive(Amount::new(3, 1)),
             },
             1,
             1,
         );
 
-        let withdrawal_change = processor.produce_delta(withdrawal);
+        let withdrawal_change = produce_and_commit(&mut processor, withdrawal)?;
         assert_eq!(
             withdrawal_change.available.unwrap_or_default(),
             Amount::new(-3, 1)
         );
         assert!(withdrawal_change.held.is_none());
-        assert!(withdrawal_change.locked.is_none());
+        assert!(withdrawal_change.status.is_none());
 
-        let dispute = transaction(transaction::TransactionKind::Dispute, 1, 1);
-        let dispute_change = processor.produce_delta(dispute);
+        let dispute = transaction(transaction::TransactionKind::Dispute { amount: None }, 1, 1);
+        let dispute_change = produce_and_commit(&mut processor, dispute)?;
 
         assert!(dispute_change.available.is_none());
         assert_eq!(dispute_change.held.unwrap_or_default(), Amount::new(3, 1));
-        assert!(dispute_change.locked.is_none());
+        assert!(dispute_change.status.is_none());
 
-        let chargeback = transaction(transaction::TransactionKind::Chargeback, 1, 1);
-        let chargeback_change = processor.produce_delta(chargeback);
+        let chargeback = transaction(
+            transaction::TransactionKind::Chargeback { amount: None },
+            1,
+            1,
+        );
+        let chargeback_change = produce_and_commit(&mut processor, chargeback)?;
 
         assert!(chargeback_change.available.is_none());
         assert_eq!(
@@ -604,11 +1183,441 @@ mod tests {
             Amount::new(-3, 1)
         );
 
-        let locked = if let Some(val) = chargeback_change.locked {
-            val
-        } else {
-            false
-        };
-        assert!(locked);
+        assert_eq!(chargeback_change.status, Some(AccountStatus::Locked));
+
+        Ok(())
+    }
+
+    #[test]
+    fn rolled_back_dispute_should_not_change_transaction_state() -> Result<(), TransactionError> {
+        let mut processor = TransactionProcessor::<TransactionStore>::default();
+
+        let deposit = transaction(
+            transaction::TransactionKind::Deposit {
+                amount: positive(Amount::new(3, 1)),
+            },
+            1,
+            1,
+        );
+        produce_and_commit(&mut processor, deposit)?;
+
+        let dispute = transaction(transaction::TransactionKind::Dispute { amount: None }, 1, 1);
+        let proposal = processor.produce_delta(dispute)?;
+        processor.rollback(proposal.transition)?;
+
+        assert_eq!(
+            processor.transactions.get(&1)?.unwrap().state,
+            TransactionState::Succeeded
+        );
+
+        let resolve = transaction(transaction::TransactionKind::Resolve { amount: None }, 1, 1);
+        let resolve_change = processor.produce_delta(resolve);
+
+        assert_eq!(
+            resolve_change.err(),
+            Some(invalid_transition(Succeeded, Resolved))
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn rolled_back_withdrawal_should_be_stored_as_failed() -> Result<(), TransactionError> {
+        let mut processor = TransactionProcessor::<TransactionStore>::default();
+
+        let withdrawal = transaction(
+            transaction::TransactionKind::Withdrawal {
+                amount: positive(Amount::new(3, 1)),
+            },
+            1,
+            1,
+        );
+        let proposal = processor.produce_delta(withdrawal)?;
+        processor.rollback(proposal.transition)?;
+
+        assert_eq!(
+            processor.transactions.get(&1)?.unwrap().state,
+            TransactionState::Failed
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn revert_of_disputed_deposit_should_release_held_funds() -> Result<(), TransactionError> {
+        let mut processor = TransactionProcessor::<TransactionStore>::default();
+
+        let deposit = transaction(
+            transaction::TransactionKind::Deposit {
+                amount: positive(Amount::new(3, 1)),
+            },
+            1,
+            1,
+        );
+        produce_and_commit(&mut processor, deposit)?;
+        produce_and_commit(
+            &mut processor,
+            transaction(transaction::TransactionKind::Dispute { amount: None }, 1, 1),
+        )?;
+
+        let revert_change = produce_and_commit(
+            &mut processor,
+            transaction(transaction::TransactionKind::Revert, 1, 1),
+        )?;
+
+        assert_eq!(revert_change.available, Some(Amount::ZERO));
+        assert_eq!(revert_change.held, Some(Amount::new(-3, 1)));
+        assert_eq!(
+            processor.transactions.get(&1)?.unwrap().state,
+            TransactionState::Reverted
+        );
+
+        let revert = transaction(transaction::TransactionKind::Revert, 1, 1);
+
+        assert_eq!(
+            processor.produce_delta(revert).err(),
+            Some(invalid_transition(Reverted, Reverted))
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn revert_of_chargeback_should_release_its_lock() -> Result<(), TransactionError> {
+        let mut processor = TransactionProcessor::<TransactionStore>::default();
+
+        let withdrawal = transaction(
+            transaction::TransactionKind::Withdrawal {
+                amount: positive(Amount::ONE),
+            },
+            1,
+            1,
+        );
+        produce_and_commit(&mut processor, withdrawal)?;
+        produce_and_commit(
+            &mut processor,
+            transaction(transaction::TransactionKind::Dispute { amount: None }, 1, 1),
+        )?;
+        produce_and_commit(
+            &mut processor,
+            transaction(
+                transaction::TransactionKind::Chargeback { amount: None },
+                1,
+                1,
+            ),
+        )?;
+
+        let revert_change = produce_and_commit(
+            &mut processor,
+            transaction(transaction::TransactionKind::Revert, 1, 1),
+        )?;
+
+        assert_eq!(revert_change.available, Some(Amount::ONE));
+        assert_eq!(revert_change.held, Some(Amount::ZERO));
+        assert_eq!(revert_change.status, None);
+        assert_eq!(revert_change.lock, Some(LockChange::Release(1)));
+
+        Ok(())
+    }
+
+    fn transfer(amount: Amount, tx_id: u32, source: u16, destination: u16) -> Transaction {
+        transaction(
+            transaction::TransactionKind::Transfer {
+                amount: positive(amount),
+                destination,
+            },
+            tx_id,
+            source,
+        )
+    }
+
+    #[test]
+    fn transfer_should_withdraw_from_source_and_deposit_to_destination(
+    ) -> Result<(), TransactionError> {
+        let processor = TransactionProcessor::<TransactionStore>::default();
+
+        let proposal = processor.produce_delta(transfer(Amount::TWO, 1, 1, 2))?;
+
+        assert_eq!(proposal.delta, AccountDelta::withdrawal(Amount::TWO));
+        assert_eq!(
+            proposal.counterpart,
+            Some(Counterpart {
+                client_id: 2,
+                delta: AccountDelta::deposit(Amount::TWO)
+            })
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn transfer_to_the_same_client_should_be_refused() {
+        let processor = TransactionProcessor::<TransactionStore>::default();
+
+        let result = processor.produce_delta(transfer(Amount::TWO, 1, 1, 1));
+
+        assert_eq!(result.err(), Some(TransactionError::SelfTransfer));
+    }
+
+    #[test]
+    fn dispute_of_transfer_should_hold_funds_of_destination() -> Result<(), TransactionError> {
+        let mut processor = TransactionProcessor::<TransactionStore>::default();
+
+        produce_and_commit(&mut processor, transfer(Amount::TWO, 1, 1, 2))?;
+
+        let dispute = processor.produce_delta(transaction(
+            transaction::TransactionKind::Dispute { amount: None },
+            1,
+            1,
+        ))?;
+
+        assert_eq!(dispute.delta, AccountDelta::none());
+        assert_eq!(
+            dispute.counterpart,
+            Some(Counterpart {
+                client_id: 2,
+                delta: AccountDelta::dispute_deposit(Amount::TWO)
+            })
+        );
+        processor.commit(dispute.transition)?;
+
+        let chargeback = processor.produce_delta(transaction(
+            transaction::TransactionKind::Chargeback { amount: None },
+            1,
+            1,
+        ))?;
+
+        assert_eq!(chargeback.delta, AccountDelta::deposit(Amount::TWO));
+        assert_eq!(
+            chargeback.counterpart,
+            Some(Counterpart {
+                client_id: 2,
+                delta: AccountDelta::chargeback(1, Amount::TWO)
+            })
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn transfer_should_be_disputed_only_by_source() -> Result<(), TransactionError> {
+        let mut processor = TransactionProcessor::<TransactionStore>::default();
+
+        produce_and_commit(&mut processor, transfer(Amount::TWO, 1, 1, 2))?;
+
+        let dispute = transaction(transaction::TransactionKind::Dispute { amount: None }, 1, 2);
+
+        assert_eq!(
+            processor.produce_delta(dispute).err(),
+            Some(TransactionError::ClientMismatch)
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn transaction_should_be_resolved_once_all_disputes_are_resolved(
+    ) -> Result<(), TransactionError> {
+        let mut processor = TransactionProcessor::<TransactionStore>::default();
+        let amount = |value| Some(positive(Amount::new(value, 0)));
+
+        produce_and_commit(
+            &mut processor,
+            transaction(
+                transaction::TransactionKind::Deposit {
+                    amount: positive(Amount::TEN),
+                },
+                1,
+                1,
+            ),
+        )?;
+        produce_and_commit(
+            &mut processor,
+            transaction(
+                transaction::TransactionKind::Dispute { amount: amount(6) },
+                1,
+                1,
+            ),
+        )?;
+
+        let change = produce_and_commit(
+            &mut processor,
+            transaction(
+                transaction::TransactionKind::Resolve { amount: amount(2) },
+                1,
+                1,
+            ),
+        )?;
+
+        assert_eq!(change, AccountDelta::resolve(Amount::TWO));
+        let stored = processor.transactions.get(&1)?.unwrap();
+        assert_eq!(stored.state, Disputed);
+        assert_eq!(
+            stored.disputed,
+            DisputedAmount {
+                total: Amount::new(6, 0),
+                outstanding: Amount::new(4, 0),
+                charged_back: Amount::ZERO
+            }
+        );
+
+        let change = produce_and_commit(
+            &mut processor,
+            transaction(transaction::TransactionKind::Resolve { amount: None }, 1, 1),
+        )?;
+
+        assert_eq!(change, AccountDelta::resolve(Amount::new(4, 0)));
+        assert_eq!(processor.transactions.get(&1)?.unwrap().state, Resolved);
+
+        Ok(())
+    }
+
+    #[test]
+    fn transaction_disputed_without_tracked_amount_should_be_disputed_as_whole(
+    ) -> Result<(), TransactionError> {
+        let mut processor = TransactionProcessor::<TransactionStore>::default();
+
+        let mut deposit = transaction(
+            transaction::TransactionKind::Deposit {
+                amount: positive(Amount::TEN),
+            },
+            1,
+            1,
+        );
+        deposit.state = Disputed;
+        processor.transactions.insert(deposit)?;
+
+        let change = produce_and_commit(
+            &mut processor,
+            transaction(
+                transaction::TransactionKind::Chargeback { amount: None },
+                1,
+                1,
+            ),
+        )?;
+
+        assert_eq!(change, AccountDelta::chargeback(1, Amount::TEN));
+
+        Ok(())
+    }
+
+    fn authorize(
+        processor: &mut TransactionProcessor<TransactionStore>,
+    ) -> Result<(), TransactionError> {
+        produce_and_commit(
+            processor,
+            transaction(
+                transaction::TransactionKind::Authorize {
+                    amount: positive(Amount::TEN),
+                },
+                1,
+                1,
+            ),
+        )?;
+        Ok(())
+    }
+
+    #[test]
+    fn authorization_should_be_captured_in_parts() -> Result<(), TransactionError> {
+        let mut processor = TransactionProcessor::<TransactionStore>::default();
+        authorize(&mut processor)?;
+
+        let change = produce_and_commit(
+            &mut processor,
+            transaction(
+                transaction::TransactionKind::Capture {
+                    amount: Some(positive(Amount::new(4, 0))),
+                },
+                1,
+                1,
+            ),
+        )?;
+
+        assert_eq!(change, AccountDelta::capture(Amount::new(4, 0)));
+        let stored = processor.transactions.get(&1)?.unwrap();
+        assert_eq!(stored.state, Succeeded);
+        assert_eq!(stored.captured, Amount::new(4, 0));
+
+        let exceeded = processor.produce_delta(transaction(
+            transaction::TransactionKind::Capture {
+                amount: Some(positive(Amount::TEN)),
+            },
+            1,
+            1,
+        ));
+
+        assert_eq!(
+            exceeded.err(),
+            Some(TransactionError::CaptureExceeded(Amount::new(6, 0)))
+        );
+
+        let change = produce_and_commit(
+            &mut processor,
+            transaction(transaction::TransactionKind::Capture { amount: None }, 1, 1),
+        )?;
+
+        assert_eq!(change, AccountDelta::capture(Amount::new(6, 0)));
+        assert_eq!(processor.transactions.get(&1)?.unwrap().state, Captured);
+
+        Ok(())
+    }
+
+    #[test]
+    fn void_should_release_part_which_has_not_been_captured() -> Result<(), TransactionError> {
+        let mut processor = TransactionProcessor::<TransactionStore>::default();
+        authorize(&mut processor)?;
+
+        produce_and_commit(
+            &mut processor,
+            transaction(
+                transaction::TransactionKind::Capture {
+                    amount: Some(positive(Amount::new(4, 0))),
+                },
+                1,
+                1,
+            ),
+        )?;
+        let change = produce_and_commit(
+            &mut processor,
+            transaction(transaction::TransactionKind::Void, 1, 1),
+        )?;
+
+        assert_eq!(change, AccountDelta::void(Amount::new(6, 0)));
+        assert_eq!(processor.transactions.get(&1)?.unwrap().state, Voided);
+
+        let capture = processor.produce_delta(transaction(
+            transaction::TransactionKind::Capture { amount: None },
+            1,
+            1,
+        ));
+
+        assert_eq!(capture.err(), Some(invalid_transition(Voided, Captured)));
+
+        Ok(())
+    }
+
+    #[test_case(transaction::TransactionKind::Capture { amount: None } ; "capture")]
+    #[test_case(transaction::TransactionKind::Void ; "void")]
+    fn capture_or_void_of_deposit_should_be_refused(
+        kind: transaction::TransactionKind,
+    ) -> Result<(), TransactionError> {
+        let mut processor = TransactionProcessor::<TransactionStore>::default();
+
+        produce_and_commit(
+            &mut processor,
+            transaction(
+                transaction::TransactionKind::Deposit {
+                    amount: positive(Amount::TEN),
+                },
+                1,
+                1,
+            ),
+        )?;
+
+        let change = processor.produce_delta(transaction(kind, 1, 1));
+
+        assert_eq!(change.err(), Some(TransactionError::NotAuthorization));
+
+        Ok(())
     }
 }
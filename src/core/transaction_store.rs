@@ -1,14 +1,61 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, io};
 
-use super::transaction::{Transaction, TransactionID};
+use super::{
+    account::Amount,
+    transaction::{DisputedAmount, Transaction, TransactionID, TransactionState},
+};
+
+#[derive(thiserror::Error, Debug, Clone, PartialEq, Eq)]
+pub enum StoreError {
+    #[error("Transaction storage I/O error: {0}")]
+    Io(String),
+    #[error("Transaction storage is corrupted at offset {0}")]
+    Corrupted(u64),
+}
+
+impl From<io::Error> for StoreError {
+    fn from(err: io::Error) -> Self {
+        StoreError::Io(err.to_string())
+    }
+}
 
 pub trait Store {
-    // Inserts transaction to storage
-    fn insert(&mut self, transaction: Transaction);
-    // Returns reference to corresponding transaction
-    fn get(&self, tx_id: &TransactionID) -> Option<&Transaction>;
-    // Returns mutable reference to corresponding transaction
-    fn get_mut(&mut self, tx_id: &TransactionID) -> Option<&mut Transaction>;
+    // Inserts transaction to storage, already existing transaction is never overwritten
+    fn insert(&mut self, transaction: Transaction) -> Result<(), StoreError>;
+    // Returns true if transaction with given id is already stored
+    fn contains(&self, tx_id: &TransactionID) -> Result<bool, StoreError> {
+        Ok(self.get(tx_id)?.is_some())
+    }
+    // Returns copy of corresponding transaction
+    fn get(&self, tx_id: &TransactionID) -> Result<Option<Transaction>, StoreError>;
+    // Changes state of corresponding transaction, unknown transaction is skipped
+    fn set_state(
+        &mut self,
+        tx_id: &TransactionID,
+        state: TransactionState,
+    ) -> Result<(), StoreError>;
+    // Changes state of corresponding transaction together with its disputed amount, unknown transaction is skipped
+    fn set_disputed(
+        &mut self,
+        tx_id: &TransactionID,
+        state: TransactionState,
+        disputed: DisputedAmount,
+    ) -> Result<(), StoreError>;
+    // Changes state of corresponding authorization together with its captured amount, unknown transaction is skipped
+    fn set_captured(
+        &mut self,
+        tx_id: &TransactionID,
+        state: TransactionState,
+        captured: Amount,
+    ) -> Result<(), StoreError>;
+    // Keeps timestamps of transactions inserted from now on, storage may drop them otherwise as only time windows need them
+    fn keep_timestamps(&mut self) {}
+    // Persists stored transactions and returns length of persisted log, storages kept in memory have none
+    fn sync(&mut self) -> Result<Option<u64>, StoreError> {
+        Ok(None)
+    }
+    // Returns iterator over all stored transactions in unspecified order
+    fn iter(&self) -> Box<dyn Iterator<Item = Result<Transaction, StoreError>> + '_>;
 }
 
 /// Represents collection of transactions
@@ -18,43 +65,116 @@ pub struct TransactionStore {
 }
 
 impl Store for TransactionStore {
-    fn insert(&mut self, transaction: Transaction) {
-        self.transactions.insert(transaction.tx_id(), transaction);
+    fn insert(&mut self, transaction: Transaction) -> Result<(), StoreError> {
+        self.transactions
+            .entry(transaction.tx_id())
+            .or_insert(transaction);
+        Ok(())
+    }
+
+    fn get(&self, tx_id: &TransactionID) -> Result<Option<Transaction>, StoreError> {
+        Ok(self.transactions.get(tx_id).cloned())
+    }
+
+    fn set_state(
+        &mut self,
+        tx_id: &TransactionID,
+        state: TransactionState,
+    ) -> Result<(), StoreError> {
+        if let Some(transaction) = self.transactions.get_mut(tx_id) {
+            transaction.state = state;
+        }
+        Ok(())
+    }
+
+    fn set_disputed(
+        &mut self,
+        tx_id: &TransactionID,
+        state: TransactionState,
+        disputed: DisputedAmount,
+    ) -> Result<(), StoreError> {
+        if let Some(transaction) = self.transactions.get_mut(tx_id) {
+            transaction.state = state;
+            transaction.disputed = disputed;
+        }
+        Ok(())
     }
 
-    fn get(&self, tx_id: &TransactionID) -> Option<&Transaction> {
-        self.transactions.get(tx_id)
+    fn set_captured(
+        &mut self,
+        tx_id: &TransactionID,
+        state: TransactionState,
+        captured: Amount,
+    ) -> Result<(), StoreError> {
+        if let Some(transaction) = self.transactions.get_mut(tx_id) {
+            transaction.state = state;
+            transaction.captured = captured;
+        }
+        Ok(())
     }
 
-    fn get_mut(&mut self, tx_id: &TransactionID) -> Option<&mut Transaction> {
-        self.transactions.get_mut(tx_id)
+    fn iter(&self) -> Box<dyn Iterator<Item = Result<Transaction, StoreError>> + '_> {
+        Box::new(self.transactions.values().cloned().map(Ok))
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::core::{account::Amount, tests::transaction, transaction::TransactionKind};
+    use crate::core::{
+        account::Amount,
+        tests::{positive, transaction},
+        transaction::TransactionKind,
+    };
 
-    use super::{Store, TransactionStore};
+    use super::{Store, StoreError, TransactionStore};
 
     #[test]
-    fn insert_and_get_transaction() {
+    fn insert_and_get_transaction() -> Result<(), StoreError> {
         let mut store = TransactionStore::default();
 
         let tx_id = 1;
 
         store.insert(transaction(
             TransactionKind::Deposit {
-                amount: Amount::ONE,
+                amount: positive(Amount::ONE),
             },
             tx_id,
             1,
-        ));
+        ))?;
 
-        let transaction = store.get(&tx_id);
+        let transaction = store.get(&tx_id)?;
 
         assert!(transaction.is_some());
 
         assert_eq!(transaction.unwrap().metadata.client_id, 1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn insert_should_not_overwrite_existing_transaction() -> Result<(), StoreError> {
+        let mut store = TransactionStore::default();
+
+        let tx_id = 1;
+
+        store.insert(transaction(
+            TransactionKind::Deposit {
+                amount: positive(Amount::ONE),
+            },
+            tx_id,
+            1,
+        ))?;
+        store.insert(transaction(
+            TransactionKind::Deposit {
+                amount: positive(Amount::TEN),
+            },
+            tx_id,
+            2,
+        ))?;
+
+        assert!(store.contains(&tx_id)?);
+        assert_eq!(store.get(&tx_id)?.unwrap().metadata.client_id, 1);
+
+        Ok(())
     }
 }
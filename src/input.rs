@@ -0,0 +1,495 @@
+use std::{
+    collections::HashMap,
+    ffi::OsStr,
+    fs::File,
+    io::{self, BufRead, BufReader},
+    path::Path,
+};
+
+use csv::{ByteRecord, Position, Reader};
+use flate2::read::MultiGzDecoder;
+use serde::{Deserialize, Serialize};
+use transactions::{ClientID, CreditLine, Transaction};
+
+/// Path which stands for standard input
+pub const STDIN: &str = "-";
+
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+const ZSTD_MAGIC: [u8; 4] = [0x28, 0xb5, 0x2f, 0xfd];
+
+/// Compression of input
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    None,
+    Gzip,
+    Zstd,
+}
+
+impl Compression {
+    /// Returns compression based on extension, `None` when extension doesn't tell
+    pub fn from_path(path: &Path) -> Option<Self> {
+        match path.extension().and_then(OsStr::to_str) {
+            Some("gz") => Some(Compression::Gzip),
+            Some("zst") => Some(Compression::Zstd),
+            _ => None,
+        }
+    }
+
+    /// Detects compression from the first bytes of input
+    pub fn detect(header: &[u8]) -> Self {
+        if header.starts_with(&GZIP_MAGIC) {
+            Compression::Gzip
+        } else if header.starts_with(&ZSTD_MAGIC) {
+            Compression::Zstd
+        } else {
+            Compression::None
+        }
+    }
+}
+
+/// Opens input file, [`STDIN`] opens standard input
+///
+/// Compressed input is decompressed on the fly, compression is taken from extension
+/// or detected from magic bytes.
+pub fn open(path: &Path) -> io::Result<Box<dyn io::Read>> {
+    let reader: Box<dyn io::Read> = if path.as_os_str() == STDIN {
+        Box::new(io::stdin())
+    } else {
+        Box::new(File::open(path)?)
+    };
+
+    decompress(reader, Compression::from_path(path))
+}
+
+fn decompress<R: io::Read + 'static>(
+    reader: R,
+    compression: Option<Compression>,
+) -> io::Result<Box<dyn io::Read>> {
+    let mut reader = BufReader::new(reader);
+
+    let compression = match compression {
+        Some(compression) => compression,
+        None => Compression::detect(reader.fill_buf()?),
+    };
+
+    Ok(match compression {
+        Compression::None => Box::new(reader),
+        Compression::Gzip => Box::new(MultiGzDecoder::new(reader)),
+        Compression::Zstd => Box::new(zstd::Decoder::with_buffer(reader)?),
+    })
+}
+
+/// Row which couldn't be turned into valid transaction
+#[derive(thiserror::Error, Serialize, Debug, PartialEq, Eq)]
+#[error("Rejected record at {source_name}:{line}: {error}")]
+pub struct Rejection {
+    #[serde(rename = "source")]
+    pub source_name: String,
+    pub line: u64,
+    pub byte: u64,
+    pub record: String,
+    pub error: String,
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum ReadError {
+    #[error("{0}")]
+    Io(#[from] csv::Error),
+    #[error("{0}")]
+    Rejected(#[from] Rejection),
+}
+
+/// Reads transactions from CSV, malformed rows are reported as [`Rejection`] instead of being dropped
+pub struct TransactionReader<R> {
+    reader: Reader<R>,
+    // Name of input reported in rejections
+    source: String,
+    headers: ByteRecord,
+    record: ByteRecord,
+    max_scale: u32,
+    finished: bool,
+}
+
+impl<R> TransactionReader<R>
+where
+    R: io::Read,
+{
+    pub fn new(mut reader: Reader<R>, source: String, max_scale: u32) -> Result<Self, csv::Error> {
+        let headers = reader.byte_headers()?.clone();
+
+        Ok(Self {
+            reader,
+            source,
+            headers,
+            record: ByteRecord::new(),
+            max_scale,
+            finished: false,
+        })
+    }
+
+    fn parse(&self) -> Result<Transaction, String> {
+        let transaction: Transaction =
+            self.record
+                .deserialize(Some(&self.headers))
+                .map_err(|err| match err.kind() {
+                    // Position is already a part of rejection, only serde message is kept
+                    csv::ErrorKind::Deserialize { err, .. } => err.to_string(),
+                    _ => err.to_string(),
+                })?;
+
+        transaction
+            .validate(self.max_scale)
+            .map_err(|err| err.to_string())?;
+
+        Ok(transaction)
+    }
+
+    fn rejection(&self, error: String) -> Rejection {
+        let position = self
+            .record
+            .position()
+            .cloned()
+            .unwrap_or_else(Position::new);
+        let record = self
+            .record
+            .iter()
+            .map(String::from_utf8_lossy)
+            .collect::<Vec<_>>()
+            .join(",");
+
+        Rejection {
+            source_name: self.source.clone(),
+            line: position.line(),
+            byte: position.byte(),
+            record,
+            error,
+        }
+    }
+}
+
+impl<R> Iterator for TransactionReader<R>
+where
+    R: io::Read,
+{
+    type Item = Result<Transaction, ReadError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.finished {
+            return None;
+        }
+
+        match self.reader.read_byte_record(&mut self.record) {
+            Ok(true) => {}
+            Ok(false) => {
+                self.finished = true;
+                return None;
+            }
+            Err(err) => {
+                // Reading can't be continued after I/O failure
+                self.finished = true;
+                return Some(Err(err.into()));
+            }
+        }
+
+        Some(self.parse().map_err(|error| self.rejection(error).into()))
+    }
+}
+
+/// Reads transactions from JSON Lines, one JSON object per line, blank lines are skipped
+pub struct JsonLinesReader<R> {
+    reader: R,
+    // Name of input reported in rejections
+    source: String,
+    line: String,
+    line_number: u64,
+    byte: u64,
+    max_scale: u32,
+    finished: bool,
+}
+
+impl<R> JsonLinesReader<R>
+where
+    R: BufRead,
+{
+    pub fn new(reader: R, source: String, max_scale: u32) -> Self {
+        Self {
+            reader,
+            source,
+            line: String::new(),
+            line_number: 0,
+            byte: 0,
+            max_scale,
+            finished: false,
+        }
+    }
+
+    fn parse(&self) -> Result<Transaction, String> {
+        let transaction =
+            Transaction::from_json(self.line.trim()).map_err(|err| err.to_string())?;
+
+        transaction
+            .validate(self.max_scale)
+            .map_err(|err| err.to_string())?;
+
+        Ok(transaction)
+    }
+
+    fn rejection(&self, byte: u64, error: String) -> Rejection {
+        Rejection {
+            source_name: self.source.clone(),
+            line: self.line_number,
+            byte,
+            record: self.line.trim().to_string(),
+            error,
+        }
+    }
+}
+
+impl<R> Iterator for JsonLinesReader<R>
+where
+    R: BufRead,
+{
+    type Item = Result<Transaction, ReadError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while !self.finished {
+            self.line.clear();
+
+            let byte = self.byte;
+            match self.reader.read_line(&mut self.line) {
+                Ok(0) => self.finished = true,
+                Ok(read) => {
+                    self.byte += read as u64;
+                    self.line_number += 1;
+
+                    if self.line.trim().is_empty() {
+                        continue;
+                    }
+
+                    return Some(
+                        self.parse()
+                            .map_err(|error| self.rejection(byte, error).into()),
+                    );
+                }
+                Err(err) => {
+                    // Reading can't be continued after I/O failure
+                    self.finished = true;
+                    return Some(Err(ReadError::Io(err.into())));
+                }
+            }
+        }
+
+        None
+    }
+}
+
+/// Reader of transactions in any of supported formats
+pub enum Input<R> {
+    Csv(TransactionReader<R>),
+    JsonLines(JsonLinesReader<BufReader<R>>),
+}
+
+impl<R> Iterator for Input<R>
+where
+    R: io::Read,
+{
+    type Item = Result<Transaction, ReadError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self {
+            Input::Csv(reader) => reader.next(),
+            Input::JsonLines(reader) => reader.next(),
+        }
+    }
+}
+
+/// Client column of credit lines file, the rest of row is read as [`CreditLine`]
+#[derive(Deserialize)]
+struct CreditLineClient {
+    client: ClientID,
+}
+
+/// Reads credit lines of clients from CSV with `client,limit,withdrawals` columns
+///
+/// Malformed row fails the whole file, client listed more than once keeps the last line.
+pub fn read_credit_lines<R: io::Read>(
+    mut reader: Reader<R>,
+) -> Result<HashMap<ClientID, CreditLine>, csv::Error> {
+    let headers = reader.byte_headers()?.clone();
+
+    reader
+        .byte_records()
+        .map(|record| {
+            let record = record?;
+            let CreditLineClient { client } = record.deserialize(Some(&headers))?;
+            let credit_line = record.deserialize(Some(&headers))?;
+
+            Ok((client, credit_line))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use test_case::test_case;
+
+    use std::io::{Read, Write};
+
+    use flate2::{write::GzEncoder, Compression as GzLevel};
+
+    use transactions::{Amount, CreditLine};
+
+    use super::{
+        decompress, read_credit_lines, Compression, JsonLinesReader, ReadError, Rejection,
+        TransactionReader,
+    };
+
+    const DATA: &str = "type,client,tx,amount\ndeposit,1,1,1.0\n";
+
+    fn gzip_encode(data: &[u8]) -> Vec<u8> {
+        let mut encoder = GzEncoder::new(Vec::new(), GzLevel::default());
+        encoder.write_all(data).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    #[test_case(DATA.as_bytes().to_vec(), Compression::None ; "plain")]
+    #[test_case(gzip_encode(DATA.as_bytes()), Compression::Gzip ; "gzip")]
+    #[test_case(zstd::encode_all(DATA.as_bytes(), 0).unwrap(), Compression::Zstd ; "zstd")]
+    fn compressed_input_should_be_detected_and_decompressed(data: Vec<u8>, expected: Compression) {
+        assert_eq!(Compression::detect(&data), expected);
+
+        let mut decompressed = String::new();
+        decompress(std::io::Cursor::new(data), None)
+            .unwrap()
+            .read_to_string(&mut decompressed)
+            .unwrap();
+
+        assert_eq!(decompressed, DATA);
+    }
+
+    #[test_case("day.csv", None ; "csv")]
+    #[test_case("day.csv.gz", Some(Compression::Gzip) ; "gzip")]
+    #[test_case("day.csv.zst", Some(Compression::Zstd) ; "zstd")]
+    fn compression_should_be_taken_from_extension(path: &str, expected: Option<Compression>) {
+        assert_eq!(Compression::from_path(std::path::Path::new(path)), expected);
+    }
+
+    fn read(data: &str) -> Vec<Result<u32, Rejection>> {
+        let reader = csv::ReaderBuilder::new()
+            .flexible(true)
+            .trim(csv::Trim::All)
+            .from_reader(data.as_bytes());
+
+        TransactionReader::new(reader, "test.csv".to_string(), 4)
+            .unwrap()
+            .map(|result| match result {
+                Ok(transaction) => Ok(transaction.tx_id()),
+                Err(ReadError::Rejected(rejection)) => Err(rejection),
+                Err(ReadError::Io(err)) => panic!("{}", err),
+            })
+            .collect()
+    }
+
+    #[test_case("deposit,1,2" ; "deposit_without_amount")]
+    #[test_case("bogus,1,2,1.0" ; "unknown_type")]
+    #[test_case("deposit,70000,2,1.0" ; "client_id_out_of_range")]
+    #[test_case("deposit,1,2,-1.0" ; "negative_amount")]
+    #[test_case("deposit,1,2,1.00001" ; "over_precise_amount")]
+    fn malformed_row_should_be_rejected_with_position(row: &str) {
+        let data = format!(
+            "type,client,tx,amount\ndeposit,1,1,1.0\n{}\ndeposit,1,3,1.0",
+            row
+        );
+
+        let results = read(&data);
+
+        assert_eq!(results.len(), 3);
+        assert_eq!(results[0], Ok(1));
+        assert_eq!(results[2], Ok(3));
+
+        let rejection = results[1].as_ref().unwrap_err();
+        assert_eq!(rejection.source_name, "test.csv");
+        assert_eq!(rejection.line, 3);
+        assert_eq!(rejection.byte, 38);
+        assert_eq!(rejection.record, row);
+        assert!(!rejection.error.is_empty());
+    }
+
+    #[test]
+    fn malformed_json_line_should_be_rejected_with_position() {
+        let data = concat!(
+            r#"{"type":"deposit","client":1,"tx":1,"amount":1.5}"#,
+            "\n\n",
+            r#"{"type":"withdrawal","client":1,"tx":2}"#,
+            "\n",
+            r#"{"type":"dispute","client":1,"tx":1}"#,
+        );
+
+        let results: Vec<_> = JsonLinesReader::new(data.as_bytes(), "test.jsonl".to_string(), 4)
+            .map(|result| match result {
+                Ok(transaction) => Ok(transaction.tx_id()),
+                Err(ReadError::Rejected(rejection)) => Err(rejection),
+                Err(ReadError::Io(err)) => panic!("{}", err),
+            })
+            .collect();
+
+        assert_eq!(results.len(), 3);
+        assert_eq!(results[0], Ok(1));
+        assert_eq!(results[2], Ok(1));
+
+        let rejection = results[1].as_ref().unwrap_err();
+        assert_eq!(rejection.source_name, "test.jsonl");
+        assert_eq!(rejection.line, 3);
+        assert_eq!(rejection.byte, 51);
+        assert_eq!(
+            rejection.record,
+            r#"{"type":"withdrawal","client":1,"tx":2}"#
+        );
+        assert!(rejection.error.contains("amount"));
+    }
+
+    fn read_lines(data: &str) -> Result<Vec<(u16, CreditLine)>, csv::Error> {
+        let reader = csv::ReaderBuilder::new()
+            .trim(csv::Trim::All)
+            .from_reader(data.as_bytes());
+
+        let mut lines: Vec<_> = read_credit_lines(reader)?.into_iter().collect();
+        lines.sort_by_key(|(client, _)| *client);
+
+        Ok(lines)
+    }
+
+    #[test]
+    fn credit_lines_should_be_read_with_full_precision() {
+        let lines =
+            read_lines("client,limit,withdrawals\n1, 12345678901234.5678, true\n2, 0.5, false\n")
+                .unwrap();
+
+        assert_eq!(
+            lines,
+            vec![
+                (
+                    1,
+                    CreditLine {
+                        limit: Amount::new(123456789012345678, 4),
+                        withdrawals: true
+                    }
+                ),
+                (
+                    2,
+                    CreditLine {
+                        limit: Amount::new(5, 1),
+                        withdrawals: false
+                    }
+                ),
+            ]
+        );
+    }
+
+    #[test_case("client,limit,withdrawals\n1,-1.0,true\n" ; "negative_limit")]
+    #[test_case("client,limit,withdrawals\n1,1.0,\n" ; "missing_withdrawals")]
+    #[test_case("client,limit\n1,1.0\n" ; "missing_column")]
+    fn malformed_credit_lines_should_be_refused(data: &str) {
+        assert!(read_lines(data).is_err());
+    }
+}
@@ -0,0 +1,37 @@
+//! Simple library for managing transactions.
+//!
+//! [`Engine`] is an entry point, every incoming [`Transaction`] is converted to [`AccountDelta`]
+//! which is later on applied to user [`Account`].
+//!
+//! Engine is generic over storages, any implementation of [`transaction_store::Store`]
+//! and [`account_store::Store`] can be plugged in.
+
+// Tests compare booleans with assert_eq
+#![cfg_attr(test, allow(clippy::bool_assert_comparison))]
+
+mod core;
+
+pub use crate::core::{
+    account::{
+        Account, AccountDelta, AccountError, AccountStatus, AccountWithStatus, Amount, ClientID,
+        CreditLine, LockChange,
+    },
+    account_store::{self, AccountStore},
+    atomic_file::AtomicFile,
+    compact_transaction_store::CompactTransactionStore,
+    engine::{Engine, ProcessOutcome},
+    expiry::{ExpiryPolicy, DAY},
+    file_transaction_store::FileTransactionStore,
+    journal::{Journal, JournalEntry},
+    sharded_engine::{ShardError, ShardedEngine},
+    snapshot::{Snapshot, SnapshotError, SNAPSHOT_VERSION},
+    transaction::{
+        AmountError, DisputedAmount, PositiveAmount, Timestamp, Transaction, TransactionID,
+        TransactionKind, TransactionMetadata, TransactionState, TransitionError, DEFAULT_MAX_SCALE,
+    },
+    transaction_processor::TransactionError,
+    transaction_store::{self, TransactionStore},
+};
+
+#[cfg(feature = "async")]
+pub use crate::core::engine_handle::{EngineHandle, HandleError};
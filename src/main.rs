@@ -1,14 +1,24 @@
-use crate::core::engine::Engine;
-use crate::core::{account_store::AccountStore, transaction_store::TransactionStore};
-use std::fs::File;
-use std::io::{self, Stdout};
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::path::Path;
 
 use clap::Parser;
-use cli::validate_ext;
-use csv::{Reader, Writer};
+use cli::{validate_ext, InputFormat};
+use csv::Writer;
+use input::{read_credit_lines, Input, JsonLinesReader, ReadError, Rejection, TransactionReader};
+use output::{AccountWriter, Commit};
+use transactions::{
+    account_store::{AccountOrder, Store},
+    transaction_store::{self, StoreError},
+    Account, AccountStore, AtomicFile, ClientID, CompactTransactionStore, CreditLine, Engine,
+    FileTransactionStore, ProcessOutcome, ShardError, ShardedEngine, Snapshot, SnapshotError,
+    Transaction, TransactionID,
+};
 
 mod cli;
-mod core;
+mod input;
+mod output;
 
 #[derive(thiserror::Error, Debug)]
 pub enum AppError {
@@ -16,40 +26,410 @@ pub enum AppError {
     OpenFileError(#[from] std::io::Error),
     #[error("{0}")]
     InvalidFileExt(String),
+    #[error("{0}")]
+    CsvError(#[from] csv::Error),
+    #[error("{0}")]
+    JsonError(#[from] serde_json::Error),
+    #[error("{0}")]
+    RejectedRecord(#[from] Rejection),
+    #[error("{0}")]
+    StoreError(#[from] StoreError),
+    #[error("{0}")]
+    SnapshotError(#[from] SnapshotError),
+    #[error("{0}")]
+    ShardError(#[from] ShardError),
+    #[error("Transaction log {0} is not empty, accounts are not kept in it so processing can be continued only with --resume-from snapshot checkpointed with this log")]
+    TransactionLogNotEmpty(String),
 }
 
 fn main() -> Result<(), AppError> {
     let args = cli::Args::parse();
     validate_ext(&args)?;
 
-    let file = File::open(args.transactions_file)?;
+    // All inputs are opened upfront, so missing file is reported before anything is processed
+    let inputs = args
+        .transactions_files
+        .iter()
+        .map(|path| read_input(input::open(path)?, path.display().to_string(), &args))
+        .collect::<Result<Vec<_>, AppError>>()?;
+
+    let rejects = args
+        .rejects
+        .as_ref()
+        .map(|path| open_rejects(path, args.resume_from.is_some()))
+        .transpose()?;
 
-    let input = csv::ReaderBuilder::new()
-        .flexible(true)
-        .trim(csv::Trim::All)
-        .from_reader(file);
+    // Snapshot is loaded before transaction log is opened, as it tells which part of the log is resumed
+    let snapshot = args.resume_from.as_ref().map(Snapshot::load).transpose()?;
 
-    let output = csv::WriterBuilder::new()
-        .flexible(true)
-        .from_writer(io::stdout());
+    let credit_lines = match &args.credit_lines {
+        Some(path) => read_credit_lines(
+            csv::ReaderBuilder::new()
+                .trim(csv::Trim::All)
+                .from_path(path)?,
+        )?,
+        None => HashMap::new(),
+    };
 
-    worker(input, output);
+    match &args.output {
+        Some(path) => {
+            let output = AccountWriter::new(AtomicFile::create(path)?, args.output_format);
+            run(inputs, snapshot, output, rejects, credit_lines, &args)
+        }
+        None => {
+            let output = AccountWriter::new(io::stdout(), args.output_format);
+            run(inputs, snapshot, output, rejects, credit_lines, &args)
+        }
+    }
+}
+
+/// Reads transactions of one input in selected format, source names input in rejections
+fn read_input<R: io::Read>(
+    reader: R,
+    source: String,
+    args: &cli::Args,
+) -> Result<Input<R>, AppError> {
+    Ok(match args.input_format {
+        InputFormat::Csv => {
+            let reader = csv::ReaderBuilder::new()
+                .flexible(true)
+                .trim(csv::Trim::All)
+                .from_reader(reader);
+            Input::Csv(TransactionReader::new(reader, source, args.max_scale)?)
+        }
+        InputFormat::JsonLines => Input::JsonLines(JsonLinesReader::new(
+            io::BufReader::new(reader),
+            source,
+            args.max_scale,
+        )),
+    })
+}
+
+/// Picks worker and storage based on arguments
+fn run<R, W>(
+    inputs: Vec<Input<R>>,
+    snapshot: Option<Snapshot>,
+    output: AccountWriter<W>,
+    rejects: Option<Writer<File>>,
+    credit_lines: HashMap<ClientID, CreditLine>,
+    args: &cli::Args,
+) -> Result<(), AppError>
+where
+    R: io::Read,
+    W: Commit,
+{
+    if args.shards > 1 {
+        return sharded_worker(inputs, output, rejects, credit_lines, args);
+    }
+
+    match &args.transaction_log {
+        Some(path) => {
+            let store = match snapshot.as_ref().and_then(|snapshot| snapshot.log_len) {
+                // Records appended after snapshot are discarded, their rows are processed again
+                Some(len) => FileTransactionStore::resume(path, len)?,
+                None => {
+                    let store = FileTransactionStore::open(path)?;
+                    // Transactions of existing log would turn every row into duplicate of already processed one
+                    if !store.is_empty() {
+                        return Err(AppError::TransactionLogNotEmpty(path.display().to_string()));
+                    }
+                    store
+                }
+            };
+
+            let engine = Engine::new(store, AccountStore::default());
+            worker(
+                engine,
+                snapshot,
+                inputs,
+                output,
+                rejects,
+                credit_lines,
+                args,
+            )
+        }
+        None => {
+            let engine = Engine::<CompactTransactionStore, AccountStore>::default();
+            worker(
+                engine,
+                snapshot,
+                inputs,
+                output,
+                rejects,
+                credit_lines,
+                args,
+            )
+        }
+    }
+}
+
+/// Processes transactions from all inputs in order through one engine
+pub fn worker<T, R, W>(
+    mut engine: Engine<T, AccountStore>,
+    snapshot: Option<Snapshot>,
+    inputs: Vec<Input<R>>,
+    output: AccountWriter<W>,
+    mut rejects: Option<Writer<File>>,
+    credit_lines: HashMap<ClientID, CreditLine>,
+    args: &cli::Args,
+) -> Result<(), AppError>
+where
+    T: transaction_store::Store,
+    R: io::Read,
+    W: Commit,
+{
+    engine.set_idempotent(args.idempotent);
+    engine.set_credit_lines(credit_lines);
+    // Policy has to be known before snapshot is restored
+    engine.set_expiry_policy(args.expiry_policy());
+
+    let (skip_rows, transactions) = match snapshot {
+        Some(snapshot) => {
+            let rows = snapshot.rows as usize;
+            let transactions = snapshot.transaction_count();
+            engine.restore(snapshot)?;
+            (args.skip_rows.unwrap_or(rows), transactions)
+        }
+        None => (args.skip_rows.unwrap_or_default(), 0),
+    };
+
+    // Row before which the next snapshot is taken
+    let mut next_checkpoint = skip_rows as u64 + args.checkpoint_interval(transactions);
+
+    // Rows are counted across all inputs
+    let rows = inputs.into_iter().flatten().enumerate().skip(skip_rows);
+
+    for (row, result) in rows {
+        // Snapshot is taken before given row, so it covers all previous rows
+        if let Some(path) = &args.checkpoint {
+            if row as u64 >= next_checkpoint {
+                // Log is synced first, so snapshot never refers to records lost in crash
+                let mut snapshot = engine.checkpoint()?;
+                snapshot.rows = row as u64;
+                snapshot.save(path)?;
+                next_checkpoint =
+                    row as u64 + args.checkpoint_interval(snapshot.transaction_count());
+            }
+        }
+
+        let transaction = match accept(result, &mut rejects, args)? {
+            Some(transaction) => transaction,
+            None => continue,
+        };
+
+        let tx_id = transaction.tx_id();
+        let outcome = engine.process_transaction(transaction);
+        log_outcome(tx_id, &outcome);
+
+        if let ProcessOutcome::Failed(err) = outcome {
+            return Err(err.into());
+        }
+    }
+
+    let order = AccountOrder::new(args.sort_by, args.order);
+    write_accounts(engine.ordered_accounts(order), output, args.with_status)?;
+
+    if let Some(rejects) = rejects.as_mut() {
+        rejects.flush()?;
+    }
 
     Ok(())
 }
 
-pub fn worker(mut input: Reader<File>, mut output: Writer<Stdout>) {
-    let mut engine = Engine::<TransactionStore, AccountStore>::default();
+/// Processes transactions on several threads, clients are partitioned between shards
+pub fn sharded_worker<R, W>(
+    inputs: Vec<Input<R>>,
+    output: AccountWriter<W>,
+    mut rejects: Option<Writer<File>>,
+    credit_lines: HashMap<ClientID, CreditLine>,
+    args: &cli::Args,
+) -> Result<(), AppError>
+where
+    R: io::Read,
+    W: Commit,
+{
+    let idempotent = args.idempotent;
+    let expiry = args.expiry_policy();
+    let mut engine = ShardedEngine::new(
+        args.shards as usize,
+        || {
+            let mut engine = Engine::<CompactTransactionStore, AccountStore>::default();
+            engine.set_idempotent(idempotent);
+            engine.set_expiry_policy(expiry);
+            // Every shard gets all lines, it only touches accounts of its own clients
+            engine.set_credit_lines(credit_lines.clone());
+            engine
+        },
+        log_outcome,
+    );
 
-    input
-        .deserialize()
+    let rows = inputs
         .into_iter()
         .flatten()
-        .for_each(|t| engine.process_transaction(t));
+        .skip(args.skip_rows.unwrap_or_default());
+
+    for result in rows {
+        let transaction = match accept(result, &mut rejects, args)? {
+            Some(transaction) => transaction,
+            None => continue,
+        };
+
+        if engine.process_transaction(transaction).is_err() {
+            // Reason of stopped shard is reported by finish
+            break;
+        }
+    }
+
+    let accounts = engine.finish()?;
+
+    let order = AccountOrder::new(args.sort_by, args.order);
+    write_accounts(accounts.ordered(order), output, args.with_status)?;
+
+    if let Some(rejects) = rejects.as_mut() {
+        rejects.flush()?;
+    }
+
+    Ok(())
+}
+
+/// Opens writer of rejected records, records rejected before crash are kept when processing is resumed
+fn open_rejects(path: &Path, resume: bool) -> Result<Writer<File>, AppError> {
+    if !resume {
+        return Ok(Writer::from_path(path)?);
+    }
+
+    let file = OpenOptions::new().append(true).create(true).open(path)?;
+    // Header has been already written by the interrupted run
+    let has_header = file.metadata()?.len() > 0;
+
+    Ok(csv::WriterBuilder::new()
+        .has_headers(!has_header)
+        .from_writer(file))
+}
+
+/// Returns transaction from read record, rejected records are reported and skipped unless strict mode is enabled
+fn accept(
+    result: Result<Transaction, ReadError>,
+    rejects: &mut Option<Writer<File>>,
+    args: &cli::Args,
+) -> Result<Option<Transaction>, AppError> {
+    match result {
+        Ok(transaction) => Ok(Some(transaction)),
+        Err(ReadError::Rejected(rejection)) if args.strict => Err(rejection.into()),
+        Err(ReadError::Rejected(rejection)) => {
+            log::error!("{}", rejection);
+            if let Some(rejects) = rejects.as_mut() {
+                rejects.serialize(&rejection)?;
+            }
+            Ok(None)
+        }
+        Err(ReadError::Io(err)) => Err(err.into()),
+    }
+}
+
+fn log_outcome(tx_id: TransactionID, outcome: &ProcessOutcome) {
+    match outcome {
+        ProcessOutcome::Applied => {}
+        ProcessOutcome::Rejected(err) => {
+            log::error!("Transaction {:?} failed: {:?}", tx_id, err)
+        }
+        ProcessOutcome::Ignored(err) => {
+            log::warn!("Transaction {:?} ignored: {:?}", tx_id, err)
+        }
+        ProcessOutcome::Duplicate => {
+            log::error!("Transaction {:?} is a duplicate", tx_id)
+        }
+        ProcessOutcome::Failed(err) => {
+            log::error!("Transaction {:?} can't be stored: {}", tx_id, err)
+        }
+    }
+}
+
+/// Writes all accounts and commits output, nothing is committed when any account can't be written
+fn write_accounts<W: Commit>(
+    accounts: Vec<&Account>,
+    mut output: AccountWriter<W>,
+    with_status: bool,
+) -> Result<(), AppError> {
+    for account in accounts {
+        if with_status {
+            output.write(&account.with_status())?;
+        } else {
+            output.write(account)?;
+        }
+    }
+
+    output.finish()?.commit()?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use clap::Parser;
+    use test_case::test_case;
+    use transactions::{AccountStore, CompactTransactionStore, Engine};
+
+    use super::{
+        cli::Args,
+        output::{AccountWriter, Commit},
+        read_input, worker,
+    };
+
+    impl Commit for &mut Vec<u8> {}
+
+    // Helper running worker on in-memory data of every input given in arguments
+    fn process(args: &[&str], data: &[&str]) -> String {
+        let args = Args::parse_from(args);
+        let inputs = args
+            .transactions_files
+            .iter()
+            .zip(data)
+            .map(|(path, data)| read_input(data.as_bytes(), path.display().to_string(), &args))
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+
+        let mut output = Vec::new();
+        worker(
+            Engine::<CompactTransactionStore, AccountStore>::default(),
+            None,
+            inputs,
+            AccountWriter::new(&mut output, args.output_format),
+            None,
+            HashMap::new(),
+            &args,
+        )
+        .unwrap();
+
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test_case(&["transactions", "-"], "type,client,tx,amount\ndeposit,1,1,3.0\nwithdrawal,1,2,1.0\n" ; "csv")]
+    #[test_case(&["transactions", "--input-format", "jsonl", "-"], concat!(
+        r#"{"type":"deposit","client":1,"tx":1,"amount":"3.0"}"#, "\n",
+        r#"{"type":"withdrawal","client":1,"tx":2,"amount":1.0}"#, "\n") ; "jsonl")]
+    fn stdin_should_be_processed_in_selected_format(args: &[&str], data: &str) {
+        assert_eq!(
+            process(args, &[data]),
+            "client,available,held,total,locked\n1,2.0,0.0000,2.0,false\n"
+        );
+    }
+
+    #[test]
+    fn dispute_in_second_input_should_refer_to_deposit_in_first() {
+        let output = process(
+            &["transactions", "first.csv", "-"],
+            &[
+                "type,client,tx,amount\ndeposit,1,1,3.0\ndeposit,2,2,1.0\n",
+                "type,client,tx,amount\ndispute,1,1,\ndeposit,2,3,1.0\n",
+            ],
+        );
 
-    engine.accounts().values().for_each(|acc| {
-        output
-            .serialize(acc)
-            .unwrap_or_else(|err| log::error!("{}", err))
-    });
+        assert_eq!(
+            output,
+            "client,available,held,total,locked\n1,0.0000,3.0,3.0,false\n2,2.0,0.0000,2.0,false\n"
+        );
+    }
 }
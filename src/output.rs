@@ -0,0 +1,192 @@
+use std::io;
+
+use serde::Serialize;
+use transactions::AtomicFile;
+
+use crate::{cli::OutputFormat, AppError};
+
+/// Destination of output which has to be completed once everything is written
+pub trait Commit: io::Write {
+    /// Makes written output visible, by default it is only flushed
+    fn commit(mut self) -> io::Result<()>
+    where
+        Self: Sized,
+    {
+        self.flush()
+    }
+}
+
+impl Commit for io::Stdout {}
+
+impl Commit for AtomicFile {
+    fn commit(self) -> io::Result<()> {
+        AtomicFile::commit(self)
+    }
+}
+
+/// Writes accounts in selected format, amounts are formatted the same way in every format
+pub enum AccountWriter<W: io::Write> {
+    Csv(Box<csv::Writer<W>>),
+    JsonLines(W),
+    Json { writer: W, empty: bool },
+}
+
+impl<W: io::Write> AccountWriter<W> {
+    pub fn new(writer: W, format: OutputFormat) -> Self {
+        match format {
+            OutputFormat::Csv => AccountWriter::Csv(Box::new(
+                csv::WriterBuilder::new().flexible(true).from_writer(writer),
+            )),
+            OutputFormat::JsonLines => AccountWriter::JsonLines(writer),
+            OutputFormat::Json => AccountWriter::Json {
+                writer,
+                empty: true,
+            },
+        }
+    }
+
+    /// Writes one account row, it is either [`transactions::Account`] or [`transactions::AccountWithStatus`]
+    pub fn write<T: Serialize>(&mut self, account: &T) -> Result<(), AppError> {
+        match self {
+            AccountWriter::Csv(writer) => writer.serialize(account)?,
+            AccountWriter::JsonLines(writer) => {
+                serde_json::to_writer(&mut *writer, account)?;
+                writer.write_all(b"\n")?;
+            }
+            AccountWriter::Json { writer, empty } => {
+                writer.write_all(if *empty { b"[" } else { b"," })?;
+                serde_json::to_writer(&mut *writer, account)?;
+                *empty = false;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Completes output and flushes it
+    pub fn finish(self) -> Result<W, AppError> {
+        let mut writer = match self {
+            AccountWriter::Csv(writer) => writer
+                .into_inner()
+                .map_err(|err| io::Error::new(err.error().kind(), err.error().to_string()))?,
+            AccountWriter::JsonLines(writer) => writer,
+            AccountWriter::Json { mut writer, empty } => {
+                writer.write_all(if empty { b"[]\n" } else { b"]\n" })?;
+                writer
+            }
+        };
+
+        writer.flush()?;
+
+        Ok(writer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use test_case::test_case;
+    use transactions::{Account, AccountDelta, Amount};
+
+    use super::{AccountWriter, AtomicFile};
+    use crate::cli::OutputFormat;
+
+    fn write(accounts: &[Account], format: OutputFormat) -> String {
+        let mut writer = AccountWriter::new(Vec::new(), format);
+
+        for account in accounts {
+            writer.write(account).unwrap();
+        }
+
+        String::from_utf8(writer.finish().unwrap()).unwrap()
+    }
+
+    fn account(id: u16, amount: Amount) -> Account {
+        let mut account = Account::new(id);
+        account.apply(AccountDelta::deposit(amount)).unwrap();
+        account
+    }
+
+    #[test_case(OutputFormat::Csv, "client,available,held,total,locked\n1,1.2346,0.0000,1.2346,false\n2,2,0.0000,2,false\n" ; "csv")]
+    #[test_case(OutputFormat::JsonLines, concat!(
+        r#"{"client":1,"available":"1.2346","held":"0.0000","total":"1.2346","locked":false}"#, "\n",
+        r#"{"client":2,"available":"2","held":"0.0000","total":"2","locked":false}"#, "\n") ; "jsonl")]
+    #[test_case(OutputFormat::Json, concat!(
+        r#"[{"client":1,"available":"1.2346","held":"0.0000","total":"1.2346","locked":false},"#,
+        r#"{"client":2,"available":"2","held":"0.0000","total":"2","locked":false}]"#, "\n") ; "json")]
+    fn accounts_should_be_written_in_selected_format(format: OutputFormat, expected: &str) {
+        let accounts = [account(1, Amount::new(123456, 5)), account(2, Amount::TWO)];
+
+        assert_eq!(write(&accounts, format), expected);
+    }
+
+    #[test_case(OutputFormat::Csv, "client,available,held,total,locked,status\n1,2,0.0000,2,false,active\n" ; "csv")]
+    #[test_case(OutputFormat::JsonLines, concat!(
+        r#"{"client":1,"available":"2","held":"0.0000","total":"2","locked":false,"status":"active"}"#, "\n") ; "jsonl")]
+    fn status_should_be_written_only_on_request(format: OutputFormat, expected: &str) {
+        let mut writer = AccountWriter::new(Vec::new(), format);
+        writer
+            .write(&account(1, Amount::TWO).with_status())
+            .unwrap();
+
+        assert_eq!(
+            String::from_utf8(writer.finish().unwrap()).unwrap(),
+            expected
+        );
+    }
+
+    #[test]
+    fn amounts_rounded_to_zero_should_be_written_with_fixed_scale() {
+        let accounts = [account(1, Amount::new(4, 5)), account(2, Amount::ZERO)];
+
+        assert_eq!(
+            write(&accounts, OutputFormat::Csv),
+            "client,available,held,total,locked\n1,0.0000,0.0000,0.0000,false\n2,0.0000,0.0000,0.0000,false\n"
+        );
+    }
+
+    #[test]
+    fn empty_json_output_should_be_valid_array() {
+        assert_eq!(write(&[], OutputFormat::Json), "[]\n");
+    }
+
+    fn directory(name: &str) -> std::path::PathBuf {
+        let directory =
+            std::env::temp_dir().join(format!("accounts-{}-{}", name, std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        directory
+    }
+
+    fn entries(directory: &std::path::Path) -> Vec<std::ffi::OsString> {
+        std::fs::read_dir(directory)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect()
+    }
+
+    #[test]
+    fn atomic_file_should_replace_target_only_on_commit() {
+        let directory = directory("commit");
+        let path = directory.join("accounts.csv");
+        std::fs::write(&path, "previous").unwrap();
+
+        let mut file = AtomicFile::create(&path).unwrap();
+        file.write_all(b"partial").unwrap();
+        drop(file);
+
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "previous");
+
+        let mut writer = AccountWriter::new(AtomicFile::create(&path).unwrap(), OutputFormat::Csv);
+        writer.write(&account(1, Amount::ONE)).unwrap();
+        writer.finish().unwrap().commit().unwrap();
+
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            "client,available,held,total,locked\n1,1,0.0000,1,false\n"
+        );
+        assert_eq!(entries(&directory), ["accounts.csv"]);
+
+        std::fs::remove_dir_all(&directory).ok();
+    }
+}